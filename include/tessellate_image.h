@@ -0,0 +1,34 @@
+/* C interface to tessellate_image, matching src/ffi.rs.  Regenerate with `cbindgen --config cbindgen.toml --output
+ * include/tessellate_image.h` after changing it.  Link against the cdylib or staticlib that `cargo build --release`
+ * leaves in target/release. */
+
+#ifndef TESSELLATE_IMAGE_H
+#define TESSELLATE_IMAGE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Draw a grayscale buffer of width * height bytes, row by row, 0 for black and 255 for white, as a Hilbert curve.
+ * A max_depth of 0 means no limit.  Returns 2 * count floats, x then y for each point, with count written to
+ * out_count, or NULL (and a count of 0) if the arguments were no good, such as a width or height of 0.  Free the
+ * result with tessellate_free_points. */
+float *tessellate_hilbert(const uint8_t *pixels, uint32_t width, uint32_t height, uint8_t gray_levels, uint32_t max_depth, size_t *out_count);
+
+/* Free points returned by tessellate_hilbert, passing the same count.  NULL is ignored. */
+void tessellate_free_points(float *points, size_t count);
+
+/* Order count points, given as x then y for each, into a short tour: greedy, then tidied by 2-opt and Or-opt.  With
+ * close it comes back round to the start; otherwise it's an open path.  Writes the order into out_order, which needs
+ * room for count indices.  Returns false for a NULL pointer or if something went wrong. */
+bool tessellate_tour(const float *points, size_t count, bool close, size_t *out_order);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TESSELLATE_IMAGE_H */
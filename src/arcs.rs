@@ -0,0 +1,134 @@
+use crate::point::Point;
+
+/// The longest run of points tried as one arc, which keeps fitting from going quadratic along a long smooth path.
+const MAX_RUN:usize = 256;
+/// Circles bigger than this are as good as straight, and left as lines.
+const MAX_RADIUS:f32 = 1e5f32;
+
+/// One move of a path after arc fitting, starting from wherever the last one finished.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Segment {
+	Line(Point),
+	/// A circular arc to `to` around `center`.  `sweep` is the angle it turns through in radians, positive when it turns
+	/// from the x axis towards the y axis, which is counterclockwise with y up and clockwise on an image with y down.
+	Arc { to: Point, center: Point, sweep: f32 },
+}
+
+/// Replace runs of points along a path that lie within `tolerance` of a circle with arcs, and keep the rest as lines.
+/// Each arc is as long as it can be, found by growing it a point at a time, and is only taken if the arc itself also
+/// stays within `tolerance` of the lines it stands in for.  The path's first point is where the moves start from.
+pub fn fit_arcs(points:&[Point], tolerance:f32) -> Vec<Segment> {
+	let mut segments = vec![];
+	let mut i = 0;
+	while i + 1 < points.len() {
+		let mut best = None;
+		let mut j = i + 2;
+		while j < points.len() && j - i <= MAX_RUN {
+			match arc_through(&points[i..=j], tolerance) {
+				Some(arc) => best = Some((j, arc)),
+				None => break,
+			}
+			j += 1;
+		}
+		match best {
+			Some((j, arc)) => {
+				segments.push(arc);
+				i = j;
+			},
+			None => {
+				segments.push(Segment::Line(points[i + 1]));
+				i += 1;
+			},
+		}
+	}
+	segments
+}
+
+/// The arc from the first of `run` to the last, if every point is close enough to one circle and the arc turns the
+/// same way all along.
+fn arc_through(run:&[Point], tolerance:f32) -> Option<Segment> {
+	let (start, end) = (run[0], run[run.len() - 1]);
+	let center = circumcenter(start, run[run.len() / 2], end)?;
+	let radius = (start - center).length();
+	if radius > MAX_RADIUS {
+		return None;
+	}
+	let mut sweep = 0f32;
+	for pair in run.windows(2) {
+		if ((pair[1] - center).length() - radius).abs() > tolerance {
+			return None;
+		}
+		let (a, b) = (pair[0] - center, pair[1] - center);
+		let turn = a.cross(b).atan2(a.dot(b));
+		if sweep != 0f32 && turn * sweep <= 0f32 {
+			return None;
+		}
+		// How far the arc bows out past the straight segment it replaces.
+		let half_chord = (pair[1] - pair[0]).length() / 2f32;
+		if radius - (radius * radius - half_chord * half_chord).max(0f32).sqrt() > tolerance {
+			return None;
+		}
+		sweep += turn;
+	}
+	if sweep.abs() >= std::f32::consts::TAU - 1e-3f32 {
+		return None;
+	}
+	Some(Segment::Arc { to: end, center, sweep })
+}
+
+fn circumcenter(a:Point, b:Point, c:Point) -> Option<Point> {
+	let (ab, ac) = (b - a, c - a);
+	let d = 2f32 * ab.cross(ac);
+	if d.abs() < 1e-9f32 {
+		return None;
+	}
+	let (ab2, ac2) = (ab.dot(ab), ac.dot(ac));
+	Some(a + Point::new(ac.y * ab2 - ab.y * ac2, ab.x * ac2 - ac.x * ab2) * (1f32 / d))
+}
+
+/// SVG path data for a path with arcs fitted, with `A` commands for the arcs and `L` for the rest.
+pub fn svg_path_data(points:&[Point], tolerance:f32, precision:usize) -> String {
+	let mut data = match points.first() {
+		Some(p) => format!("M{:.*} {:.*}", precision, p.x, precision, p.y),
+		None => return String::new(),
+	};
+	for segment in fit_arcs(points, tolerance) {
+		match segment {
+			Segment::Line(p) => data.push_str(&format!(" L{:.*} {:.*}", precision, p.x, precision, p.y)),
+			Segment::Arc { to, center, sweep } => {
+				let radius = (to - center).length();
+				let large = sweep.abs() > std::f32::consts::PI;
+				data.push_str(&format!(" A{:.*} {:.*} 0 {} {} {:.*} {:.*}", precision, radius, precision, radius, large as u8, (sweep > 0f32) as u8, precision, to.x, precision, to.y));
+			},
+		}
+	}
+	data
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_fit_arcs() {
+		// A quarter circle, then a straight run off the end of it.
+		let mut points:Vec<Point> = (0..=16).map(|i| {
+			let angle = i as f32 / 16f32 * std::f32::consts::FRAC_PI_2;
+			Point::new(10f32 * angle.cos(), 10f32 * angle.sin())
+		}).collect();
+		points.push(Point::new(-5f32, 10f32));
+		points.push(Point::new(-10f32, 10f32));
+		let segments = fit_arcs(&points, 0.05f32);
+		assert_eq!(segments.len(), 3);
+		match segments[0] {
+			Segment::Arc { to, center, sweep } => {
+				assert!((to - Point::new(0f32, 10f32)).length() < 1e-4f32);
+				assert!(center.length() < 1e-3f32);
+				assert!((sweep - std::f32::consts::FRAC_PI_2).abs() < 1e-3f32);
+			},
+			other => panic!("expected an arc, got {:?}", other),
+		}
+		assert_eq!(&segments[1..], &[Segment::Line(Point::new(-5f32, 10f32)), Segment::Line(Point::new(-10f32, 10f32))]);
+		assert!(svg_path_data(&points, 0.05f32, 2).starts_with("M10.00 0.00 A10.00 10.00 0 0 1 "));
+	}
+}
@@ -0,0 +1,68 @@
+use crate::point::Point;
+
+/// One cubic Bézier piece: it leaves `start` heading for `control1` and arrives at `end` from `control2`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CubicBezier {
+	pub start: Point,
+	pub control1: Point,
+	pub control2: Point,
+	pub end: Point,
+}
+
+impl CubicBezier {
+	pub fn point_at(&self, t:f32) -> Point {
+		let s = 1f32 - t;
+		self.start * (s*s*s) + self.control1 * (3f32*s*s*t) + self.control2 * (3f32*s*t*t) + self.end * (t*t*t)
+	}
+}
+
+/// A smooth curve through every point, one piece per segment.  The tangents are the Catmull-Rom ones, so each point's
+/// direction comes from its two neighbours and the curve never needs more than the points it was given.
+pub fn through(points:&[Point]) -> Vec<CubicBezier> {
+	let n = points.len();
+	(0..n.saturating_sub(1)).map(|i| {
+		let before = points[i.saturating_sub(1)];
+		let after = points[(i + 2).min(n - 1)];
+		let (start, end) = (points[i], points[i+1]);
+		CubicBezier {
+			start,
+			control1: start + (end - before) / 6f32,
+			control2: end - (after - start) / 6f32,
+			end,
+		}
+	}).collect()
+}
+
+/// SVG path data for a chain of pieces, `M` to the first start and a `C` for each piece.
+pub fn svg_path_data(curve:&[CubicBezier], precision:usize) -> String {
+	let mut data = String::new();
+	if let Some(first) = curve.first() {
+		data.push_str(&format!("M{:.*} {:.*}", precision, first.start.x, precision, first.start.y));
+	}
+	for piece in curve {
+		let coordinates = [piece.control1.x, piece.control1.y, piece.control2.x, piece.control2.y, piece.end.x, piece.end.y];
+		data.push_str(" C");
+		data.push_str(&coordinates.iter().map(|v| format!("{:.*}", precision, v)).collect::<Vec<String>>().join(" "));
+	}
+	data
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_curve_through_points() {
+		let points = vec![Point::new(0f32, 0f32), Point::new(1f32, 2f32), Point::new(3f32, 2f32), Point::new(4f32, 0f32)];
+		let curve = through(&points);
+		assert_eq!(curve.len(), 3);
+		for (i, piece) in curve.iter().enumerate() {
+			assert_eq!((piece.point_at(0f32), piece.point_at(1f32)), (points[i], points[i+1]));
+		}
+		// Evenly spaced points on a line stay on it.
+		let line = through(&[Point::new(0f32, 0f32), Point::new(1f32, 1f32), Point::new(2f32, 2f32)]);
+		let mid = line[1].point_at(0.3f32);
+		assert!((mid.x - mid.y).abs() < 1e-6f32);
+		assert_eq!(svg_path_data(&line[..1], 2), "M0.00 0.00 C0.17 0.17 0.67 0.67 1.00 1.00");
+	}
+}
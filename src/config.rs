@@ -0,0 +1,145 @@
+use std::io;
+
+/// Read settings from a small subset of TOML: `key = value` lines, with `#` comments and `[section]` headers.  The
+/// sections are only there to group things for the reader; every key is a command line flag without its dashes, so
+/// `smooth = "chaikin"` means `--smooth chaikin`.  Values can be strings, numbers, `true` (a switch that's on; `false`
+/// leaves it off) or arrays, which become comma separated lists like `--offset 10,20`.
+/// Comes back as command line arguments, flag and then value if it has one.
+pub fn parse(text:&str) -> io::Result<Vec<String>> {
+	let mut arguments = vec![];
+	for (number, line) in text.lines().enumerate() {
+		let invalid = |msg:&str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, msg));
+		let line = strip_comment(line).trim();
+		if line.is_empty() || (line.starts_with('[') && line.ends_with(']')) {
+			continue;
+		}
+		let (key, value) = match line.find('=') {
+			Some(idx) => (line[..idx].trim().trim_matches('"'), line[idx+1..].trim()),
+			None => return Err(invalid("expected key = value")),
+		};
+		if key.is_empty() || key.contains(char::is_whitespace) {
+			return Err(invalid("expected a key before ="));
+		}
+		let value = match value {
+			"true" => None,
+			"false" => continue,
+			_ if value.starts_with('[') => {
+				let items = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).ok_or_else(|| invalid("unclosed array"))?;
+				let items:Result<Vec<String>, io::Error> = items.split(',').map(str::trim).filter(|item| !item.is_empty()).map(|item| scalar(item).ok_or_else(|| invalid("bad array item"))).collect();
+				Some(items?.join(","))
+			},
+			_ => Some(scalar(value).ok_or_else(|| invalid("bad value"))?),
+		};
+		arguments.push(format!("--{}", key));
+		arguments.extend(value);
+	}
+	Ok(arguments)
+}
+
+/// Read settings from a flat JSON object, like `{"mode": "tsp", "points": 2000, "curves": true}`.  The keys and values
+/// mean the same as in `parse`, whose values look enough like JSON's to take them as they are.
+pub fn parse_json(text:&str) -> io::Result<Vec<String>> {
+	let invalid = |msg:&str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+	let inner = text.trim().strip_prefix('{').and_then(|t| t.strip_suffix('}')).ok_or_else(|| invalid("expected a JSON object"))?;
+	// One `key = value` line per entry, split at the commas that aren't inside a string or an array.
+	let mut lines = String::new();
+	let (mut quoted, mut depth) = (false, 0);
+	for c in inner.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			'[' if !quoted => depth += 1,
+			']' if !quoted => depth -= 1,
+			',' if !quoted && depth == 0 => {
+				lines.push('\n');
+				continue;
+			},
+			':' if !quoted && depth == 0 => {
+				lines.push('=');
+				continue;
+			},
+			'\n' | '#' if !quoted => {
+				lines.push(' ');
+				continue;
+			},
+			_ => {},
+		}
+		lines.push(c);
+	}
+	parse(&lines)
+}
+
+/// Everything before a `#` that isn't inside a string.
+fn strip_comment(line:&str) -> &str {
+	let mut quoted = false;
+	for (idx, c) in line.char_indices() {
+		match c {
+			'"' => quoted = !quoted,
+			'#' if !quoted => return &line[..idx],
+			_ => {},
+		}
+	}
+	line
+}
+
+/// A quoted string without its quotes, or a bare number or word as it is.
+fn scalar(value:&str) -> Option<String> {
+	if let Some(inner) = value.strip_prefix('"') {
+		let inner = inner.strip_suffix('"')?;
+		return Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"));
+	}
+	if value.is_empty() || value.contains(|c:char| c.is_whitespace() || c == '"') {
+		return None;
+	}
+	Some(value.to_string())
+}
+
+/// Named sets of settings for a few looks worth starting from.
+pub const PRESETS:[(&str, &str); 4] = [
+	("sketch", "smooth = \"chaikin\"\njitter = 0.6\njitter-double = true\n"),
+	("scribble", "pattern = \"coil\"\ncoil-loops = 2\ncoil-modulate = true\n"),
+	("stitch", "pattern = \"cross-stitch\"\nstitch-size = 3\ndedup = 0.01\n"),
+	("smooth", "smooth = \"catmull-rom\"\nsmooth-iterations = 4\nsimplify = 0.5\npipeline = [\"simplify\", \"smooth\"]\n"),
+];
+
+pub fn preset(name:&str) -> Option<Vec<String>> {
+	PRESETS.iter().find(|&&(preset, _)| preset == name).map(|&(_, text)| parse(text).expect("presets are valid"))
+}
+
+/// Add each flag in `settings` to `arguments` unless it's already there, so what was typed wins over what was read.
+pub fn merge(arguments:&mut Vec<String>, settings:&[String]) {
+	let mut idx = 0;
+	while idx < settings.len() {
+		let flag = &settings[idx];
+		let has_value = settings.get(idx + 1).is_some_and(|next| !next.starts_with("--"));
+		let end = if has_value { idx + 2 } else { idx + 1 };
+		if !arguments.contains(flag) {
+			arguments.extend_from_slice(&settings[idx..end]);
+		}
+		idx = end;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse_and_merge() {
+		let text = "# a portrait\n[decorate]\nsmooth = \"chaikin\" # rounder\npoints = 2000\nclosed = true\nstats = false\noffset = [10, -5]\n";
+		let settings = parse(text).unwrap();
+		assert_eq!(settings, vec!["--smooth", "chaikin", "--points", "2000", "--closed", "--offset", "10,-5"]);
+		let mut arguments:Vec<String> = vec!["prog".into(), "--points".into(), "50".into()];
+		merge(&mut arguments, &settings);
+		assert_eq!(arguments, vec!["prog", "--points", "50", "--smooth", "chaikin", "--closed", "--offset", "10,-5"]);
+		assert!(parse("smooth chaikin").is_err());
+		assert!(PRESETS.iter().all(|&(name, _)| preset(name).is_some()));
+	}
+
+	#[test]
+	fn test_parse_json() {
+		let settings = parse_json(r#"{"smooth": "chaikin", "points": 2000, "closed": true, "stats": false, "offset": [10, -5]}"#).unwrap();
+		assert_eq!(settings, vec!["--smooth", "chaikin", "--points", "2000", "--closed", "--offset", "10,-5"]);
+		assert_eq!(parse_json("{}").unwrap(), Vec::<String>::new());
+		assert!(parse_json("[1, 2]").is_err());
+	}
+}
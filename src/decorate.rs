@@ -0,0 +1,163 @@
+use crate::point::Point;
+use crate::polyline::Polyline;
+use crate::postprocess;
+use crate::tessellation::{self, SegmentTessellator, Shade};
+use crate::travel;
+use log::info;
+
+/// The stages between tracing the image and laying out the output, in the order they run unless the pipeline says
+/// otherwise.
+pub const STAGES:[&str; 7] = ["smooth", "simplify", "tessellate", "jitter", "dash", "merge", "order"];
+
+/// The settings for every decorating stage.  A stage only does something if its own settings are given, and only if
+/// it's in `pipeline`.
+pub struct DecorateOptions {
+	/// Which of `STAGES` to run, in order.
+	pub pipeline: Vec<String>,
+	/// chaikin or catmull-rom.
+	pub smoothing: Option<String>,
+	pub smooth_iterations: u32,
+	pub simplify_tolerance: Option<f32>,
+	pub tessellator: Option<tessellation::Recursive>,
+	pub jitter_amplitude: Option<f32>,
+	pub jitter_wavelength: f32,
+	pub jitter_seed: u64,
+	/// Wobble each path over twice, differently each time.
+	pub jitter_double: bool,
+	pub dash_period: Option<f32>,
+	/// The share of each dash period drawn.
+	pub dash_duty: f32,
+	/// Scales the duty where the dash falls, so dark parts get long dashes and light ones short dots.
+	pub dash_shade: Option<Shade>,
+	pub merge_distance: Option<f32>,
+	/// Order the paths to cut pen-up travel, with 2-opt if `Some(true)`.
+	pub order: Option<bool>,
+}
+
+impl Default for DecorateOptions {
+	fn default() -> Self {
+		DecorateOptions {
+			pipeline: STAGES.iter().map(|stage| stage.to_string()).collect(), smoothing: None, smooth_iterations: 2, simplify_tolerance: None,
+			tessellator: None, jitter_amplitude: None, jitter_wavelength: 20f32, jitter_seed: 0, jitter_double: false,
+			dash_period: None, dash_duty: 0.5f32, dash_shade: None, merge_distance: None, order: None,
+		}
+	}
+}
+
+impl DecorateOptions {
+	/// The stages that were given settings but left out of the pipeline, so won't run.
+	pub fn skipped(&self) -> Vec<&'static str> {
+		let configured = [
+			("smooth", self.smoothing.is_some()),
+			("simplify", self.simplify_tolerance.is_some()),
+			("tessellate", self.tessellator.is_some()),
+			("jitter", self.jitter_amplitude.is_some()),
+			("dash", self.dash_period.is_some()),
+			("merge", self.merge_distance.is_some()),
+			("order", self.order.is_some()),
+		];
+		configured.iter().filter(|&&(stage, set)| set && !self.pipeline.iter().any(|s| s == stage)).map(|&(stage, _)| stage).collect()
+	}
+}
+
+/// Wrap a pattern in what the pattern options ask for: following `shade` with its amplitude, flipping every other
+/// segment, and going `depth` levels deep.
+pub fn tessellator(pattern:Box<dyn SegmentTessellator>, shade:Option<Shade>, alternate:bool, depth:u32, min_length:f32) -> tessellation::Recursive {
+	let pattern:Box<dyn SegmentTessellator> = match shade {
+		Some(shade) => Box::new(tessellation::Modulated { pattern, amplitude: Box::new(move |p| shade(p)) }),
+		None => pattern,
+	};
+	let pattern:Box<dyn SegmentTessellator> = if alternate {
+		Box::new(tessellation::Alternating::new(pattern))
+	} else {
+		pattern
+	};
+	tessellation::Recursive { pattern, depth, min_length }
+}
+
+/// Run every stage of the pipeline over the paths in turn.
+pub fn decorate(paths:Vec<Polyline>, options:&DecorateOptions) -> Vec<Polyline> {
+	options.pipeline.iter().fold(paths, |paths, stage| run_stage(stage, paths, options))
+}
+
+/// Run one of `STAGES` over the paths.  Panics on any other name.
+pub fn run_stage(stage:&str, paths:Vec<Polyline>, options:&DecorateOptions) -> Vec<Polyline> {
+	match stage {
+		"smooth" => match options.smoothing.as_deref() {
+			Some("chaikin") => map_paths(paths, |path| postprocess::chaikin(path, options.smooth_iterations)),
+			Some("catmull-rom") => map_paths(paths, |path| postprocess::catmull_rom(path, options.smooth_iterations)),
+			_ => paths,
+		},
+		"simplify" => match options.simplify_tolerance {
+			Some(tolerance) => map_paths(paths, |path| postprocess::simplify(path, tolerance)),
+			None => paths,
+		},
+		"tessellate" => match &options.tessellator {
+			Some(tessellator) => paths.iter().map(|path| tessellation::tessellate_path(path, tessellator)).collect(),
+			None => paths,
+		},
+		// A second, differently wobbled pass over each path looks like a sketch gone over twice.
+		"jitter" => match options.jitter_amplitude {
+			Some(amplitude) => {
+				let passes = if options.jitter_double { 2 } else { 1 };
+				paths.iter().flat_map(|path| (0..passes).map(move |pass| {
+					Polyline::new(postprocess::jitter(&path.points, amplitude, options.jitter_wavelength, options.jitter_seed + pass * 2))
+				})).collect()
+			},
+			None => paths,
+		},
+		"dash" => match options.dash_period {
+			Some(period) => {
+				let duty = |p:Point| match &options.dash_shade {
+					Some(shade) => options.dash_duty * shade(p),
+					None => options.dash_duty,
+				};
+				paths.iter().flat_map(|path| path.dashes(period, duty)).collect()
+			},
+			None => paths,
+		},
+		"merge" => match options.merge_distance {
+			Some(distance) => {
+				let before = paths.len();
+				let paths = travel::merge_paths(paths, distance);
+				info!("Joined {} paths into {}", before, paths.len());
+				paths
+			},
+			None => paths,
+		},
+		// Last by default, so it sees the pieces everything before it made.
+		"order" => match options.order {
+			Some(two_opt) => {
+				let before = travel::pen_up_travel(&paths);
+				let paths = travel::order_paths(paths, two_opt);
+				info!("Cut pen-up travel from {:.0} to {:.0}", before, travel::pen_up_travel(&paths));
+				paths
+			},
+			None => paths,
+		},
+		other => panic!("{} isn't a decorating stage", other),
+	}
+}
+
+/// Run a post-processing pass over every path.
+fn map_paths<F:Fn(&[Point]) -> Vec<Point>>(paths:Vec<Polyline>, pass:F) -> Vec<Polyline> {
+	paths.into_iter().map(|path| Polyline::new(pass(&path.points))).collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_stages_follow_the_pipeline() {
+		let square = || vec![Polyline::new(vec![Point::new(0f32, 0f32), Point::new(10f32, 0f32), Point::new(10f32, 10f32)])];
+		// Nothing set does nothing.
+		assert_eq!(decorate(square(), &DecorateOptions::default())[0].points, square()[0].points);
+		let dashed = DecorateOptions { dash_period: Some(4f32), ..Default::default() };
+		assert_eq!(decorate(square(), &dashed).len(), 5);
+		assert!(dashed.skipped().is_empty());
+		let left_out = DecorateOptions { pipeline: vec!["smooth".to_string()], ..dashed };
+		assert_eq!(decorate(square(), &left_out).len(), 1);
+		assert_eq!(left_out.skipped(), vec!["dash"]);
+	}
+}
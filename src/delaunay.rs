@@ -0,0 +1,117 @@
+use crate::point::Point;
+use std::collections::{HashMap, HashSet};
+
+struct Triangle {
+	vertices: [usize; 3],
+	center: Point,
+	radius_squared: f32,
+}
+
+impl Triangle {
+	fn new(vertices:[usize; 3], points:&[Point]) -> Self {
+		let a = points[vertices[0]];
+		let b = points[vertices[1]];
+		let c = points[vertices[2]];
+		let d = 2f32 * (a.x*(b.y - c.y) + b.x*(c.y - a.y) + c.x*(a.y - b.y));
+		if d.abs() < 1e-12f32 {
+			// Degenerate (collinear) triangle.  Make it contain everything so the next insertion removes it.
+			return Triangle { vertices, center: a, radius_squared: f32::INFINITY };
+		}
+		let a2 = a.x*a.x + a.y*a.y;
+		let b2 = b.x*b.x + b.y*b.y;
+		let c2 = c.x*c.x + c.y*c.y;
+		let center = Point::new(
+			(a2*(b.y - c.y) + b2*(c.y - a.y) + c2*(a.y - b.y)) / d,
+			(a2*(c.x - b.x) + b2*(a.x - c.x) + c2*(b.x - a.x)) / d,
+		);
+		let dx = a.x - center.x;
+		let dy = a.y - center.y;
+		Triangle { vertices, center, radius_squared: dx*dx + dy*dy }
+	}
+
+	fn circumcircle_contains(&self, p:Point) -> bool {
+		let dx = p.x - self.center.x;
+		let dy = p.y - self.center.y;
+		dx*dx + dy*dy < self.radius_squared
+	}
+}
+
+/// Delaunay triangulation of `points` by Bowyer-Watson.  Returns triangles as triples of point indices.
+/// This is the simple O(n^2) version, which is plenty for a few thousand stipple points.
+pub fn triangulate(points:&[Point]) -> Vec<[usize; 3]> {
+	if points.len() < 3 {
+		return vec![];
+	}
+
+	// Start with one huge triangle around everything.  Its vertices live at the end of the point list.
+	let mut min = points[0];
+	let mut max = points[0];
+	for p in points {
+		min.x = min.x.min(p.x);
+		min.y = min.y.min(p.y);
+		max.x = max.x.max(p.x);
+		max.y = max.y.max(p.y);
+	}
+	let size = (max.x - min.x).max(max.y - min.y).max(1f32) * 20f32;
+	let mid = Point::new((min.x + max.x) / 2f32, (min.y + max.y) / 2f32);
+	let mut all_points = points.to_vec();
+	all_points.push(Point::new(mid.x - size, mid.y - size));
+	all_points.push(Point::new(mid.x + size, mid.y - size));
+	all_points.push(Point::new(mid.x, mid.y + size));
+	let n = points.len();
+
+	let mut triangles = vec![Triangle::new([n, n+1, n+2], &all_points)];
+	for (idx, &p) in points.iter().enumerate() {
+		// Carve out every triangle whose circumcircle holds the new point, then fan the hole's boundary to it.
+		let mut edge_counts:HashMap<(usize, usize), u32> = HashMap::new();
+		triangles.retain(|t| {
+			if !t.circumcircle_contains(p) {
+				return true;
+			}
+			for i in 0..3 {
+				let a = t.vertices[i];
+				let b = t.vertices[(i+1)%3];
+				*edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+			}
+			false
+		});
+		for ((a, b), count) in edge_counts {
+			if count == 1 {
+				triangles.push(Triangle::new([a, b, idx], &all_points));
+			}
+		}
+	}
+
+	triangles.into_iter().map(|t| t.vertices).filter(|v| v.iter().all(|&i| i < n)).collect()
+}
+
+/// The unique edges of a set of triangles, each given once with the smaller index first.
+pub fn triangle_edges(triangles:&[[usize; 3]]) -> Vec<(usize, usize)> {
+	let mut seen = HashSet::new();
+	let mut edges = vec![];
+	for t in triangles {
+		for i in 0..3 {
+			let a = t[i];
+			let b = t[(i+1)%3];
+			let edge = (a.min(b), a.max(b));
+			if seen.insert(edge) {
+				edges.push(edge);
+			}
+		}
+	}
+	edges
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_square() {
+		let pts = vec![Point::new(0f32, 0f32), Point::new(1f32, 0f32), Point::new(1f32, 1.1f32), Point::new(0f32, 1f32)];
+		let triangles = triangulate(&pts);
+		assert_eq!(triangles.len(), 2);
+		// Four sides and one diagonal.
+		assert_eq!(triangle_edges(&triangles).len(), 5);
+	}
+}
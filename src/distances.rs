@@ -0,0 +1,122 @@
+use rayon::prelude::*;
+
+/// Anything that can say how far apart two points are, by index.  The local search passes take one of these, so they
+/// can run on plain coordinates or on a precomputed `DistanceMatrix`.
+pub trait Metric: Sync {
+	/// How many points there are.
+	fn point_count(&self) -> usize;
+	fn between(&self, a:usize, b:usize) -> f32;
+
+	/// Length of the path visiting the points in `tour` order, back to the start if `close` is set.
+	fn tour_length(&self, tour:&[usize], close:bool) -> f32 {
+		let legs:f32 = tour.windows(2).map(|w| self.between(w[0], w[1])).sum();
+		match (close, tour.first(), tour.last()) {
+			(true, Some(&first), Some(&last)) => legs + self.between(last, first),
+			_ => legs,
+		}
+	}
+}
+
+impl Metric for [(f32, f32)] {
+	fn point_count(&self) -> usize {
+		self.len()
+	}
+
+	fn between(&self, a:usize, b:usize) -> f32 {
+		let dx = self[b].0 - self[a].0;
+		let dy = self[b].1 - self[a].1;
+		((dx*dx) + (dy*dy)).sqrt()
+	}
+}
+
+impl Metric for Vec<(f32, f32)> {
+	fn point_count(&self) -> usize {
+		self.len()
+	}
+
+	fn between(&self, a:usize, b:usize) -> f32 {
+		self.as_slice().between(a, b)
+	}
+}
+
+/// Every pairwise distance, worked out once up front so the solvers don't take a square root per lookup.
+/// Only the lower triangle is stored.  If that won't fit in the memory cap, distances are worked out as they're asked
+/// for instead, the same as using the points directly.
+/// A square root is cheap next to a cache miss, so the table only pays off while it stays small enough to sit in
+/// cache, or for objectives that look the same pairs up over and over.
+pub struct DistanceMatrix {
+	points: Vec<(f32, f32)>,
+	table: Option<Vec<f32>>,
+}
+
+impl DistanceMatrix {
+	/// Build the table for `points` if it takes no more than `memory_cap` bytes.
+	pub fn new(points:&[(f32, f32)], memory_cap:usize) -> Self {
+		let table = if DistanceMatrix::bytes_needed(points.len()) <= memory_cap {
+			// One allocation the size of the table, cut into its rows so they can be filled in parallel.
+			let mut table = vec![0f32; DistanceMatrix::bytes_needed(points.len()) / std::mem::size_of::<f32>()];
+			let mut rows = Vec::with_capacity(points.len());
+			let mut rest = table.as_mut_slice();
+			for a in 0..points.len() {
+				let (row, tail) = rest.split_at_mut(a);
+				rows.push(row);
+				rest = tail;
+			}
+			rows.into_par_iter().enumerate().for_each(|(a, row)| {
+				for (b, distance) in row.iter_mut().enumerate() {
+					*distance = points.between(a, b);
+				}
+			});
+			Some(table)
+		} else {
+			None
+		};
+		DistanceMatrix { points: points.to_vec(), table }
+	}
+
+	/// How much memory the table for `points` points takes.
+	pub fn bytes_needed(points:usize) -> usize {
+		points * points.saturating_sub(1) / 2 * std::mem::size_of::<f32>()
+	}
+
+	/// Whether the table was built, or the cap was too small and distances are computed on demand.
+	pub fn is_cached(&self) -> bool {
+		self.table.is_some()
+	}
+}
+
+impl Metric for DistanceMatrix {
+	fn point_count(&self) -> usize {
+		self.points.len()
+	}
+
+	fn between(&self, a:usize, b:usize) -> f32 {
+		match &self.table {
+			Some(table) if a != b => {
+				let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+				table[hi * (hi - 1) / 2 + lo]
+			},
+			Some(_) => 0f32,
+			None => self.points.between(a, b),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_matrix_matches_points() {
+		let pts:Vec<(f32, f32)> = (0..30).map(|i| (((i * 17) % 23) as f32, ((i * 5) % 13) as f32)).collect();
+		let cached = DistanceMatrix::new(&pts, usize::MAX);
+		let uncached = DistanceMatrix::new(&pts, DistanceMatrix::bytes_needed(30) - 1);
+		assert!(cached.is_cached() && !uncached.is_cached());
+		for (a, b) in [(0, 0), (3, 29), (29, 3), (12, 7)] {
+			assert_eq!(cached.between(a, b), pts.between(a, b));
+			assert_eq!(uncached.between(a, b), pts.between(a, b));
+		}
+		let tour:Vec<usize> = (0..30).collect();
+		assert!((cached.tour_length(&tour, true) - pts.tour_length(&tour, true)).abs() < 1e-3f32);
+	}
+}
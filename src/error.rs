@@ -0,0 +1,79 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// Everything that can stop a drawing, worded for whoever typed the command.
+#[derive(Debug)]
+pub enum Error {
+	/// A flag or argument whose value couldn't be read, like `--points lots`.
+	BadValue { name: String, value: String },
+	/// Options that don't make sense, alone or together.
+	Usage(String),
+	/// A file that couldn't be read or written.
+	Io { path: String, source: io::Error },
+	/// An input image that couldn't be opened or decoded.
+	Image { path: String, source: image::ImageError },
+	/// Drawing the output failed for some reason other than the file itself.
+	Output { path: String, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+	pub fn io(path:impl Into<String>, source:io::Error) -> Self {
+		Error::Io { path: path.into(), source }
+	}
+
+	/// What the process should exit with: 2 for a command line that can't work, like most tools, and 1 for a run that
+	/// failed part way.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Error::BadValue { .. } | Error::Usage(_) => 2,
+			Error::Io { .. } | Error::Image { .. } | Error::Output { .. } => 1,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::BadValue { name, value } => write!(f, "Bad value '{}' for {}", value, name),
+			Error::Usage(msg) => write!(f, "{}", msg),
+			Error::Io { path, source } => write!(f, "Failed on {}: {}", path, source),
+			Error::Image { path, source } => write!(f, "Failed to open {}: {}", path, source),
+			Error::Output { path, message } => write!(f, "Failed to write {}: {}", path, message),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Io { source, .. } => Some(source),
+			Error::Image { source, .. } => Some(source),
+			_ => None,
+		}
+	}
+}
+
+/// Read `value` as a `T`, blaming `name` if it isn't one.
+pub fn parse_value<T:FromStr>(name:&str, value:&str) -> Result<T> {
+	value.parse::<T>().map_err(|_| Error::BadValue { name: name.to_string(), value: value.to_string() })
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse_value_and_exit_codes() {
+		assert_eq!(parse_value::<u8>("gray_levels", "12").unwrap(), 12);
+		let bad = parse_value::<u8>("gray_levels", "300").unwrap_err();
+		assert_eq!(bad.to_string(), "Bad value '300' for gray_levels");
+		assert_eq!(bad.exit_code(), 2);
+		let missing = Error::io("out.svg", io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+		assert_eq!(missing.to_string(), "Failed on out.svg: denied");
+		assert_eq!(missing.exit_code(), 1);
+		assert!(std::error::Error::source(&missing).is_some());
+	}
+}
@@ -0,0 +1,118 @@
+use crate::hamiltonian;
+use crate::render::{hilbert_points, RenderOptions};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Draw a grayscale buffer as a Hilbert curve for callers in C.  `pixels` holds `width * height` bytes row by row, 0
+/// for black and 255 for white, and a `max_depth` of 0 means no limit.  Gives back `2 * count` floats, x then y for each
+/// point, with `count` written to `out_count`.  Hand them back to `tessellate_free_points` once done.  Null means the
+/// arguments were no good (an empty image among them) or something went wrong, and `out_count` is set to 0.
+///
+/// # Safety
+/// `pixels` must point at `width * height` readable bytes and `out_count` at a writable `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn tessellate_hilbert(pixels:*const u8, width:u32, height:u32, gray_levels:u8, max_depth:u32, out_count:*mut usize) -> *mut f32 {
+	if out_count.is_null() {
+		return ptr::null_mut();
+	}
+	*out_count = 0;
+	if pixels.is_null() || width == 0 || height == 0 {
+		return ptr::null_mut();
+	}
+	let pixels = std::slice::from_raw_parts(pixels, width as usize * height as usize);
+	let options = RenderOptions { gray_levels, max_depth: if max_depth == 0 { None } else { Some(max_depth) }, ..RenderOptions::default() };
+	// Unwinding into C is undefined, so a panic becomes a null like any other failure.
+	let points = match catch_unwind(AssertUnwindSafe(|| hilbert_points(pixels, width, height, &options))) {
+		Ok(Ok(points)) => points,
+		_ => return ptr::null_mut(),
+	};
+	*out_count = points.len();
+	let flat:Box<[f32]> = points.into_iter().flat_map(|(x, y)| vec![x, y]).collect();
+	Box::into_raw(flat) as *mut f32
+}
+
+/// Free the points from `tessellate_hilbert`.  Null is ignored.
+///
+/// # Safety
+/// `points` and `count` must be exactly what `tessellate_hilbert` gave back, and each set can only be freed once.
+#[no_mangle]
+pub unsafe extern "C" fn tessellate_free_points(points:*mut f32, count:usize) {
+	if !points.is_null() {
+		drop(Box::from_raw(ptr::slice_from_raw_parts_mut(points, count * 2)));
+	}
+}
+
+/// Order `count` points, given as x then y for each, into a short tour for callers in C: a greedy tour tidied up by
+/// 2-opt and Or-opt.  With `close` it comes back round to the start; otherwise it's an open path.  Writes the order
+/// into `out_order`, which has room for `count` indices.  False means a null pointer or a panic.
+///
+/// # Safety
+/// `points` must point at `2 * count` readable floats and `out_order` at `count` writable `size_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn tessellate_tour(points:*const f32, count:usize, close:bool, out_order:*mut usize) -> bool {
+	if points.is_null() || out_order.is_null() {
+		return false;
+	}
+	let points:Vec<(f32, f32)> = std::slice::from_raw_parts(points, count * 2).chunks(2).map(|p| (p[0], p[1])).collect();
+	let order = catch_unwind(AssertUnwindSafe(|| {
+		let mut tour = hamiltonian::greedy_edge_tour(&points, 8);
+		if !close {
+			tour = hamiltonian::cut_longest_edge(&points, &tour);
+		}
+		let neighbors = hamiltonian::neighbor_lists(&points, 8);
+		hamiltonian::two_opt(&points, &mut tour, close, Some(&neighbors));
+		hamiltonian::or_opt(&points, &mut tour, close, Some(&neighbors));
+		tour
+	}));
+	match order {
+		Ok(order) => {
+			std::slice::from_raw_parts_mut(out_order, count).copy_from_slice(&order);
+			true
+		},
+		Err(_) => false,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_hilbert_through_c_api() {
+		let pixels:Vec<u8> = (0..16*16).map(|i| ((i % 16) * 16) as u8).collect();
+		let mut count = 0usize;
+		unsafe {
+			let points = tessellate_hilbert(pixels.as_ptr(), 16, 16, 10, 0, &mut count);
+			assert!(!points.is_null());
+			let flat = std::slice::from_raw_parts(points, count * 2);
+			let expected = hilbert_points(&pixels, 16, 16, &RenderOptions::default()).unwrap();
+			assert_eq!(flat.chunks(2).map(|p| (p[0], p[1])).collect::<Vec<_>>(), expected);
+			tessellate_free_points(points, count);
+			assert!(tessellate_hilbert(pixels.as_ptr(), 16, 16, 0, 0, &mut count).is_null());
+			assert_eq!(count, 0);
+			for &(width, height) in &[(0, 16), (16, 0), (0, 0)] {
+				count = 1;
+				assert!(tessellate_hilbert(pixels.as_ptr(), width, height, 10, 0, &mut count).is_null());
+				assert_eq!(count, 0);
+			}
+		}
+	}
+
+	#[test]
+	fn test_tour_through_c_api() {
+		// A ring of points, handed over out of order.
+		let ring:Vec<(f32, f32)> = (0..12).map(|i| ((i * 5 % 12) as f32 * std::f32::consts::TAU / 12f32).sin_cos()).collect();
+		let flat:Vec<f32> = ring.iter().flat_map(|&(x, y)| vec![x, y]).collect();
+		let mut order = vec![0usize; ring.len()];
+		unsafe {
+			assert!(tessellate_tour(flat.as_ptr(), ring.len(), true, order.as_mut_ptr()));
+			assert!(!tessellate_tour(ptr::null(), ring.len(), true, order.as_mut_ptr()));
+		}
+		let mut sorted = order.clone();
+		sorted.sort_unstable();
+		assert_eq!(sorted, (0..ring.len()).collect::<Vec<usize>>());
+		// Going round the ring is twelve chords of 2 sin(pi / 12).
+		let around = 24f32 * (std::f32::consts::PI / 12f32).sin();
+		assert!((hamiltonian::tour_length(&ring, Some(&order), true) - around).abs() < 1e-3f32);
+	}
+}
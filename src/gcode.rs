@@ -0,0 +1,150 @@
+use crate::arcs::{self, Segment};
+use crate::polyline::Polyline;
+use std::fmt::Write;
+
+/// How to drive one kind of machine: what to send before and after the drawing, how to lift and lower the pen, and how
+/// drawing units map onto the bed.  The header, footer and pen commands are templates, where `{z_up}`, `{z_down}`,
+/// `{travel_feed}`, `{draw_feed}`, `{width}` and `{height}` stand for those settings (the last two in millimetres).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+	pub header: String,
+	pub footer: String,
+	pub pen_up: String,
+	pub pen_down: String,
+	pub z_up: f32,
+	pub z_down: f32,
+	/// Feed rates in millimetres a minute, with the pen up and down.
+	pub travel_feed: f32,
+	pub draw_feed: f32,
+	/// Millimetres per drawing unit.
+	pub scale: f32,
+	/// Where the drawing's corner goes on the bed, in millimetres.
+	pub offset: (f32, f32),
+	/// Images count down from the top and most machines count up from the bottom, so by default y is turned over.
+	pub flip_y: bool,
+	/// Fit arcs within this distance, in drawing units, and send them as `G2`/`G3` rather than many short lines.
+	pub arc_tolerance: Option<f32>,
+}
+
+impl Default for Profile {
+	fn default() -> Self {
+		Profile {
+			header: "G21 ; millimetres\nG90 ; absolute positions\n".to_string(),
+			footer: "G0 X0 Y0\n".to_string(),
+			pen_up: "G0 Z{z_up}".to_string(),
+			pen_down: "G1 Z{z_down} F{draw_feed}".to_string(),
+			z_up: 5f32,
+			z_down: 0f32,
+			travel_feed: 3000f32,
+			draw_feed: 1000f32,
+			scale: 1f32,
+			offset: (0f32, 0f32),
+			flip_y: true,
+			arc_tolerance: None,
+		}
+	}
+}
+
+/// Profiles for a few common kinds of machine, to start from.
+pub const MACHINES:[&str; 3] = ["z-axis", "servo", "laser"];
+
+/// A named profile: `z-axis` raises and lowers the pen on Z like a CNC router, `servo` swings it with a spindle
+/// command like most AxiDraw clones running GRBL, and `laser` turns the beam off and on instead.
+pub fn machine(name:&str) -> Option<Profile> {
+	let profile = Profile::default();
+	match name {
+		"z-axis" => Some(profile),
+		"servo" => Some(Profile { pen_up: "M3 S30\nG4 P0.2".to_string(), pen_down: "M3 S90\nG4 P0.2".to_string(), ..profile }),
+		"laser" => Some(Profile { pen_up: "M5".to_string(), pen_down: "M3 S1000".to_string(), footer: "M5\nG0 X0 Y0\n".to_string(), ..profile }),
+		_ => None,
+	}
+}
+
+impl Profile {
+	fn fill(&self, template:&str, width:f32, height:f32) -> String {
+		template.replace("{z_up}", &number(self.z_up))
+			.replace("{z_down}", &number(self.z_down))
+			.replace("{travel_feed}", &number(self.travel_feed))
+			.replace("{draw_feed}", &number(self.draw_feed))
+			.replace("{width}", &number(width))
+			.replace("{height}", &number(height))
+	}
+}
+
+/// The program that draws `paths` on a canvas `canvas_height` units tall: a rapid move to the start of each path with
+/// the pen up, then feed moves along it with the pen down.
+pub fn gcode_text(paths:&[Polyline], profile:&Profile, canvas_width:u32, canvas_height:u32) -> String {
+	let (width, height) = (canvas_width as f32 * profile.scale, canvas_height as f32 * profile.scale);
+	let command = |template:&str| {
+		let mut text = profile.fill(template, width, height);
+		if !text.is_empty() && !text.ends_with('\n') {
+			text.push('\n');
+		}
+		text
+	};
+	let position = |x:f32, y:f32| {
+		let y = if profile.flip_y { canvas_height as f32 - y } else { y };
+		(x * profile.scale + profile.offset.0, y * profile.scale + profile.offset.1)
+	};
+	let mut text = command(&profile.header);
+	text.push_str(&command(&profile.pen_up));
+	for path in paths.iter().filter(|path| !path.is_empty()) {
+		let (x, y) = position(path.points[0].x, path.points[0].y);
+		let _ = writeln!(text, "G0 X{} Y{} F{}", number(x), number(y), number(profile.travel_feed));
+		text.push_str(&command(&profile.pen_down));
+		let segments = match profile.arc_tolerance {
+			Some(tolerance) => arcs::fit_arcs(&path.points, tolerance),
+			None => path.points[1..].iter().map(|&p| Segment::Line(p)).collect(),
+		};
+		let mut from = path.points[0];
+		for (i, segment) in segments.iter().enumerate() {
+			// The feed rate sticks once it's set, so it's only given with the first move of each stroke.
+			let feed = if i == 0 { format!(" F{}", number(profile.draw_feed)) } else { String::new() };
+			match *segment {
+				Segment::Line(p) => {
+					let (x, y) = position(p.x, p.y);
+					let _ = writeln!(text, "G1 X{} Y{}{}", number(x), number(y), feed);
+					from = p;
+				},
+				Segment::Arc { to, center, sweep } => {
+					let ((x, y), (cx, cy), (fx, fy)) = (position(to.x, to.y), position(center.x, center.y), position(from.x, from.y));
+					// Turning y over turns the arc round too.
+					let counterclockwise = (sweep > 0f32) != profile.flip_y;
+					let _ = writeln!(text, "{} X{} Y{} I{} J{}{}", if counterclockwise { "G3" } else { "G2" }, number(x), number(y), number(cx - fx), number(cy - fy), feed);
+					from = to;
+				},
+			}
+		}
+		text.push_str(&command(&profile.pen_up));
+	}
+	text.push_str(&command(&profile.footer));
+	text
+}
+
+/// Three decimal places, less the zeros on the end, which is plenty for a plotter and keeps the file small.
+fn number(value:f32) -> String {
+	let text = format!("{:.3}", value);
+	let text = text.trim_end_matches('0').trim_end_matches('.');
+	if text == "-0" { "0".to_string() } else { text.to_string() }
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::point::Point;
+
+	#[test]
+	fn test_gcode_text() {
+		let paths = vec![Polyline::new(vec![Point::new(1f32, 2f32), Point::new(3f32, 2f32), Point::new(3f32, 10f32)])];
+		let profile = Profile { header: "; {width} x {height}\n".to_string(), footer: String::new(), scale: 0.5f32, offset: (10f32, 0f32), ..Profile::default() };
+		let text = gcode_text(&paths, &profile, 20, 10);
+		let expected = "; 10 x 5\nG0 Z5\nG0 X10.5 Y4 F3000\nG1 Z0 F1000\nG1 X11.5 Y4 F1000\nG1 X11.5 Y0\nG0 Z5\n";
+		assert_eq!(text, expected);
+		assert!(MACHINES.iter().all(|name| machine(name).is_some()));
+		assert!(gcode_text(&paths, &machine("servo").unwrap(), 20, 10).contains("M3 S90\nG4 P0.2\nG1 X"));
+		// A half circle, counterclockwise on the image, comes out clockwise on a machine with y up.
+		let half = Polyline::new((0..=8).map(|i| Point::new((i as f32 * 0.125f32 * std::f32::consts::PI).cos(), (i as f32 * 0.125f32 * std::f32::consts::PI).sin())).collect());
+		let text = gcode_text(&[half], &Profile { arc_tolerance: Some(0.05f32), ..Profile::default() }, 0, 0);
+		assert!(text.contains("\nG2 X-1 Y0 I-1 J0 F1000\n"), "{}", text);
+	}
+}
@@ -0,0 +1,78 @@
+use crate::kdtree::KdTree;
+use crate::point::Point;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A candidate edge from a vertex in the tree to one outside of it.  Ordered so that BinaryHeap pops the shortest first.
+struct Candidate {
+	length: f32,
+	from: usize,
+	to: usize,
+}
+
+impl PartialEq for Candidate {
+	fn eq(&self, other:&Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+	fn partial_cmp(&self, other:&Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Candidate {
+	fn cmp(&self, other:&Self) -> Ordering {
+		other.length.total_cmp(&self.length)
+	}
+}
+
+/// Compute the Euclidean minimum spanning tree over `points`, returned as pairs of point indices.
+/// This is Prim's algorithm where each tree vertex only remembers its nearest non-tree vertex (found with a k-d tree).
+/// When that candidate gets absorbed by someone else we ask the tree again, so we never have to consider all n^2 edges.
+pub fn minimum_spanning_tree(points:&[Point]) -> Vec<(usize, usize)> {
+	let mut edges = vec![];
+	if points.len() < 2 {
+		return edges;
+	}
+
+	let mut outside = KdTree::new(points);
+	let mut heap = BinaryHeap::new();
+	let push_nearest = |heap:&mut BinaryHeap<Candidate>, outside:&KdTree, from:usize| {
+		if let Some((to, length)) = outside.nearest(points[from]) {
+			heap.push(Candidate { length, from, to });
+		}
+	};
+
+	outside.remove(0);
+	push_nearest(&mut heap, &outside, 0);
+	while let Some(Candidate { from, to, .. }) = heap.pop() {
+		if outside.is_removed(to) {
+			// Stale.  Someone else got there first.
+			push_nearest(&mut heap, &outside, from);
+			continue;
+		}
+		outside.remove(to);
+		edges.push((from, to));
+		push_nearest(&mut heap, &outside, from);
+		push_nearest(&mut heap, &outside, to);
+	}
+
+	edges
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_mst_of_line() {
+		let pts = vec![Point::new(0f32, 0f32), Point::new(3f32, 0f32), Point::new(1f32, 0f32), Point::new(2f32, 0f32)];
+		let mut edges:Vec<(usize, usize)> = minimum_spanning_tree(&pts).into_iter().map(|(a, b)| (a.min(b), a.max(b))).collect();
+		edges.sort();
+		assert_eq!(edges, vec![(0, 2), (1, 3), (2, 3)]);
+	}
+}
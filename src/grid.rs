@@ -0,0 +1,151 @@
+use crate::kdtree::distance_squared;
+use crate::point::{Point, PointKey};
+use std::collections::HashMap;
+
+/// Points bucketed into square cells `cell_size` on a side.  Unlike `KdTree` it can grow a point at a time, and it's
+/// quickest when the points are spread fairly evenly and queries only look a cell or two away.
+pub struct SpatialGrid {
+	cell_size: f32,
+	points: Vec<Point>,
+	cells: HashMap<PointKey, Vec<usize>>,
+	// The corners of the range of cells that hold anything, so searches know when to give up.
+	lo: PointKey,
+	hi: PointKey,
+}
+
+impl SpatialGrid {
+	pub fn new(cell_size:f32) -> Self {
+		assert!(cell_size > 0f32, "grid cells need a positive size");
+		SpatialGrid {
+			cell_size,
+			points: vec![],
+			cells: HashMap::new(),
+			lo: PointKey { x: i64::MAX, y: i64::MAX },
+			hi: PointKey { x: i64::MIN, y: i64::MIN },
+		}
+	}
+
+	pub fn from_points(points:&[Point], cell_size:f32) -> Self {
+		let mut grid = SpatialGrid::new(cell_size);
+		for &p in points {
+			grid.insert(p);
+		}
+		grid
+	}
+
+	/// A cell size that puts about `per_cell` of `points` in each cell if they're spread evenly over their bounding box.
+	pub fn cell_size_for(points:&[Point], per_cell:usize) -> f32 {
+		let (lo, hi) = points.iter().fold((Point::new(f32::MAX, f32::MAX), Point::new(f32::MIN, f32::MIN)), |(lo, hi), p| {
+			(Point::new(lo.x.min(p.x), lo.y.min(p.y)), Point::new(hi.x.max(p.x), hi.y.max(p.y)))
+		});
+		let area = ((hi.x - lo.x) * (hi.y - lo.y)).max(1f32);
+		(area * per_cell as f32 / points.len().max(1) as f32).sqrt().max(f32::EPSILON)
+	}
+
+	/// Add a point, giving back its index.
+	pub fn insert(&mut self, p:Point) -> usize {
+		let key = self.cell(p);
+		let idx = self.points.len();
+		self.points.push(p);
+		self.cells.entry(key).or_default().push(idx);
+		self.lo = PointKey { x: self.lo.x.min(key.x), y: self.lo.y.min(key.y) };
+		self.hi = PointKey { x: self.hi.x.max(key.x), y: self.hi.y.max(key.y) };
+		idx
+	}
+
+	pub fn len(&self) -> usize {
+		self.points.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.points.is_empty()
+	}
+
+	pub fn point(&self, idx:usize) -> Point {
+		self.points[idx]
+	}
+
+	fn cell(&self, p:Point) -> PointKey {
+		PointKey { x: (p.x / self.cell_size).floor() as i64, y: (p.y / self.cell_size).floor() as i64 }
+	}
+
+	/// Indices of the points in the cells overlapping the box from `lo` to `hi`.
+	fn in_box(&self, lo:Point, hi:Point) -> impl Iterator<Item=usize> + '_ {
+		let (lo, hi) = (self.cell(lo), self.cell(hi));
+		(lo.x.max(self.lo.x)..=hi.x.min(self.hi.x))
+			.flat_map(move |x| (lo.y.max(self.lo.y)..=hi.y.min(self.hi.y)).map(move |y| PointKey { x, y }))
+			.filter_map(move |key| self.cells.get(&key))
+			.flatten()
+			.copied()
+	}
+
+	/// Every point no further than `radius` from `query`.
+	pub fn within(&self, query:Point, radius:f32) -> Vec<usize> {
+		let corner = Point::new(radius, radius);
+		self.in_box(query - corner, query + corner).filter(|&idx| distance_squared(self.points[idx], query) <= radius*radius).collect()
+	}
+
+	/// Whether any point is closer than `radius` to `query`.
+	pub fn any_within(&self, query:Point, radius:f32) -> bool {
+		let corner = Point::new(radius, radius);
+		self.in_box(query - corner, query + corner).any(|idx| distance_squared(self.points[idx], query) < radius*radius)
+	}
+
+	/// The `k` points closest to `query`, nearest first, with their squared distances.  Searches outward a ring of
+	/// cells at a time until nothing further out could be closer.
+	pub fn k_nearest(&self, query:Point, k:usize) -> Vec<(usize, f32)> {
+		let mut found:Vec<(usize, f32)> = vec![];
+		if k == 0 || self.is_empty() {
+			return found;
+		}
+		let center = self.cell(query);
+		let reach = [center.x - self.lo.x, self.hi.x - center.x, center.y - self.lo.y, self.hi.y - center.y].iter().copied().max().unwrap().max(0);
+		for ring in 0..=reach {
+			for x in center.x - ring..=center.x + ring {
+				for y in center.y - ring..=center.y + ring {
+					if (x - center.x).abs() != ring && (y - center.y).abs() != ring {
+						continue;
+					}
+					for &idx in self.cells.get(&PointKey { x, y }).into_iter().flatten() {
+						found.push((idx, distance_squared(self.points[idx], query)));
+					}
+				}
+			}
+			// Anything in the next ring out is at least this far away.
+			let cleared = ring as f32 * self.cell_size;
+			if found.len() >= k {
+				found.sort_by(|a, b| a.1.total_cmp(&b.1));
+				found.truncate(k);
+				if found[k-1].1 <= cleared*cleared {
+					break;
+				}
+			}
+		}
+		found.sort_by(|a, b| a.1.total_cmp(&b.1));
+		found
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_queries_match_brute_force() {
+		let pts:Vec<Point> = (0..300).map(|i| Point::new(((i * 37) % 101) as f32 * 0.7f32, ((i * 53) % 89) as f32 * 1.3f32)).collect();
+		let grid = SpatialGrid::from_points(&pts, 4f32);
+		let query = Point::new(30.2f32, 51.7f32);
+
+		let mut expected:Vec<(usize, f32)> = pts.iter().enumerate().map(|(i, &p)| (i, distance_squared(p, query))).collect();
+		expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+		let nearest:Vec<f32> = grid.k_nearest(query, 7).iter().map(|&(_, d)| d).collect();
+		assert_eq!(nearest, expected[..7].iter().map(|&(_, d)| d).collect::<Vec<f32>>());
+
+		let mut close = grid.within(query, 9f32);
+		close.sort();
+		let mut brute:Vec<usize> = expected.iter().filter(|&&(_, d)| d <= 81f32).map(|&(i, _)| i).collect();
+		brute.sort();
+		assert_eq!(close, brute);
+		assert!(grid.any_within(query, 9f32) && !grid.any_within(Point::new(-100f32, -100f32), 9f32));
+	}
+}
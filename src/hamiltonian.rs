@@ -1,15 +1,27 @@
-use rand::{thread_rng, Rng, RngCore};
+use crate::distances::{DistanceMatrix, Metric};
+use crate::graph::minimum_spanning_tree;
+use crate::grid::SpatialGrid;
+use crate::intersect;
+use crate::kdtree::KdTree;
+use crate::point::Point;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 /// Measure the length of the path between the given points.
 /// If `order` is None, will measure the length of the points sequentially.
 /// If `close` is True, will add the distance between the last point and the start.
-pub fn tour_length(points:&Vec<(f32, f32)>, order:Option<&Vec<usize>>, close:bool) -> f32 {
+pub fn tour_length(points:&[(f32, f32)], order:Option<&[usize]>, close:bool) -> f32 {
 	assert!(points.len() > 1);
 	let mut length:f32 = 0f32;
 	let mut order = if let Some(ord) = order {
-		ord.clone()
+		ord.to_vec()
 	} else {
-		(0..points.len()).into_iter().collect()
+		(0..points.len()).collect()
 	};
 	if close {
 		order.push(*order.first().unwrap());
@@ -26,87 +38,1385 @@ pub fn tour_length(points:&Vec<(f32, f32)>, order:Option<&Vec<usize>>, close:boo
 	length
 }
 
-pub fn solve_tsp_approx(points:&Vec<(f32, f32)>, max_iterations:u64, verbose:bool) -> Vec<usize> {
-	let mutation_odds = 0.01f64;
-	let num_paths = 500;
-	let mut tours = vec![];
-	let mut rng = thread_rng();
+/// Called by the solvers as they go with (iteration, best length so far, best tour so far).
+pub type Progress<'a> = &'a mut dyn FnMut(u64, f32, &[usize]);
 
-	// Make a bunch of candidate tours.
-	for _ in 0..num_paths {
-		let tour:Vec<usize> = tour_from_unselected(
-			points.len(),
-			(0..points.len()).into_iter().map(|p|{ rng.next_u64() as usize }).collect()
-		);
-		tours.push(tour);
+/// When a solver should give up.  It stops as soon as any of the limits that are set is reached.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StopCriteria {
+	/// Stop after this many generations.
+	pub max_generations: Option<u64>,
+	/// Stop after this many generations in a row without the best tour getting shorter.
+	pub stall_generations: Option<u64>,
+	/// Stop once this much wall-clock time has passed.
+	pub time_budget: Option<Duration>,
+	/// Improvements to the best tour smaller than this don't count as progress for `stall_generations`.
+	pub min_improvement: f32,
+}
+
+impl StopCriteria {
+	/// Run for exactly `generations` generations.
+	pub fn generations(generations:u64) -> Self {
+		StopCriteria { max_generations: Some(generations), ..Default::default() }
+	}
+
+	fn reached(&self, generation:u64, stalled:u64, started:Instant) -> bool {
+		self.max_generations.is_some_and(|max| generation >= max)
+			|| self.stall_generations.is_some_and(|max| stalled >= max)
+			|| self.time_budget.is_some_and(|budget| started.elapsed() >= budget)
+	}
+}
+
+/// How parents are picked for each child.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Selection {
+	/// The best of this many tours drawn at random.
+	Tournament(usize),
+	/// Always the two best tours.  Converges fast, but the population loses its variety quickly.
+	TopTwo,
+}
+
+/// Tuning for the genetic solver.
+#[derive(Copy, Clone, Debug)]
+pub struct GaConfig {
+	/// How many tours are in each generation.
+	pub population: usize,
+	/// How many of the best tours survive into the next generation unchanged.
+	pub elites: usize,
+	pub selection: Selection,
+	/// Odds of each mutation (a swap and an inversion) happening to a child.
+	pub mutation_rate: f64,
+}
+
+impl Default for GaConfig {
+	fn default() -> Self {
+		GaConfig { population: 500, elites: 2, selection: Selection::Tournament(3), mutation_rate: 0.2f64 }
+	}
+}
+
+/// What the genetic solver minimizes.  Lower is better.
+pub trait Objective: Sync {
+	fn cost(&self, points:&[(f32, f32)], tour:&[usize]) -> f32;
+}
+
+/// Plain tour length.  If `close` is false, tours are scored as open paths.
+#[derive(Copy, Clone, Debug)]
+pub struct Length {
+	pub close: bool,
+}
+
+impl Objective for Length {
+	fn cost(&self, points:&[(f32, f32)], tour:&[usize]) -> f32 {
+		tour_length(points, Some(tour), self.close)
+	}
+}
+
+/// Tour length plus `weight` times the total turning, in radians, at every point.  A pen that doesn't have to swing
+/// around hairpins draws smoother lines.
+#[derive(Copy, Clone, Debug)]
+pub struct Curvature {
+	pub close: bool,
+	pub weight: f32,
+}
+
+impl Objective for Curvature {
+	fn cost(&self, points:&[(f32, f32)], tour:&[usize]) -> f32 {
+		tour_length(points, Some(tour), self.close) + self.weight * total_turning(points, tour, self.close)
+	}
+}
+
+/// Estimated time for a plotter to draw the tour, stopping at every point.  Each segment speeds up at
+/// `acceleration` to at most `max_speed` and slows down again, so lots of short segments cost more than a few long
+/// ones of the same total length.
+#[derive(Copy, Clone, Debug)]
+pub struct PlotTime {
+	pub close: bool,
+	pub max_speed: f32,
+	pub acceleration: f32,
+}
+
+impl PlotTime {
+	/// Time to cover `distance` from a standstill to a standstill.
+	pub fn segment_time(&self, distance:f32) -> f32 {
+		// Distance needed to reach full speed and stop again.
+		let ramps = self.max_speed * self.max_speed / self.acceleration;
+		if distance >= ramps {
+			distance / self.max_speed + self.max_speed / self.acceleration
+		} else {
+			2f32 * (distance / self.acceleration).sqrt()
+		}
+	}
+}
+
+impl Objective for PlotTime {
+	fn cost(&self, points:&[(f32, f32)], tour:&[usize]) -> f32 {
+		let n = tour.len();
+		let segments = if self.close { n } else { n.saturating_sub(1) };
+		(0..segments).map(|i| {
+			let (ax, ay) = points[tour[i]];
+			let (bx, by) = points[tour[(i + 1) % n]];
+			self.segment_time(((bx - ax)*(bx - ax) + (by - ay)*(by - ay)).sqrt())
+		}).sum()
+	}
+}
+
+/// Tour length looked up in a distance matrix built from the same points.
+pub struct MatrixLength<'a> {
+	pub distances: &'a DistanceMatrix,
+	pub close: bool,
+}
+
+impl Objective for MatrixLength<'_> {
+	fn cost(&self, _points:&[(f32, f32)], tour:&[usize]) -> f32 {
+		self.distances.tour_length(tour, self.close)
+	}
+}
+
+/// Sum of the absolute angles the path turns through at each point.  Open paths don't turn at their ends.
+pub fn total_turning(points:&[(f32, f32)], tour:&[usize], close:bool) -> f32 {
+	let n = tour.len();
+	if n < 3 {
+		return 0f32;
+	}
+	let corners = if close { 0..n } else { 1..n-1 };
+	corners.map(|i| {
+		let (ax, ay) = points[tour[(i + n - 1) % n]];
+		let (bx, by) = points[tour[i]];
+		let (cx, cy) = points[tour[(i + 1) % n]];
+		let (ux, uy) = (bx - ax, by - ay);
+		let (vx, vy) = (cx - bx, cy - by);
+		(ux*vy - uy*vx).atan2(ux*vx + uy*vy).abs()
+	}).sum()
+}
+
+/// Evolve a population of tours with order crossover and swap and inversion mutation, as tuned by `config`, keeping
+/// the ones with the lowest `objective` cost.
+/// All randomness comes from `rng`, so a seeded generator gives the same tour every run.
+/// `progress` is called every generation with the generation number and the best tour so far and its cost.
+pub fn solve_tsp_approx(points:&[(f32, f32)], config:GaConfig, stop:StopCriteria, objective:&dyn Objective, progress:Progress, rng:&mut impl Rng) -> Vec<usize> {
+	evolve(GaState::new(points, config.population, rng), config, stop, objective, progress, None)
+}
+
+/// Everything the genetic solver needs to pick up where it left off, including the points it's touring.
+pub struct GaState {
+	pub points: Vec<(f32, f32)>,
+	pub generation: u64,
+	/// Generations since the best tour last got shorter.
+	pub stalled: u64,
+	pub best_length: f32,
+	/// Seed for the generator the next generations draw from.  Reseeding at every checkpoint stands in for saving the
+	/// generator itself, and keeps a resumed run identical to one that was never interrupted.
+	pub seed: u64,
+	pub tours: Vec<Vec<usize>>,
+}
+
+/// Where and how often `evolve` saves its state.
+pub struct Checkpoint {
+	pub path: PathBuf,
+	pub every: u64,
+}
+
+const STATE_MAGIC:&[u8; 4] = b"TSPG";
+const STATE_VERSION:u8 = 1;
+
+impl GaState {
+	/// A fresh population.  Greedy tours from random starting points are a far better start than random ones.
+	pub fn new(points:&[(f32, f32)], population:usize, rng:&mut impl Rng) -> Self {
+		let tours = (0..population).map(|_| nearest_neighbor_tour(points, rng.gen_range(0, points.len()))).collect();
+		GaState { points: points.to_vec(), generation: 0, stalled: 0, best_length: f32::MAX, seed: rng.gen(), tours }
 	}
 
-	for _ in 0..max_iterations {
-		// Calculate the length of each tour and keep the two best.
-		let mut best_idx = 0;
-		let mut best_length:f32 = tour_length(points, Some(&tours[0]), true);
-		let mut second_idx = 1;
-		let mut second_length:f32 = tour_length(points, Some(&tours[1]), true);
+	pub fn save<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		out.write_all(STATE_MAGIC)?;
+		out.write_all(&[STATE_VERSION])?;
+		out.write_all(&self.generation.to_le_bytes())?;
+		out.write_all(&self.stalled.to_le_bytes())?;
+		out.write_all(&self.best_length.to_le_bytes())?;
+		out.write_all(&self.seed.to_le_bytes())?;
+		out.write_all(&(self.points.len() as u32).to_le_bytes())?;
+		for &(x, y) in &self.points {
+			out.write_all(&x.to_le_bytes())?;
+			out.write_all(&y.to_le_bytes())?;
+		}
+		out.write_all(&(self.tours.len() as u32).to_le_bytes())?;
+		for tour in &self.tours {
+			out.write_all(&(tour.len() as u32).to_le_bytes())?;
+			for &city in tour {
+				out.write_all(&(city as u32).to_le_bytes())?;
+			}
+		}
+		Ok(())
+	}
 
-		for idx in 2..tours.len() {
-			let tour_len = tour_length(points, Some(&tours[idx]), true);
-			if tour_len < best_length {
-				second_idx = best_idx;
-				second_length = best_length;
-				best_idx = idx;
-				best_length = tour_len;
-			} else if tour_len < second_length {
-				second_length = tour_len;
-				second_idx = idx;
+	/// Read back a state written by `save`.
+	pub fn load<R:Read>(input:&mut R) -> io::Result<Self> {
+		let invalid = |msg:&str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+		let mut read = |buf:&mut [u8]| input.read_exact(buf);
+
+		let mut magic = [0u8; 4];
+		read(&mut magic)?;
+		if &magic != STATE_MAGIC {
+			return Err(invalid("not a saved solver state"));
+		}
+		let mut version = [0u8; 1];
+		read(&mut version)?;
+		if version[0] != STATE_VERSION {
+			return Err(invalid("unsupported solver state version"));
+		}
+		let mut buf8 = [0u8; 8];
+		let mut buf4 = [0u8; 4];
+		read(&mut buf8)?;
+		let generation = u64::from_le_bytes(buf8);
+		read(&mut buf8)?;
+		let stalled = u64::from_le_bytes(buf8);
+		read(&mut buf4)?;
+		let best_length = f32::from_le_bytes(buf4);
+		read(&mut buf8)?;
+		let seed = u64::from_le_bytes(buf8);
+		// Counts come from the file, so nothing is reserved ahead of them: a bad one runs out of input, not memory.
+		read(&mut buf4)?;
+		let mut points = vec![];
+		for _ in 0..u32::from_le_bytes(buf4) {
+			read(&mut buf4)?;
+			let x = f32::from_le_bytes(buf4);
+			read(&mut buf4)?;
+			points.push((x, f32::from_le_bytes(buf4)));
+		}
+		read(&mut buf4)?;
+		let count = u32::from_le_bytes(buf4);
+		if count < 3 {
+			return Err(invalid("solver state needs at least three tours"));
+		}
+		let mut tours = vec![];
+		for _ in 0..count {
+			read(&mut buf4)?;
+			if u32::from_le_bytes(buf4) as usize != points.len() {
+				return Err(invalid("solver state has a tour that doesn't match its points"));
+			}
+			// Each point exactly once, or a tour that skips some would look like the shortest.
+			let mut visited = vec![false; points.len()];
+			let mut tour = vec![];
+			for _ in 0..points.len() {
+				read(&mut buf4)?;
+				let city = u32::from_le_bytes(buf4) as usize;
+				if city >= points.len() || visited[city] {
+					return Err(invalid("solver state has a tour that doesn't visit every point once"));
+				}
+				visited[city] = true;
+				tour.push(city);
 			}
+			tours.push(tour);
 		}
+		Ok(GaState { points, generation, stalled, best_length, seed, tours })
+	}
 
-		let mut next_tours = vec![];
-		next_tours.push(tours[best_idx].clone());
-		next_tours.push(tours[second_idx].clone());
-		for _ in 0..num_paths-2 {
-			next_tours.push(cross_vectors(&tours[best_idx], &tours[second_idx], mutation_odds, points.len()));
+	/// Save to a temporary file and move it into place, so a crash mid-write never clobbers the last good checkpoint.
+	fn save_to(&self, path:&Path) -> io::Result<()> {
+		let temporary = path.with_extension("tmp");
+		let mut writer = BufWriter::new(File::create(&temporary)?);
+		self.save(&mut writer)?;
+		writer.flush()?;
+		drop(writer);
+		fs::rename(temporary, path)
+	}
+}
+
+/// Run the genetic solver from `state`, saving to `checkpoint` as it goes.  Failing to save is reported but doesn't
+/// stop the run.  The population is resized to `config.population` from the next generation on.
+pub fn evolve(state:GaState, config:GaConfig, stop:StopCriteria, objective:&dyn Objective, progress:Progress, checkpoint:Option<&Checkpoint>) -> Vec<usize> {
+	evolve_population(state, config, stop, objective, progress, checkpoint).tours.swap_remove(0)
+}
+
+/// `evolve`, but giving back the whole final population, best tour first.
+fn evolve_population(state:GaState, config:GaConfig, stop:StopCriteria, objective:&dyn Objective, progress:Progress, checkpoint:Option<&Checkpoint>) -> GaState {
+	assert!(config.population >= 2 && config.elites < config.population, "need a population of two or more with room for children");
+	let started = Instant::now();
+	let GaState { points, mut generation, mut stalled, mut best_length, seed, mut tours } = state;
+	let points = points.as_slice();
+	let num_paths = config.population;
+	let elites = config.elites.min(tours.len());
+	let mut rng = StdRng::seed_from_u64(seed);
+	let mut costs:Vec<f32> = tours.par_iter().map(|tour| objective.cost(points, tour)).collect();
+
+	loop {
+		let mut ranked:Vec<usize> = (0..tours.len()).collect();
+		ranked.sort_by(|&a, &b| costs[a].total_cmp(&costs[b]));
+		progress(generation, costs[ranked[0]], &tours[ranked[0]]);
+		if costs[ranked[0]] < best_length - stop.min_improvement {
+			best_length = costs[ranked[0]];
+			stalled = 0;
+		} else {
+			stalled += 1;
+		}
+		if stop.reached(generation, stalled, started) {
+			let tours = ranked.iter().map(|&idx| std::mem::take(&mut tours[idx])).collect();
+			return GaState { points: points.to_vec(), generation, stalled, best_length, seed: rng.gen(), tours };
+		}
+		generation += 1;
+
+		// Elites keep their costs; only the children need scoring, and they're bred and measured in parallel.
+		// Each child gets its own generator seeded from `rng`, so the result doesn't depend on how rayon splits the work.
+		let seeds:Vec<u64> = (elites..num_paths).map(|_| rng.gen()).collect();
+		let children:Vec<(Vec<usize>, f32)> = seeds.into_par_iter().map(|seed| {
+			let mut rng = StdRng::seed_from_u64(seed);
+			let (p, q) = match config.selection {
+				Selection::Tournament(size) => (tournament(&costs, size, &mut rng), tournament(&costs, size, &mut rng)),
+				Selection::TopTwo => (ranked[0], ranked[1.min(ranked.len() - 1)]),
+			};
+			let mut child = order_crossover(&tours[p], &tours[q], &mut rng);
+			mutate(&mut child, config.mutation_rate, &mut rng);
+			let cost = objective.cost(points, &child);
+			(child, cost)
+		}).collect();
+
+		let mut next_tours = Vec::with_capacity(num_paths);
+		let mut next_costs = Vec::with_capacity(num_paths);
+		for &idx in &ranked[..elites] {
+			next_tours.push(std::mem::take(&mut tours[idx]));
+			next_costs.push(costs[idx]);
+		}
+		for (child, cost) in children {
+			next_tours.push(child);
+			next_costs.push(cost);
 		}
 		tours = next_tours;
+		costs = next_costs;
+
+		if let Some(checkpoint) = checkpoint.filter(|c| c.every > 0 && generation % c.every == 0) {
+			let seed = rng.gen();
+			rng = StdRng::seed_from_u64(seed);
+			let state = GaState { points: points.to_vec(), generation, stalled, best_length, seed, tours };
+			if let Err(e) = state.save_to(&checkpoint.path) {
+				log::warn!("Failed to save checkpoint {}: {}", checkpoint.path.display(), e);
+			}
+			tours = state.tours;
+		}
+	}
+}
+
+/// How the island model splits up the genetic solver.
+#[derive(Copy, Clone, Debug)]
+pub struct IslandConfig {
+	/// How many populations evolve side by side, each of `GaConfig::population` tours.
+	pub islands: usize,
+	/// Generations between migrations.
+	pub migration_interval: u64,
+	/// How many of each island's best tours move to the next island over, replacing its worst.
+	pub migrants: usize,
+}
+
+impl Default for IslandConfig {
+	fn default() -> Self {
+		IslandConfig { islands: 4, migration_interval: 20, migrants: 2 }
+	}
+}
+
+/// Run several genetic populations in parallel, each on its own thread, and every `migration_interval` generations
+/// pass the best tours of each island on to the next one around a ring.  The islands explore separately in between,
+/// which keeps more variety than one big population.
+/// `progress` is called after each migration with the generation number and the best tour so far and its cost.
+pub fn solve_tsp_islands(points:&[(f32, f32)], config:GaConfig, islands:IslandConfig, stop:StopCriteria, objective:&dyn Objective, progress:Progress, rng:&mut impl Rng) -> Vec<usize> {
+	assert!(islands.islands >= 1 && islands.migration_interval >= 1, "need at least one island and a migration interval");
+	let started = Instant::now();
+	let mut states:Vec<GaState> = (0..islands.islands).map(|_| GaState::new(points, config.population, rng)).collect();
+	let migrants = islands.migrants.min(config.population - 1);
+	let (mut generation, mut stalled) = (0, 0);
+	let mut best:Option<(f32, Vec<usize>)> = None;
+
+	loop {
+		let epoch = StopCriteria {
+			max_generations: Some(stop.max_generations.unwrap_or(u64::MAX).min(generation + islands.migration_interval)),
+			time_budget: stop.time_budget.map(|budget| budget.saturating_sub(started.elapsed())),
+			..Default::default()
+		};
+		states = states.into_par_iter().map(|state| evolve_population(state, config, epoch, objective, &mut |_, _, _| {}, None)).collect();
+		let previous = generation;
+		generation = states.iter().map(|state| state.generation).max().unwrap_or(generation);
+
+		let (cost, tour) = states.iter().map(|state| (objective.cost(points, &state.tours[0]), &state.tours[0])).min_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+		if best.as_ref().is_none_or(|(best_cost, _)| cost < best_cost - stop.min_improvement) {
+			stalled = 0;
+		} else {
+			stalled += generation - previous;
+		}
+		if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+			best = Some((cost, tour.clone()));
+		}
+		let (best_cost, best_tour) = best.as_ref().unwrap();
+		progress(generation, *best_cost, best_tour);
+		if stop.reached(generation, stalled, started) || generation == previous {
+			return best.unwrap().1;
+		}
+
+		// Each island's best replace the worst of the next island around.
+		let emigrants:Vec<Vec<Vec<usize>>> = states.iter().map(|state| state.tours[..migrants].to_vec()).collect();
+		for (i, group) in emigrants.into_iter().enumerate() {
+			let tours = &mut states[(i + 1) % islands.islands].tours;
+			let keep = tours.len() - group.len();
+			tours.truncate(keep);
+			tours.extend(group);
+		}
+	}
+}
+
+/// Index of the shortest of `size` tours picked at random.
+fn tournament(lengths:&[f32], size:usize, rng:&mut impl Rng) -> usize {
+	(0..size.max(1)).map(|_| rng.gen_range(0, lengths.len())).min_by(|&a, &b| lengths[a].total_cmp(&lengths[b])).unwrap()
+}
+
+/// How the temperature falls over a simulated annealing run.
+#[derive(Copy, Clone, Debug)]
+pub enum CoolingSchedule {
+	/// Fall from `start` to `end` by the same factor every step.
+	Exponential { start: f32, end: f32 },
+	/// Fall in a straight line from `start` to zero.
+	Linear { start: f32 },
+}
+
+impl CoolingSchedule {
+	/// An exponential schedule scaled to the tour: starting hot enough to accept moves about as long as an average edge.
+	pub fn for_tour(points:&[(f32, f32)], tour:&[usize]) -> Self {
+		let start = tour_length(points, Some(tour), true) / tour.len() as f32;
+		CoolingSchedule::Exponential { start, end: start * 1e-3f32 }
+	}
+
+	fn temperature(&self, step:u64, steps:u64) -> f32 {
+		let progress = step as f32 / steps.max(1) as f32;
+		match *self {
+			CoolingSchedule::Exponential { start, end } => start * (end / start).powf(progress),
+			CoolingSchedule::Linear { start } => start * (1f32 - progress),
+		}
+	}
+}
+
+/// Simulated annealing over 2-opt moves, starting from `tour`.
+/// Each step proposes reversing a random stretch of the tour, always taking it if it helps and otherwise taking it
+/// with a probability that falls with the temperature.  Scales to far more points than `solve_tsp_approx`.
+/// If `close` is false the tour is treated as an open path, so its ends can move too.
+/// `progress` is called after every sweep (one proposed move per point) with the sweep number and the current tour.
+pub fn solve_tsp_annealing(points:&[(f32, f32)], tour:Vec<usize>, steps:u64, schedule:CoolingSchedule, close:bool, progress:Progress, rng:&mut impl Rng) -> Vec<usize> {
+	let n = tour.len();
+	if n < 4 {
+		return tour;
+	}
+	let mut tour = Tour::new(points, tour, close);
+
+	for step in 0..steps {
+		let temperature = schedule.temperature(step, steps);
+		let mut from = rng.gen_range(0, n);
+		let mut to = rng.gen_range(0, n);
+		if from > to {
+			std::mem::swap(&mut from, &mut to);
+		}
+		let delta = tour.reverse_delta(points, from, to);
+		if delta < 0f32 || (temperature > 0f32 && rng.gen::<f32>() < (-delta / temperature).exp()) {
+			tour.reverse(points, from, to);
+		}
+
+		if (step + 1).is_multiple_of(n as u64) {
+			progress((step + 1) / n as u64, tour.length(), tour.order());
+		}
+	}
+
+	tour.into_order()
+}
+
+/// A tour that keeps track of its own length, for annealing.  Reversals are scored by the two edges they change, so
+/// trying one costs the same however many points there are.
+pub struct Tour {
+	order: Vec<usize>,
+	length: f32,
+	close: bool,
+}
+
+impl Tour {
+	pub fn new<M:Metric + ?Sized>(points:&M, order:Vec<usize>, close:bool) -> Self {
+		let length = points.tour_length(&order, close);
+		Tour { order, length, close }
+	}
+
+	pub fn length(&self) -> f32 {
+		self.length
+	}
+
+	pub fn order(&self) -> &[usize] {
+		&self.order
+	}
+
+	pub fn into_order(self) -> Vec<usize> {
+		self.order
+	}
+
+	/// How much reversing order[from..=to] would change the length.
+	pub fn reverse_delta<M:Metric + ?Sized>(&self, points:&M, from:usize, to:usize) -> f32 {
+		let n = self.order.len();
+		// Reversing all (or all but one) of a closed tour gives back the same cycle.
+		if to <= from || (self.close && to - from + 1 >= n - 1) {
+			return 0f32;
+		}
+		// Only the edges into and out of the stretch change.
+		self.delta(points, &[from.wrapping_sub(1), to], |k| if (from..=to).contains(&k) { self.order[from + to - k] } else { self.order[k] })
+	}
 
-		if verbose {
-			println!("Shortest tour: {}", best_length);
+	pub fn reverse<M:Metric + ?Sized>(&mut self, points:&M, from:usize, to:usize) {
+		self.length += self.reverse_delta(points, from, to);
+		if self.close {
+			reverse_closed(&mut self.order, from, to);
+		} else {
+			self.order[from..=to].reverse();
 		}
 	}
 
-	tours[0].clone()
+	/// The change in length of the edges starting at each of `starts` when the point at position k becomes
+	/// `moved(k)`.  Starts off the front (usize::MAX) wrap around on closed tours and drop out on open ones.
+	fn delta<M:Metric + ?Sized>(&self, points:&M, starts:&[usize], moved:impl Fn(usize) -> usize) -> f32 {
+		let n = self.order.len();
+		let mut edges:Vec<usize> = starts.iter().map(|&k| if k == usize::MAX { n - 1 } else { k }).filter(|&k| self.close || k + 1 < n).collect();
+		edges.sort_unstable();
+		edges.dedup();
+		edges.iter().map(|&k| points.between(moved(k), moved((k + 1) % n)) - points.between(self.order[k], self.order[(k + 1) % n])).sum()
+	}
+}
+
+/// Turn a closed tour into the open path you get by dropping its longest edge.
+/// Solvers that only work on closed tours can serve open paths this way.
+pub fn cut_longest_edge(points:&[(f32, f32)], tour:&[usize]) -> Vec<usize> {
+	let n = tour.len();
+	let longest = (0..n).max_by(|&a, &b| {
+		let da = distance(points[tour[a]], points[tour[(a+1) % n]]);
+		let db = distance(points[tour[b]], points[tour[(b+1) % n]]);
+		da.total_cmp(&db)
+	});
+	match longest {
+		Some(cut) => tour[cut+1..].iter().chain(tour[..=cut].iter()).copied().collect(),
+		None => vec![],
+	}
+}
+
+/// Tuning for the ant colony solver.
+#[derive(Copy, Clone, Debug)]
+pub struct AcoConfig {
+	/// Tours built per iteration.
+	pub ants: usize,
+	/// Fraction of the pheromone that fades every iteration.
+	pub evaporation: f32,
+	/// How strongly ants follow pheromone.
+	pub alpha: f32,
+	/// How strongly ants prefer short edges.
+	pub beta: f32,
+	/// Ants only lay and follow pheromone along edges to this many nearest neighbors.  Past those they walk to the
+	/// closest point left.
+	pub neighbors: usize,
 }
 
-/// Perform some random cross between two 'genes' with mutation.
-/// Given two vectors...
-/// [1, 2, 3, 4, 5]
-/// [a, b, c, d, e]
-/// Flip a coin to see which parent's base will be used.
-/// Possible outputs:
-/// [a, 2, 3, d, 5]
-/// [1, 2, 3, d, 5]
-/// [a, b, c, d, e]
-/// [a, b, 3, 4, 5]
-/// If mutation_odds is greater than zero, will, with that probability, select a random value to insert, rather than a value from either parent.
-fn cross_vectors(p:&Vec<usize>, q:&Vec<usize>, mutation_odds:f64, num_points:usize) -> Vec<usize> {
-	let mut res = vec![];
-	let mut rng = thread_rng();
+impl Default for AcoConfig {
+	fn default() -> Self {
+		AcoConfig { ants: 20, evaporation: 0.1f32, alpha: 1f32, beta: 3f32, neighbors: 10 }
+	}
+}
+
+/// Ant colony optimization.  Each iteration a batch of ants builds tours in parallel, picking each next point with
+/// odds weighted by the pheromone on the edge and how short it is.  Pheromone then evaporates everywhere and is laid
+/// along the best tour found so far, so later ants favor its edges.  If `close` is false, tours are scored as open
+/// paths.  `progress` is called every iteration with the best tour so far.
+pub fn solve_tsp_ant_colony(points:&[(f32, f32)], config:AcoConfig, stop:StopCriteria, close:bool, progress:Progress, rng:&mut impl Rng) -> Vec<usize> {
+	let started = Instant::now();
+	let n = points.len();
+	let candidates = neighbor_lists(points, config.neighbors);
+	let pts:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+
+	let mut best = nearest_neighbor_tour(points, 0);
+	let mut best_length = tour_length(points, Some(&best), close);
+	// One pheromone level per candidate edge, laid out like `candidates`.
+	let initial = 1f32 / (n as f32 * best_length.max(f32::EPSILON));
+	let mut pheromone:Vec<Vec<f32>> = candidates.iter().map(|list| vec![initial; list.len()]).collect();
+
+	let mut stalled = 0;
+	let mut generation = 0;
+	loop {
+		progress(generation, best_length, &best);
+		if stop.reached(generation, stalled, started) {
+			return best;
+		}
+		generation += 1;
+
+		let seeds:Vec<u64> = (0..config.ants.max(1)).map(|_| rng.gen()).collect();
+		let tours:Vec<(Vec<usize>, f32)> = seeds.into_par_iter().map(|seed| {
+			let mut rng = StdRng::seed_from_u64(seed);
+			let tour = ant_tour(&pts, &candidates, &pheromone, config, &mut rng);
+			let length = tour_length(points, Some(&tour), close);
+			(tour, length)
+		}).collect();
+		let (tour, length) = tours.into_iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+		if length < best_length - stop.min_improvement {
+			stalled = 0;
+		} else {
+			stalled += 1;
+		}
+		if length < best_length {
+			best = tour;
+			best_length = length;
+		}
+
+		for level in pheromone.iter_mut().flatten() {
+			*level *= 1f32 - config.evaporation;
+		}
+		let deposit = 1f32 / best_length.max(f32::EPSILON);
+		let edges = if close { n } else { n - 1 };
+		for i in 0..edges {
+			let (a, b) = (best[i], best[(i + 1) % n]);
+			for (from, to) in [(a, b), (b, a)] {
+				if let Some(slot) = candidates[from].iter().position(|&c| c == to) {
+					pheromone[from][slot] += deposit;
+				}
+			}
+		}
+	}
+}
+
+/// Walk one ant from a random start until it has visited everything.
+fn ant_tour(pts:&[Point], candidates:&[Vec<usize>], pheromone:&[Vec<f32>], config:AcoConfig, rng:&mut impl Rng) -> Vec<usize> {
+	let n = pts.len();
+	let mut unvisited = KdTree::new(pts);
+	let mut tour = Vec::with_capacity(n);
+	let mut current = rng.gen_range(0, n);
+	let mut weights = Vec::with_capacity(candidates.first().map_or(0, |list| list.len()));
+	loop {
+		unvisited.remove(current);
+		tour.push(current);
+		if unvisited.is_empty() {
+			return tour;
+		}
 
-	// p and q should be the same size in theory, but...
-	for i in 0..p.len().min(q.len()) {
-		res.push(
-			if rng.gen_bool(mutation_odds) {
-				rng.next_u64() as usize % num_points
+		weights.clear();
+		let mut total = 0f32;
+		for (slot, &c) in candidates[current].iter().enumerate() {
+			let weight = if unvisited.is_removed(c) {
+				0f32
 			} else {
-				if rng.gen_bool(0.5f64 - mutation_odds as f64/2f64) {
-					p[i]
-				} else {
-					q[i]
+				let closeness = 1f32 / distance(pts[current].into(), pts[c].into()).max(1e-6f32);
+				pheromone[current][slot].powf(config.alpha) * closeness.powf(config.beta)
+			};
+			total += weight;
+			weights.push(weight);
+		}
+
+		current = if total > 0f32 {
+			let mut target = rng.gen::<f32>() * total;
+			let mut pick = candidates[current].len() - 1;
+			for (slot, &weight) in weights.iter().enumerate() {
+				if weight > 0f32 && target <= weight {
+					pick = slot;
+					break;
+				}
+				target -= weight;
+			}
+			// Rounding can leave `target` just past the last weight; fall back to the last live candidate.
+			while weights[pick] == 0f32 {
+				pick -= 1;
+			}
+			candidates[current][pick]
+		} else {
+			unvisited.nearest(pts[current]).unwrap().0
+		};
+	}
+}
+
+/// Reverse tour[from..=to] of a closed tour.  If that's more than half the tour, reverse the rest instead, which gives
+/// the same cycle with half the work.
+fn reverse_closed(tour:&mut [usize], from:usize, to:usize) {
+	let n = tour.len();
+	let inside = to + 1 - from;
+	if inside <= n / 2 {
+		tour[from..=to].reverse();
+		return;
+	}
+	let mut right = from + n - 1;
+	for left in (to + 1)..(to + 1 + (n - inside) / 2) {
+		tour.swap(left % n, right % n);
+		right -= 1;
+	}
+}
+
+/// Build a tour greedily: start at `start` and always go to the closest point not yet visited.
+/// Uses a k-d tree for the lookups, so it's fast enough for tens of thousands of points.
+pub fn nearest_neighbor_tour(points:&[(f32, f32)], start:usize) -> Vec<usize> {
+	let pts:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+	let mut unvisited = KdTree::new(&pts);
+	let mut tour = Vec::with_capacity(points.len());
+	let mut current = start;
+	loop {
+		unvisited.remove(current);
+		tour.push(current);
+		match unvisited.nearest(pts[current]) {
+			Some((next, _)) => current = next,
+			None => return tour,
+		}
+	}
+}
+
+/// A Christofides-style tour: take the minimum spanning tree, pair up its odd-degree points greedily (closest pairs
+/// first), walk an Euler circuit of the result and skip points already visited.  Deterministic, and a much better
+/// start for the refinement passes than a greedy walk, which strands long jumps at the end.
+/// A true Christofides tour uses a minimum-weight perfect matching; the greedy one loses its 1.5x bound but is far
+/// cheaper.
+pub fn christofides_tour(points:&[(f32, f32)]) -> Vec<usize> {
+	let n = points.len();
+	if n < 3 {
+		return (0..n).collect();
+	}
+	let pts:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+	let mut edges = minimum_spanning_tree(&pts);
+
+	let mut degree = vec![0usize; n];
+	for &(a, b) in &edges {
+		degree[a] += 1;
+		degree[b] += 1;
+	}
+	let odd:Vec<usize> = (0..n).filter(|&i| degree[i] % 2 == 1).collect();
+	edges.extend(greedy_matching(&pts, &odd));
+
+	// Hierholzer's algorithm.  Every point now has even degree, so the circuit uses every edge.
+	let mut adjacent:Vec<Vec<(usize, usize)>> = vec![vec![]; n];
+	for (id, &(a, b)) in edges.iter().enumerate() {
+		adjacent[a].push((b, id));
+		adjacent[b].push((a, id));
+	}
+	let mut used = vec![false; edges.len()];
+	let mut stack = vec![0];
+	let mut circuit = Vec::with_capacity(edges.len() + 1);
+	while let Some(&current) = stack.last() {
+		match adjacent[current].pop() {
+			Some((_, id)) if used[id] => {},
+			Some((next, id)) => {
+				used[id] = true;
+				stack.push(next);
+			},
+			None => {
+				circuit.push(current);
+				stack.pop();
+			},
+		}
+	}
+
+	let mut visited = vec![false; n];
+	circuit.into_iter().filter(|&p| !std::mem::replace(&mut visited[p], true)).collect()
+}
+
+/// Pair up `vertices` (indices into `pts`, an even number of them), shortest pairs first.
+/// Pairs among each point's few nearest neighbors are tried in order of length; whatever is left is matched walking
+/// to the nearest unmatched point.
+fn greedy_matching(pts:&[Point], vertices:&[usize]) -> Vec<(usize, usize)> {
+	let subset:Vec<Point> = vertices.iter().map(|&v| pts[v]).collect();
+	let mut tree = KdTree::new(&subset);
+	let mut pairs:Vec<(f32, usize, usize)> = vec![];
+	for (i, &p) in subset.iter().enumerate() {
+		for (j, d) in tree.k_nearest(p, 6) {
+			if i < j {
+				pairs.push((d, i, j));
+			}
+		}
+	}
+	pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+	let mut matching = Vec::with_capacity(vertices.len() / 2);
+	for (_, i, j) in pairs {
+		if !tree.is_removed(i) && !tree.is_removed(j) {
+			tree.remove(i);
+			tree.remove(j);
+			matching.push((vertices[i], vertices[j]));
+		}
+	}
+	for i in 0..subset.len() {
+		if tree.is_removed(i) {
+			continue;
+		}
+		tree.remove(i);
+		if let Some((j, _)) = tree.nearest(subset[i]) {
+			tree.remove(j);
+			matching.push((vertices[i], vertices[j]));
+		}
+	}
+	matching
+}
+
+/// The greedy edge tour: go through the candidate edges shortest first, keeping each one unless it would give a point
+/// three edges or close a loop early.  The fragments left over are joined end to end, nearest first.
+/// Unlike `nearest_neighbor_tour` it never paints itself into a corner, so it doesn't leave long jumps back across
+/// ring-shaped stipplings.  Candidate edges are each point's `neighbors` nearest.
+pub fn greedy_edge_tour(points:&[(f32, f32)], neighbors:usize) -> Vec<usize> {
+	let n = points.len();
+	let candidates = neighbor_lists(points, neighbors);
+	let mut edges:Vec<(f32, usize, usize)> = candidates.iter().enumerate().flat_map(|(a, list)| {
+		list.iter().filter(move |&&b| a < b).map(move |&b| (distance(points[a], points[b]), a, b))
+	}).collect();
+	edges.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+	let mut links:Vec<Vec<usize>> = vec![vec![]; n];
+	let mut fragment:Vec<usize> = (0..n).collect();
+	for (_, a, b) in edges {
+		if links[a].len() < 2 && links[b].len() < 2 {
+			let (ra, rb) = (find_root(&mut fragment, a), find_root(&mut fragment, b));
+			if ra != rb {
+				fragment[ra] = rb;
+				links[a].push(b);
+				links[b].push(a);
+			}
+		}
+	}
+
+	// Chain the fragments: walk one to its far end, then jump to the nearest end of a fragment not yet used.
+	let pts:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+	let mut ends = KdTree::new(&pts);
+	for (v, linked) in links.iter().enumerate() {
+		if linked.len() == 2 {
+			ends.remove(v);
+		}
+	}
+	let mut tour = Vec::with_capacity(n);
+	let mut next = (0..n).find(|&v| links[v].len() < 2);
+	while let Some(start) = next {
+		let mut previous = usize::MAX;
+		let mut current = start;
+		ends.remove(start);
+		loop {
+			tour.push(current);
+			match links[current].iter().find(|&&v| v != previous) {
+				Some(&v) => {
+					previous = current;
+					current = v;
+				},
+				None => break,
+			}
+		}
+		ends.remove(current);
+		next = ends.nearest(pts[current]).map(|(v, _)| v);
+	}
+	tour
+}
+
+/// Union-find lookup with path halving: the representative of the set `v` is in.
+fn find_root(parent:&mut [usize], mut v:usize) -> usize {
+	while parent[v] != v {
+		parent[v] = parent[parent[v]];
+		v = parent[v];
+	}
+	v
+}
+
+/// For each point, the indices of its `k` nearest other points, nearest first.
+/// Local search that only tries joining a point to its neighbors skips almost no good moves and runs far faster.
+pub fn neighbor_lists(points:&[(f32, f32)], k:usize) -> Vec<Vec<usize>> {
+	let pts:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+	let grid = SpatialGrid::from_points(&pts, SpatialGrid::cell_size_for(&pts, 2));
+	pts.par_iter().enumerate().map(|(idx, &p)| {
+		grid.k_nearest(p, k + 1).into_iter().map(|(other, _)| other).filter(|&other| other != idx).take(k).collect()
+	}).collect()
+}
+
+/// Where each point sits in the tour.
+fn positions(points:usize, tour:&[usize]) -> Vec<usize> {
+	let mut position = vec![0; points];
+	for (i, &city) in tour.iter().enumerate() {
+		position[city] = i;
+	}
+	position
+}
+
+/// Improve a tour in place by 2-opt: whenever reversing a stretch of the tour makes it shorter, do it, and keep going
+/// until no reversal helps.  Removes every crossing, which the genetic solver alone leaves plenty of.
+/// If `close` is false the tour is treated as an open path, so the ends are free to move too.
+/// With `neighbors` (from `neighbor_lists`), only moves that join a point to one of its neighbors are tried, which
+/// takes each pass from quadratic to linear in the number of points.
+/// Returns the number of improving moves made.
+pub fn two_opt<M:Metric + ?Sized>(points:&M, tour:&mut [usize], close:bool, neighbors:Option<&[Vec<usize>]>) -> usize {
+	let n = tour.len();
+	let mut moves = 0;
+	if n < 4 {
+		return moves;
+	}
+	let mut position = positions(points.point_count(), tour);
+
+	let mut improved = true;
+	while improved {
+		improved = false;
+		if !close {
+			// The start of an open path can move too, by reversing a prefix so that tour[0] joins tour[j+1].
+			let ends:Vec<usize> = match neighbors {
+				Some(lists) => lists[tour[0]].iter().map(|&c| position[c]).filter(|&p| p >= 2).map(|p| p - 1).collect(),
+				None => (1..n-1).collect(),
+			};
+			for j in ends {
+				let delta = points.between(tour[0], tour[j+1]) - points.between(tour[j], tour[j+1]);
+				if delta < -1e-6f32 {
+					reverse_tracked(tour, &mut position, 0, j);
+					moves += 1;
+					improved = true;
+				}
+			}
+		}
+		for i in 0..n-1 {
+			let pairs:Vec<(usize, usize)> = match neighbors {
+				// Joining tour[i] to a neighbor c is either the move at (i, c) or, along the other edges, (i-1, c-1).
+				Some(lists) => lists[tour[i]].iter().flat_map(|&c| {
+					let j = position[c];
+					let mut pairs = vec![(i.min(j), i.max(j))];
+					if i > 0 && j > 0 {
+						pairs.push(((i-1).min(j-1), (i-1).max(j-1)));
+					}
+					pairs
+				}).filter(|&(lo, hi)| hi >= lo + 2).collect(),
+				None => (i+2..n).map(|j| (i, j)).collect(),
+			};
+			for (lo, hi) in pairs {
+				if let Some(delta) = two_opt_delta(points, tour, lo, hi, close) {
+					if delta < -1e-6f32 {
+						reverse_tracked(tour, &mut position, lo+1, hi);
+						moves += 1;
+						improved = true;
+					}
+				}
+			}
+		}
+	}
+	moves
+}
+
+fn reverse_tracked(tour:&mut [usize], position:&mut [usize], from:usize, to:usize) {
+	tour[from..=to].reverse();
+	for (i, &city) in tour.iter().enumerate().take(to + 1).skip(from) {
+		position[city] = i;
+	}
+}
+
+/// How much reversing tour[i+1..=j] changes the tour's length, or None if that's not a real move.
+/// Edges (a, b) and (c, d) become (a, c) and (b, d).
+fn two_opt_delta<M:Metric + ?Sized>(points:&M, tour:&[usize], i:usize, j:usize, close:bool) -> Option<f32> {
+	let n = tour.len();
+	let d = |a:usize, b:usize| points.between(a, b);
+	let (a, b, c) = (tour[i], tour[i+1], tour[j]);
+	if j + 1 < n {
+		let e = tour[j+1];
+		Some(d(a, c) + d(b, e) - d(a, b) - d(c, e))
+	} else if close {
+		if i == 0 {
+			// (c, a) is already the closing edge.
+			return None;
+		}
+		let e = tour[0];
+		Some(d(a, c) + d(b, e) - d(a, b) - d(c, e))
+	} else {
+		// Reversing to the end of an open path only changes the one edge.
+		Some(d(a, c) - d(a, b))
+	}
+}
+
+/// Remove every crossing from a tour.  Crossing edges are found with a sweep over x (see `intersect::crossings`), and each pair is undone by
+/// reversing the stretch between them, which always makes the tour shorter.  Much cheaper than a full 2-opt when all
+/// you care about is how the drawing looks.
+/// If `close` is false the tour is treated as an open path.  Returns the number of crossings removed.
+pub fn uncross(points:&[(f32, f32)], tour:&mut [usize], close:bool) -> usize {
+	let n = tour.len();
+	let mut moves = 0;
+	if n < 4 {
+		return moves;
+	}
+	loop {
+		let edges:Vec<(usize, usize)> = (0..if close { n } else { n - 1 }).map(|i| (tour[i], tour[(i + 1) % n])).collect();
+		let crossings = crossing_edges(points, &edges);
+		if crossings.is_empty() {
+			return moves;
+		}
+		let mut position = positions(points.len(), tour);
+		let before = moves;
+		for (e, f) in crossings {
+			// Earlier reversals in this round may have already broken up one of the edges.
+			let (Some(i), Some(j)) = (edge_position(&position, edges[e], n, close), edge_position(&position, edges[f], n, close)) else {
+				continue;
+			};
+			let (i, j) = (i.min(j), i.max(j));
+			// Rounding can make nearly parallel edges look crossed; only go ahead if it really is shorter.
+			if two_opt_delta(points, tour, i, j, close).is_some_and(|delta| delta < 0f32) {
+				reverse_tracked(tour, &mut position, i+1, j);
+				moves += 1;
+			}
+		}
+		if moves == before {
+			return moves;
+		}
+	}
+}
+
+/// Where the edge between `a` and `b` starts in the tour, if they're still next to each other.
+fn edge_position(position:&[usize], (a, b):(usize, usize), n:usize, close:bool) -> Option<usize> {
+	let follows = |x:usize, y:usize| if close { (position[x] + 1) % n == position[y] } else { position[x] + 1 == position[y] };
+	if follows(a, b) {
+		Some(position[a])
+	} else if follows(b, a) {
+		Some(position[b])
+	} else {
+		None
+	}
+}
+
+/// Pairs of edges that cross each other.
+fn crossing_edges(points:&[(f32, f32)], edges:&[(usize, usize)]) -> Vec<(usize, usize)> {
+	let segments:Vec<(Point, Point)> = edges.iter().map(|&(a, b)| (points[a].into(), points[b].into())).collect();
+	intersect::crossings(&segments)
+}
+
+/// Improve a tour in place by Or-opt: take chains of one to three consecutive points and move them (possibly reversed)
+/// to wherever in the tour they fit best, as long as that makes the tour shorter.  Complements `two_opt`, which can't
+/// move a point without reversing everything between.
+/// If `close` is false the tour is treated as an open path.  With `neighbors`, a chain is only tried next to the
+/// neighbors of its ends.  Returns the number of improving moves made.
+pub fn or_opt<M:Metric + ?Sized>(points:&M, tour:&mut [usize], close:bool, neighbors:Option<&[Vec<usize>]>) -> usize {
+	let mut moves = 0;
+	if tour.len() < 5 {
+		return moves;
+	}
+	let mut position = positions(points.point_count(), tour);
+
+	let mut improved = true;
+	while improved {
+		improved = false;
+		for chain_length in 1..=3 {
+			let mut start = 0;
+			while start + chain_length <= tour.len() {
+				if let Some((spot, reversed)) = relocate_chain(points, tour, &position, start, chain_length, close, neighbors) {
+					move_chain(tour, &mut position, start, chain_length, spot, reversed);
+					moves += 1;
+					improved = true;
+				}
+				start += 1;
+			}
+		}
+	}
+	moves
+}
+
+/// Find the best place to move tour[start..start+chain_length] to.  If that's an improvement, gives back the position
+/// the chain goes after (None for the front of an open path) and whether it goes in reversed.
+fn relocate_chain<M:Metric + ?Sized>(points:&M, tour:&[usize], position:&[usize], start:usize, chain_length:usize, close:bool, neighbors:Option<&[Vec<usize>]>) -> Option<(Option<usize>, bool)> {
+	let n = tour.len();
+	let in_chain = |k:usize| (start..start+chain_length).contains(&k);
+	let chain = &tour[start..start+chain_length];
+	let distance = |a:usize, b:usize| points.between(a, b);
+	let first = chain[0];
+	let last = chain[chain_length-1];
+	let prev = if start > 0 { Some(tour[start-1]) } else if close { Some(tour[n-1]) } else { None };
+	let next = if start + chain_length < n { Some(tour[start+chain_length]) } else if close { Some(tour[0]) } else { None };
+
+	// What we save by cutting the chain out and joining its neighbors.
+	let removed = prev.map_or(0f32, |p| distance(p, first)) + next.map_or(0f32, |q| distance(last, q)) - match (prev, next) {
+		(Some(p), Some(q)) => distance(p, q),
+		_ => 0f32,
+	};
+
+	// Once the chain is cut out, the position after (or before) k.  None off the ends of an open path.
+	let step = |k:usize, forward:bool| -> Option<usize> {
+		let mut q = k;
+		loop {
+			q = match (forward, q) {
+				(true, q) if q + 1 < n => q + 1,
+				(false, q) if q > 0 => q - 1,
+				_ if !close => return None,
+				(true, _) => 0,
+				(false, _) => n - 1,
+			};
+			if !in_chain(q) {
+				return Some(q);
+			}
+		}
+	};
+	let front = (0..n).find(|&k| !in_chain(k))?;
+
+	// Places to try, as the position the chain goes after.  None is the front of an open path.
+	let spots:Vec<Option<usize>> = match neighbors {
+		Some(lists) => {
+			let mut spots = vec![];
+			for &end in &[chain[0], chain[chain_length-1]] {
+				for &c in &lists[end] {
+					let k = position[c];
+					if !in_chain(k) {
+						spots.push(Some(k));
+						spots.push(step(k, false));
+					}
+				}
+			}
+			spots
+		},
+		None => (0..n).filter(|&k| !in_chain(k)).map(Some).chain(std::iter::once(None)).collect(),
+	};
+
+	// (cost, insert after, reversed)
+	let mut best:Option<(f32, Option<usize>, bool)> = None;
+	for spot in spots {
+		if spot.is_none() && close {
+			continue;
+		}
+		for &reversed in &[false, true] {
+			let (head, tail) = if reversed { (last, first) } else { (first, last) };
+			let added = match spot {
+				Some(k) => {
+					let a = tour[k];
+					distance(a, head) + step(k, true).map_or(0f32, |b| distance(tail, tour[b]) - distance(a, tour[b]))
+				},
+				None => distance(tail, tour[front]),
+			};
+			if best.is_none_or(|(best_cost, _, _)| added < best_cost) {
+				best = Some((added, spot, reversed));
+			}
+		}
+	}
+
+	let (cost, spot, reversed) = best?;
+	if cost >= removed - 1e-6f32 {
+		return None;
+	}
+	Some((spot, reversed))
+}
+
+/// Move tour[start..start+chain_length] to go after `spot`, as `relocate_chain` chose.  Only the stretch between
+/// where the chain was and where it goes shifts, so only that much of `position` needs fixing.
+fn move_chain(tour:&mut [usize], position:&mut [usize], start:usize, chain_length:usize, spot:Option<usize>, reversed:bool) {
+	let end = start + chain_length;
+	let (range, chain) = match spot {
+		Some(k) if k >= end => {
+			tour[start..=k].rotate_left(chain_length);
+			(start..k + 1, k + 1 - chain_length..k + 1)
+		},
+		_ => {
+			let from = spot.map_or(0, |k| k + 1);
+			tour[from..end].rotate_right(chain_length);
+			(from..end, from..from + chain_length)
+		},
+	};
+	if reversed {
+		tour[chain].reverse();
+	}
+	for k in range {
+		position[tour[k]] = k;
+	}
+}
+
+/// A closed tour that can reverse any stretch of itself in place.  Long stretches are handled by reversing the rest and
+/// flipping which way the tour is read, so no move costs more than half the tour.
+struct ArrayTour {
+	order: Vec<usize>,
+	position: Vec<usize>,
+	reversed: bool,
+}
+
+impl ArrayTour {
+	fn new(tour:&[usize]) -> Self {
+		let mut position = vec![0; tour.len()];
+		for (i, &city) in tour.iter().enumerate() {
+			position[city] = i;
+		}
+		ArrayTour { order: tour.to_vec(), position, reversed: false }
+	}
+
+	fn step(&self, city:usize, forward:bool) -> usize {
+		let n = self.order.len();
+		let i = self.position[city];
+		if forward != self.reversed { self.order[(i + 1) % n] } else { self.order[(i + n - 1) % n] }
+	}
+
+	fn next(&self, city:usize) -> usize {
+		self.step(city, true)
+	}
+
+	fn prev(&self, city:usize) -> usize {
+		self.step(city, false)
+	}
+
+	/// Reverse the stretch of the tour running forward from `from` to `to`.
+	fn reverse(&mut self, from:usize, to:usize) {
+		let n = self.order.len();
+		let (mut a, mut b) = if self.reversed { (to, from) } else { (from, to) };
+		let inside = (self.position[b] + n - self.position[a]) % n + 1;
+		if inside * 2 > n {
+			// Reversing the complement gives the mirror image of what we want, so read the tour the other way from now on.
+			let after = self.order[(self.position[b] + 1) % n];
+			let before = self.order[(self.position[a] + n - 1) % n];
+			a = after;
+			b = before;
+			self.reversed = !self.reversed;
+		}
+		let mut i = self.position[a];
+		let mut j = self.position[b];
+		for _ in 0..((j + n - i) % n).div_ceil(2) {
+			self.order.swap(i, j);
+			self.position[self.order[i]] = i;
+			self.position[self.order[j]] = j;
+			i = (i + 1) % n;
+			j = (j + n - 1) % n;
+		}
+	}
+
+	fn into_tour(self) -> Vec<usize> {
+		if self.reversed {
+			self.order.into_iter().rev().collect()
+		} else {
+			self.order
+		}
+	}
+}
+
+/// The ways to extend a Lin–Kernighan chain from t2: each t3 near t2 that keeps the chain's gain positive, with the
+/// t4 whose edge it cuts, best first.
+fn chain_choices(points:&[(f32, f32)], candidates:&[usize], array:&ArrayTour, t1:usize, t2:usize, change:f32) -> Vec<(f32, usize, usize)> {
+	let d = |a:usize, b:usize| distance(points[a], points[b]);
+	let mut choices = vec![];
+	for &t3 in candidates {
+		if d(t1, t2) - d(t2, t3) - change <= 0f32 {
+			// The lists are sorted, so every later t3 is worse.
+			break;
+		}
+		if t3 == t1 || t3 == array.next(t2) {
+			continue;
+		}
+		let t4 = array.prev(t3);
+		choices.push((d(t4, t3) - d(t2, t3), t3, t4));
+	}
+	choices.sort_by(|a, b| b.0.total_cmp(&a.0));
+	choices
+}
+
+/// Improve a closed tour in place with a Lin–Kernighan style search.  From each point it chains up to `max_depth`
+/// 2-opt moves, each picking the new edge from the `neighbors` nearest points, and keeps the best prefix of the chain
+/// if that shortens the tour.  Points whose surroundings haven't changed are skipped ("don't-look bits"), so this
+/// scales to tens of thousands of points and beats `two_opt` and `or_opt` combined.
+/// Returns the number of improving chains made.
+pub fn lin_kernighan(points:&[(f32, f32)], tour:&mut Vec<usize>, neighbors:usize, max_depth:usize) -> usize {
+	let n = tour.len();
+	let mut moves = 0;
+	if n < 5 {
+		return moves;
+	}
+	let candidates = neighbor_lists(points, neighbors);
+	let d = |a:usize, b:usize| distance(points[a], points[b]);
+	let mut array = ArrayTour::new(tour);
+
+	let mut queue:std::collections::VecDeque<usize> = tour.iter().copied().collect();
+	let mut queued = vec![true; n];
+	while let Some(t1) = queue.pop_front() {
+		queued[t1] = false;
+		for _ in 0..2 {
+			// Try breaking the edge on either side of t1 by flipping which way the tour is read.
+			array.reversed = !array.reversed;
+
+			// Each applied move removes (t1, t2) and (t4, t3) and adds (t2, t3) and (t1, t4); t4 becomes the new t2.
+			// The chain is greedy past the first move, but the first move tries every candidate until one pays off.
+			let mut applied:Vec<(usize, usize)> = vec![];
+			let mut best_moves = 0;
+			let first_choices = chain_choices(points, &candidates[array.next(t1)], &array, t1, array.next(t1), 0f32).len();
+			for first in 0..first_choices {
+				let mut change = 0f32;
+				let mut best_change = -1e-4f32;
+				let mut t2 = array.next(t1);
+				while applied.len() < max_depth {
+					let choices = chain_choices(points, &candidates[t2], &array, t1, t2, change);
+					let pick = if applied.is_empty() { choices.get(first) } else { choices.first() };
+					let Some(&(_, t3, t4)) = pick else { break };
+					change += d(t2, t3) + d(t1, t4) - d(t1, t2) - d(t4, t3);
+					array.reverse(t2, t4);
+					applied.push((t2, t4));
+					if change < best_change {
+						best_change = change;
+						best_moves = applied.len();
+					}
+					t2 = t4;
+				}
+
+				// Back out whatever part of the chain didn't pay off.
+				for &(from, to) in applied[best_moves..].iter().rev() {
+					array.reverse(to, from);
+				}
+				applied.truncate(best_moves);
+				if best_moves > 0 {
+					break;
+				}
+			}
+			if best_moves > 0 {
+				moves += 1;
+				for &(a, b) in &applied[..best_moves] {
+					for city in [a, b, array.next(a), array.prev(a), array.next(b), array.prev(b)] {
+						if !queued[city] {
+							queued[city] = true;
+							queue.push_back(city);
+						}
+					}
 				}
-		});
+				if !queued[t1] {
+					queued[t1] = true;
+					queue.push_back(t1);
+				}
+			}
+		}
 	}
 
-	res
+	*tour = array.into_tour();
+	moves
+}
+
+fn distance(p:(f32, f32), q:(f32, f32)) -> f32 {
+	let dx = q.0 - p.0;
+	let dy = q.1 - p.1;
+	((dx*dx) + (dy*dy)).sqrt()
+}
+
+/// Order crossover (OX1) between two parent tours.
+/// A random stretch is copied straight from `p`, then the rest of the points are filled in the order `q` visits them,
+/// starting after the stretch and wrapping around.  Unlike mixing element by element, this always gives a valid tour.
+/// Given parents
+/// [0, 1, 2, 3, 4, 5]
+/// [5, 3, 1, 0, 4, 2]
+/// and the stretch 1..=2, the child is [3, 1, 2, 0, 4, 5].
+fn order_crossover(p:&[usize], q:&[usize], rng:&mut impl Rng) -> Vec<usize> {
+	let n = p.len();
+	let mut i = rng.gen_range(0, n);
+	let mut j = rng.gen_range(0, n);
+	if i > j {
+		std::mem::swap(&mut i, &mut j);
+	}
+	order_crossover_between(p, q, i, j)
+}
+
+fn order_crossover_between(p:&[usize], q:&[usize], i:usize, j:usize) -> Vec<usize> {
+	let n = p.len();
+	let mut used = vec![false; n];
+	let mut child = vec![0; n];
+	for k in i..=j {
+		child[k] = p[k];
+		used[p[k]] = true;
+	}
+	let mut slot = (j + 1) % n;
+	for k in 0..n {
+		let city = q[(j + 1 + k) % n];
+		if !used[city] {
+			child[slot] = city;
+			slot = (slot + 1) % n;
+		}
+	}
+	child
+}
+
+/// With probability `mutation_odds` each, swap two random points and reverse a random stretch of the tour.
+fn mutate(tour:&mut [usize], mutation_odds:f64, rng:&mut impl Rng) {
+	let n = tour.len();
+	if rng.gen_bool(mutation_odds) {
+		tour.swap(rng.gen_range(0, n), rng.gen_range(0, n));
+	}
+	if rng.gen_bool(mutation_odds) {
+		let mut i = rng.gen_range(0, n);
+		let mut j = rng.gen_range(0, n);
+		if i > j {
+			std::mem::swap(&mut i, &mut j);
+		}
+		tour[i..=j].reverse();
+	}
 }
 
 /// Build a tour that touches every point from a vec of indices.
@@ -114,7 +1424,7 @@ fn cross_vectors(p:&Vec<usize>, q:&Vec<usize>, mutation_odds:f64, num_points:usi
 /// If we get `unselected` = [2, 1, 0], we give back [c, b, a].
 /// Maps each entry in `unselected` to some index%num_points, with the num_points decreasing as more
 /// are drawn from the pile.  Will never select more than one visit to the same item.
-fn tour_from_unselected(num_points:usize, unselected:Vec<usize>) -> Vec<usize> {
+pub fn tour_from_unselected(num_points:usize, unselected:Vec<usize>) -> Vec<usize> {
 	let mut points:Vec<usize> = (0..num_points).collect();
 	let mut ordering = vec![];
 
@@ -134,7 +1444,305 @@ mod test {
 	fn test_sanity() {
 		// Just see if it runs without crashing.
 		let pts = vec![(0f32, 0f32), (1f32, 0f32), (0f32, 1f32), (1f32, 1f32)];
-		let tour = solve_tsp_approx(&pts, 10, false);
+		let _tour = solve_tsp_approx(&pts, GaConfig::default(), StopCriteria::generations(10), &Length { close: true }, &mut |_, _, _| {}, &mut StdRng::seed_from_u64(0));
+	}
+
+	#[test]
+	fn test_seeded_runs_repeat() {
+		let pts:Vec<(f32, f32)> = (0..30).map(|i| (((i * 13) % 30) as f32, ((i * 7) % 11) as f32)).collect();
+		let mut reports = vec![];
+		let first = solve_tsp_approx(&pts, GaConfig::default(), StopCriteria::generations(5), &Length { close: true }, &mut |generation, _, _| reports.push(generation), &mut StdRng::seed_from_u64(42));
+		assert_eq!(reports, vec![0, 1, 2, 3, 4, 5]);
+		let second = solve_tsp_approx(&pts, GaConfig::default(), StopCriteria::generations(5), &Length { close: true }, &mut |_, _, _| {}, &mut StdRng::seed_from_u64(42));
+		assert_eq!(first, second);
+	}
+
+	#[test]
+	fn test_stall_stops_early() {
+		// Four points only have one tour, so nothing ever improves after the first generation.
+		let pts = vec![(0f32, 0f32), (1f32, 0f32), (0f32, 1f32), (1f32, 1f32)];
+		let stop = StopCriteria { max_generations: Some(10_000), stall_generations: Some(3), ..Default::default() };
+		let started = Instant::now();
+		let mut last = 0;
+		let tour = solve_tsp_approx(&pts, GaConfig::default(), stop, &Length { close: true }, &mut |generation, _, _| last = generation, &mut StdRng::seed_from_u64(0));
+		assert_eq!(tour.len(), 4);
+		// The first generation sets the best, and three more without beating it end the run.
+		assert_eq!(last, 3);
+		assert!(!stop.reached(10, 2, started));
+		assert!(stop.reached(0, 3, started));
+	}
+
+	#[test]
+	fn test_resume_matches_uninterrupted() {
+		let pts:Vec<(f32, f32)> = (0..20).map(|i| (((i * 13) % 20) as f32, ((i * 7) % 11) as f32)).collect();
+		let config = GaConfig { population: 20, selection: Selection::TopTwo, ..Default::default() };
+		let state = GaState::new(&pts, config.population, &mut StdRng::seed_from_u64(9));
+		let checkpoint = Checkpoint { path: std::env::temp_dir().join("tessellate_test_checkpoint.tspg"), every: 4 };
+
+		// Picking up from the checkpoint at generation 4 should finish exactly like the run that wrote it.
+		let straight = evolve(state, config, StopCriteria::generations(7), &Length { close: true }, &mut |_, _, _| {}, Some(&checkpoint));
+		let loaded = GaState::load(&mut File::open(&checkpoint.path).unwrap()).unwrap();
+		assert_eq!(loaded.generation, 4);
+		let resumed = evolve(loaded, config, StopCriteria::generations(7), &Length { close: true }, &mut |_, _, _| {}, None);
+		assert_eq!(straight, resumed);
+		fs::remove_file(&checkpoint.path).unwrap();
+	}
+
+	#[test]
+	fn test_load_rejects_bad_state() {
+		let pts:Vec<(f32, f32)> = (0..5).map(|i| (i as f32, (i * i) as f32)).collect();
+		let mut buffer = vec![];
+		GaState::new(&pts, 3, &mut StdRng::seed_from_u64(1)).save(&mut buffer).unwrap();
+		assert!(GaState::load(&mut buffer.as_slice()).is_ok());
+		// The point count sits after the 33 byte header.  A huge one with nothing behind it just runs out of input.
+		let mut huge = buffer[..37].to_vec();
+		huge[33..37].copy_from_slice(&u32::MAX.to_le_bytes());
+		assert!(GaState::load(&mut huge.as_slice()).is_err());
+		// The first tour's cities come after the points, the tour count and its length, and it can't repeat one.
+		let mut repeated = buffer.clone();
+		let cities = 37 + 8 * pts.len() + 8;
+		let first = repeated[cities..cities + 4].to_vec();
+		repeated[cities + 4..cities + 8].copy_from_slice(&first);
+		assert_eq!(GaState::load(&mut repeated.as_slice()).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+	}
+
+	#[test]
+	fn test_islands() {
+		let pts:Vec<(f32, f32)> = (0..25).map(|i| (((i * 13) % 25) as f32, ((i * 7) % 11) as f32)).collect();
+		let config = GaConfig { population: 30, ..Default::default() };
+		let islands = IslandConfig { islands: 3, migration_interval: 4, migrants: 2 };
+		let mut reports = vec![];
+		let run = |reports:&mut Vec<u64>| solve_tsp_islands(&pts, config, islands, StopCriteria::generations(10), &Length { close: true }, &mut |generation, _, _| reports.push(generation), &mut StdRng::seed_from_u64(5));
+		let first = run(&mut reports);
+		assert_eq!(reports, vec![4, 8, 10]);
+		assert_eq!(first, run(&mut vec![]));
+		let mut sorted = first.clone();
+		sorted.sort();
+		assert_eq!(sorted, (0..25).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_order_crossover() {
+		let p = [0, 1, 2, 3, 4, 5];
+		let q = [5, 3, 1, 0, 4, 2];
+		assert_eq!(order_crossover_between(&p, &q, 1, 2), vec![3, 1, 2, 0, 4, 5]);
+		let mut rng = StdRng::seed_from_u64(1);
+		let mut child = order_crossover(&p, &q, &mut rng);
+		mutate(&mut child, 1f64, &mut rng);
+		child.sort();
+		assert_eq!(child, p.to_vec());
+	}
+
+	#[test]
+	fn test_two_opt_uncrosses() {
+		let pts = vec![(0f32, 0f32), (1f32, 1f32), (1f32, 0f32), (0f32, 1f32)];
+		let mut tour = vec![0, 1, 2, 3];
+		assert!(two_opt(&pts, &mut tour, true, None) > 0);
+		assert_eq!(tour_length(&pts, Some(&tour), true), 4f32);
+
+		let pts = vec![(0f32, 0f32), (2f32, 0f32), (1f32, 0f32), (3f32, 0f32)];
+		let mut tour = vec![0, 1, 2, 3];
+		two_opt(&pts, &mut tour, false, None);
+		assert_eq!(tour_length(&pts, Some(&tour), false), 3f32);
+	}
+
+	#[test]
+	fn test_or_opt_moves_stray_point() {
+		let pts:Vec<(f32, f32)> = (0..8).map(|i| (i as f32, 0f32)).collect();
+		// Point 3 is visited at the wrong time.
+		let mut tour = vec![0, 1, 2, 4, 5, 6, 3, 7];
+		assert!(or_opt(&pts, &mut tour, false, None) > 0);
+		assert_eq!(tour_length(&pts, Some(&tour), false), 7f32);
+	}
+
+	#[test]
+	fn test_move_chain() {
+		let n = 8;
+		for chain_length in 1..=3 {
+			for start in 0..=n - chain_length {
+				let spots = (0..n).filter(|k| !(start..start + chain_length).contains(k)).map(Some).chain(std::iter::once(None));
+				for spot in spots {
+					for &reversed in &[false, true] {
+						let mut chain:Vec<usize> = (start..start + chain_length).collect();
+						if reversed {
+							chain.reverse();
+						}
+						let mut expected = if spot.is_none() { chain.clone() } else { vec![] };
+						for k in (0..n).filter(|k| !chain.contains(k)) {
+							expected.push(k);
+							if spot == Some(k) {
+								expected.extend(&chain);
+							}
+						}
+						let mut tour:Vec<usize> = (0..n).collect();
+						let mut position = positions(n, &tour);
+						move_chain(&mut tour, &mut position, start, chain_length, spot, reversed);
+						assert_eq!(tour, expected);
+						assert_eq!(position, positions(n, &tour));
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_neighbor_lists_restrict_moves() {
+		let pts:Vec<(f32, f32)> = (0..50).map(|i| (((i * 17) % 50) as f32, ((i * 29) % 31) as f32)).collect();
+		let neighbors = neighbor_lists(&pts, 6);
+		assert!(neighbors.iter().all(|list| list.len() == 6));
+		let mut tour:Vec<usize> = (0..50).collect();
+		let before = tour_length(&pts, Some(&tour), true);
+		two_opt(&pts, &mut tour, true, Some(&neighbors));
+		or_opt(&pts, &mut tour, true, Some(&neighbors));
+		assert!(tour_length(&pts, Some(&tour), true) < before * 0.5f32);
+		tour.sort();
+		assert_eq!(tour, (0..50).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_christofides_tour() {
+		let pts:Vec<(f32, f32)> = (0..36).map(|i| ((i % 6) as f32, (i / 6) as f32)).collect();
+		let mut tour = christofides_tour(&pts);
+		// The best tour is 36 long; greedy matching loses the guarantee, but a grid should still stay within 1.5x.
+		assert!(tour_length(&pts, Some(&tour), true) <= 54f32);
+		tour.sort();
+		assert_eq!(tour, (0..36).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_greedy_edge_ring() {
+		// Points on a ring: greedy edges follow it all the way round, where a greedy walk from point 0 would not.
+		let pts:Vec<(f32, f32)> = (0..24).map(|i| {
+			let angle = (i * 7 % 24) as f32 / 24f32 * std::f32::consts::TAU;
+			(10f32 * angle.cos(), 10f32 * angle.sin())
+		}).collect();
+		let mut tour = greedy_edge_tour(&pts, 4);
+		let perimeter = 24f32 * 2f32 * 10f32 * (std::f32::consts::PI / 24f32).sin();
+		assert!((tour_length(&pts, Some(&tour), true) - perimeter).abs() < 1e-3f32);
+		tour.sort();
+		assert_eq!(tour, (0..24).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_nearest_neighbor_tour() {
+		let pts = vec![(0f32, 0f32), (3f32, 0f32), (1f32, 0f32), (2f32, 0f32)];
+		assert_eq!(nearest_neighbor_tour(&pts, 0), vec![0, 2, 3, 1]);
+	}
+
+	#[test]
+	fn test_curvature_penalizes_zigzag() {
+		let pts = vec![(0f32, 0f32), (1f32, 0f32), (2f32, 0f32), (1f32, 1f32)];
+		let curvature = Curvature { close: false, weight: 1f32 };
+		// Both paths are two units long, but the second turns a corner.
+		assert_eq!(Length { close: false }.cost(&pts, &[0, 1, 2]), Length { close: false }.cost(&pts, &[0, 1, 3]));
+		assert_eq!(curvature.cost(&pts, &[0, 1, 2]), 2f32);
+		assert!((curvature.cost(&pts, &[0, 1, 3]) - (2f32 + std::f32::consts::FRAC_PI_2)).abs() < 1e-5f32);
+	}
+
+	#[test]
+	fn test_plot_time() {
+		let plotter = PlotTime { close: false, max_speed: 10f32, acceleration: 100f32 };
+		// Ramping up to full speed and back down takes one unit of distance and 0.2s.
+		assert!((plotter.segment_time(1f32) - 0.2f32).abs() < 1e-6f32);
+		assert!((plotter.segment_time(3f32) - 0.4f32).abs() < 1e-6f32);
+		// One long segment beats two short ones covering the same ground.
+		let pts = vec![(0f32, 0f32), (0.5f32, 0f32), (1f32, 0f32)];
+		assert!(plotter.cost(&pts, &[0, 2]) < plotter.cost(&pts, &[0, 1, 2]));
+	}
+
+	#[test]
+	fn test_uncross() {
+		// Zigzagging across a 3x3 grid crosses itself; uncrossing should leave no crossings and a shorter tour.
+		let pts:Vec<(f32, f32)> = (0..9).map(|i| ((i % 3) as f32, (i / 3) as f32)).collect();
+		let mut tour = vec![0, 4, 2, 3, 1, 5, 6, 8, 7];
+		let before = tour_length(&pts, Some(&tour), true);
+		assert!(uncross(&pts, &mut tour, true) > 0);
+		let edges:Vec<(usize, usize)> = (0..9).map(|i| (tour[i], tour[(i + 1) % 9])).collect();
+		assert!(crossing_edges(&pts, &edges).is_empty());
+		assert!(tour_length(&pts, Some(&tour), true) < before);
+		let mut sorted = tour.clone();
+		sorted.sort();
+		assert_eq!(sorted, (0..9).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_tour_tracks_length() {
+		let pts:Vec<(f32, f32)> = (0..12).map(|i| (((i * 7) % 12) as f32, ((i * 5) % 9) as f32)).collect();
+		for close in [true, false] {
+			let mut tour = Tour::new(&pts, (0..12).collect(), close);
+			for &(i, j) in &[(0, 11), (2, 3), (0, 5), (4, 9), (1, 10), (6, 6)] {
+				tour.reverse(&pts, i, j);
+				assert!((tour.length() - tour_length(&pts, Some(tour.order()), close)).abs() < 1e-3f32);
+			}
+		}
+	}
+
+	#[test]
+	fn test_annealing_improves_tour() {
+		let pts:Vec<(f32, f32)> = (0..40).map(|i| ((i % 8) as f32, (i / 8) as f32)).collect();
+		let start:Vec<usize> = (0..40).map(|i| (i * 17) % 40).collect();
+		let before = tour_length(&pts, Some(&start), true);
+		let schedule = CoolingSchedule::for_tour(&pts, &start);
+		let mut tour = solve_tsp_annealing(&pts, start, 50_000, schedule, true, &mut |_, _, _| {}, &mut StdRng::seed_from_u64(2));
+		assert!(tour_length(&pts, Some(&tour), true) < before);
+		tour.sort();
+		assert_eq!(tour, (0..40).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_open_annealing_and_cut() {
+		let pts:Vec<(f32, f32)> = (0..10).map(|i| (i as f32, 0f32)).collect();
+		let start = vec![0, 5, 1, 6, 2, 7, 3, 8, 4, 9];
+		let schedule = CoolingSchedule::Linear { start: 0f32 };
+		let tour = solve_tsp_annealing(&pts, start, 20_000, schedule, false, &mut |_, _, _| {}, &mut StdRng::seed_from_u64(3));
+		assert_eq!(tour_length(&pts, Some(&tour), false), 9f32);
+
+		let closed = vec![3, 4, 5, 6, 7, 8, 9, 0, 1, 2];
+		assert_eq!(cut_longest_edge(&pts, &closed), (0..10).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_ant_colony_beats_greedy() {
+		let pts:Vec<(f32, f32)> = (0..36).map(|i| ((i % 6) as f32, (i / 6) as f32)).collect();
+		let greedy = tour_length(&pts, Some(&nearest_neighbor_tour(&pts, 0)), true);
+		let config = AcoConfig { ants: 10, neighbors: 6, ..Default::default() };
+		let mut tour = solve_tsp_ant_colony(&pts, config, StopCriteria::generations(40), true, &mut |_, _, _| {}, &mut StdRng::seed_from_u64(5));
+		assert!(tour_length(&pts, Some(&tour), true) < greedy);
+		tour.sort();
+		assert_eq!(tour, (0..36).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_reverse_closed() {
+		let pts:Vec<(f32, f32)> = (0..6).map(|i| ((i * i) as f32, (i % 3) as f32)).collect();
+		let mut expected = vec![0, 1, 2, 3, 4, 5];
+		expected[1..=4].reverse();
+		// Long enough that the complement gets reversed instead, which is the same cycle.
+		let mut tour = vec![0, 1, 2, 3, 4, 5];
+		reverse_closed(&mut tour, 1, 4);
+		assert_ne!(tour, expected);
+		assert_eq!(tour_length(&pts, Some(&tour), true), tour_length(&pts, Some(&expected), true));
+	}
+
+	#[test]
+	fn test_lin_kernighan_matches_grid() {
+		// The best tour of a 6x6 grid is 36 long; allow one diagonal shortcut's worth of slack.
+		let pts:Vec<(f32, f32)> = (0..36).map(|i| ((i % 6) as f32, (i / 6) as f32)).collect();
+		let mut tour:Vec<usize> = (0..36).map(|i| (i * 7) % 36).collect();
+		assert!(lin_kernighan(&pts, &mut tour, 8, 10) > 0);
+		assert!(tour_length(&pts, Some(&tour), true) < 36.9f32);
+		tour.sort();
+		assert_eq!(tour, (0..36).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn test_array_tour_reverse() {
+		let mut array = ArrayTour::new(&[0, 1, 2, 3, 4, 5]);
+		array.reverse(1, 2);
+		assert_eq!(array.order, vec![0, 2, 1, 3, 4, 5]);
+		// Long enough to flip the rest instead.
+		array.reverse(2, 4);
+		assert_eq!(array.into_tour().len(), 6);
 	}
 
 	#[test]
@@ -152,6 +1760,6 @@ mod test {
 	#[test]
 	fn test_back_and_forth() {
 		let pts = vec![(0f32, 0f32), (1f32, 0f32)];
-		assert_eq!(tour_length(&pts, Some(&vec![0usize, 1, 0, 1]), false), 3f32);
+		assert_eq!(tour_length(&pts, Some(&[0usize, 1, 0, 1]), false), 3f32);
 	}
 }
\ No newline at end of file
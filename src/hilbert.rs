@@ -1,83 +1,108 @@
+use crate::point::Point;
+use rayon::prelude::*;
+use std::io::{self, Read, Write};
 
+/// Index of a node in a `Hilbert` tree's arena.
+pub type NodeId = u32;
+
+/// A quadtree whose cells are visited in Hilbert curve order.
+/// All cells live in one flat arena and refer to their children by index, which keeps deep trees cheap to build.
+/// The root is always node 0.
 pub struct Hilbert {
+	nodes: Vec<Node>,
+	max_depth: Option<u32>,
+	min_cell_size: u32,
+}
+
+pub struct Node {
 	orientation: QuadOrientation,
-	leaves: [Option<Box<Hilbert>>; 4], // UL, UR, DL, DR
-	left: u32,
-	right: u32,
-	top: u32,
-	bottom: u32,
+	leaves: [Option<NodeId>; 4], // UL, UR, DL, DR
+	// Bounds are kept in floating point so that odd sizes split evenly instead of drifting toward one side.
+	left: f32,
+	right: f32,
+	top: f32,
+	bottom: f32,
+}
+
+/// How to lay the quadtree over an image that isn't a power-of-two square.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fit {
+	/// Cover exactly the image.  Cells are split evenly in floating point, so on a non-square image they're rectangles.
+	Stretch,
+	/// Cover the smallest power-of-two square holding the image, anchored at the top left, so every cell is a square of
+	/// whole pixels.  The image frame is unchanged, but points that land outside the image need to be clipped.
+	Pad,
+}
+
+/// The size of the canvas the root cell should cover for an image of the given size.
+pub fn canvas_size(width:u32, height:u32, fit:Fit) -> (u32, u32) {
+	match fit {
+		Fit::Stretch => (width, height),
+		Fit::Pad => {
+			let side = width.max(height).max(1).next_power_of_two();
+			(side, side)
+		}
+	}
 }
 
 #[derive(Copy, Clone)]
 pub enum QuadOrientation {
-	A, B, C, D
+	A, B, C, D,
+	/// Only meaningful at the root.  Arranges four Hilbert curves into a Moore curve, which ends next to where it starts.
+	Moore,
 }
 
-impl Hilbert {
-	pub fn new(right:u32, top:u32, left:u32, bottom:u32, starting_orientation:Option<QuadOrientation>) -> Self {
-		let orientation = if let Some(o) = starting_orientation {
-			o
-		} else {
-			QuadOrientation::A
-		};
-
-		Hilbert {
+impl Node {
+	fn new(right:f32, top:f32, left:f32, bottom:f32, orientation:QuadOrientation) -> Self {
+		Node {
 			orientation,
 			leaves: [None, None, None, None],
 			left, right, top, bottom
 		}
 	}
 
-	pub fn get_leaf(&self, x:u32, y:u32) -> &Option<Box<Hilbert>> {
-		let mid_x = (self.left + self.right) / 2;
-		let mid_y = (self.top + self.bottom) / 2;
-		return if y <= mid_y {
-			if x <= mid_x {
-				&self.leaves[0]
-			} else {
-				&self.leaves[1]
-			}
-		} else {
-			if x <= mid_x {
-				&self.leaves[2]
-			} else {
-				&self.leaves[3]
-			}
-		}
+	/// The cell's extent as (left, top, right, bottom).
+	pub fn bounds(&self) -> (f32, f32, f32, f32) {
+		(self.left, self.top, self.right, self.bottom)
 	}
 
-	pub fn subdivide_leaf(&mut self, x:u32, y:u32, depth:u32) {
-		if depth == 0 {
-			return;
+	/// Which of the four leaves (UL: 0, UR: 1, DL: 2, DR: 3) holds the center of pixel x, y.
+	fn quadrant(&self, x:u32, y:u32) -> usize {
+		self.quadrant_of_point(x as f32 + 0.5f32, y as f32 + 0.5f32)
+	}
+
+	fn quadrant_of_point(&self, x:f32, y:f32) -> usize {
+		let mid_x = (self.left + self.right) / 2f32;
+		let mid_y = (self.top + self.bottom) / 2f32;
+		match (x <= mid_x, y <= mid_y) {
+			(true, true) => 0,
+			(false, true) => 1,
+			(true, false) => 2,
+			(false, false) => 3,
 		}
+	}
 
-		let mid_x = (self.left + self.right) / 2;
-		let mid_y = (self.top + self.bottom) / 2;
-		let mut leaf = if y <= mid_y {
-			if x <= mid_x {
-				&mut self.leaves[0]
-			} else {
-				&mut self.leaves[1]
-			}
-		} else {
-			if x <= mid_x {
-				&mut self.leaves[2]
-			} else {
-				&mut self.leaves[3]
-			}
-		};
-		if let Some(leaf) = leaf {
-			leaf.subdivide_leaf(x, y, depth-1);
-		} else {
-			self.subdivide();
-			self.subdivide_leaf(x, y, depth-1);
+	/// True if splitting this cell would give children at least `min_size` wide and tall.
+	fn can_split(&self, min_size:u32) -> bool {
+		let min_size = min_size.max(1) as f32;
+		self.right - self.left >= 2f32*min_size && self.bottom - self.top >= 2f32*min_size
+	}
+
+	/// The extent of one quadrant of this cell as (left, top, right, bottom).
+	pub fn quadrant_bounds(&self, quadrant:usize) -> (f32, f32, f32, f32) {
+		let mid_x = (self.left + self.right) / 2f32;
+		let mid_y = (self.top + self.bottom) / 2f32;
+		match quadrant {
+			0 => (self.left, self.top, mid_x, mid_y), // UL
+			1 => (mid_x, self.top, self.right, mid_y), // UR
+			2 => (self.left, mid_y, mid_x, self.bottom), // DL
+			_ => (mid_x, mid_y, self.right, self.bottom), // DR
 		}
 	}
 
-	pub fn subdivide(&mut self) {
-		let mid_x = (self.left + self.right) / 2;
-		let mid_y = (self.top + self.bottom) / 2;
-		let mut new_orientations = match self.orientation {
+	/// Build the (unsubdivided) child cell for the given quadrant.
+	fn make_child(&self, quadrant:usize) -> Node {
+		let new_orientations = match self.orientation {
 			// A:
 			// AA
 			// DB
@@ -114,27 +139,32 @@ impl Hilbert {
 				QuadOrientation::A,
 				QuadOrientation::D,
 			],
+			// Moore:
+			// BD
+			// BD
+			QuadOrientation::Moore => [
+				QuadOrientation::B,
+				QuadOrientation::D,
+				QuadOrientation::B,
+				QuadOrientation::D,
+			],
 		};
 
-		self.leaves = [
-			Some(Box::new(Hilbert::new(mid_x, self.top, self.left, mid_y, Some(new_orientations[0])))), // UL
-			Some(Box::new(Hilbert::new(self.right, self.top, mid_x, mid_y, Some(new_orientations[1])))), // UR
-			Some(Box::new(Hilbert::new(mid_x, mid_y, self.left, self.bottom, Some(new_orientations[2])))), // DL
-			Some(Box::new(Hilbert::new(self.right, mid_y, mid_x, self.bottom, Some(new_orientations[3])))), // DR
-		];
+		let (left, top, right, bottom) = self.quadrant_bounds(quadrant);
+		Node::new(right, top, left, bottom, new_orientations[quadrant])
 	}
 
-	pub fn rasterize(&self) -> Vec<(f32, f32)> {
+	/// The order this cell visits its quadrants in, along with the point to use for each quadrant that isn't subdivided.
+	fn visit_order(&self) -> ([usize; 4], [(f32, f32); 4]) {
 		// Order is determined by _this_ orientation.
-		let x_mid = (self.left + self.right) / 2;
-		let y_mid = (self.top + self.bottom) / 2;
-		let ul_pt = ((self.left+x_mid) as f32 / 2f32, (self.top+y_mid) as f32 / 2f32);
-		let ur_pt = ((self.right+x_mid) as f32 / 2f32, (self.top+y_mid) as f32 / 2f32);
-		let dl_pt = ((self.left+x_mid) as f32 / 2f32, (self.bottom+y_mid) as f32 / 2f32);
-		let dr_pt = ((self.right+x_mid) as f32 / 2f32, (self.bottom+y_mid) as f32 / 2f32);
-		let mut result = vec![];
+		let x_mid = (self.left + self.right) / 2f32;
+		let y_mid = (self.top + self.bottom) / 2f32;
+		let ul_pt = ((self.left+x_mid) / 2f32, (self.top+y_mid) / 2f32);
+		let ur_pt = ((self.right+x_mid) / 2f32, (self.top+y_mid) / 2f32);
+		let dl_pt = ((self.left+x_mid) / 2f32, (self.bottom+y_mid) / 2f32);
+		let dr_pt = ((self.right+x_mid) / 2f32, (self.bottom+y_mid) / 2f32);
 		// UL: 0, UR: 1, DL: 2, DR: 3
-		let (visit_ordering, backup_pt) = match self.orientation {
+		match self.orientation {
 			QuadOrientation::A => {
 				// DL, UL, UR, DR
 				([2, 0, 1, 3], [dl_pt, ul_pt, ur_pt, dr_pt])
@@ -151,59 +181,784 @@ impl Hilbert {
 				// DL, DR, UR, UL
 				([2, 3, 1, 0], [dl_pt, dr_pt, ur_pt, ul_pt])
 			},
+			QuadOrientation::Moore => {
+				// DR, UR, UL, DL
+				([3, 1, 0, 2], [dr_pt, ur_pt, ul_pt, dl_pt])
+			},
+		}
+	}
+}
+
+impl Hilbert {
+	pub fn new(right:u32, top:u32, left:u32, bottom:u32, starting_orientation:Option<QuadOrientation>) -> Self {
+		let orientation = if let Some(o) = starting_orientation {
+			o
+		} else {
+			QuadOrientation::A
 		};
 
-		visit_ordering.iter().zip(backup_pt.iter()).for_each(|(order, back_pt)|{
-			if let Some(leaf) = &self.leaves[*order] {
-				result.append(&mut leaf.rasterize());
-			} else {
-				result.push(*back_pt);
+		Hilbert {
+			nodes: vec![Node::new(right as f32, top as f32, left as f32, bottom as f32, orientation)],
+			max_depth: None,
+			min_cell_size: 1,
+		}
+	}
+
+	/// Never subdivide more than `max_depth` levels below the root.  None means no limit.
+	pub fn set_max_depth(&mut self, max_depth:Option<u32>) {
+		self.max_depth = max_depth;
+	}
+
+	/// Never make cells smaller than `min_cell_size` on a side.  Anything under 1 is treated as 1,
+	/// since smaller cells collapse to zero area and repeat their neighbors' points.
+	pub fn set_min_cell_size(&mut self, min_cell_size:u32) {
+		self.min_cell_size = min_cell_size;
+	}
+
+	pub fn root(&self) -> NodeId {
+		0
+	}
+
+	pub fn node(&self, id:NodeId) -> &Node {
+		&self.nodes[id as usize]
+	}
+
+	/// The child of `node` whose quadrant holds x, y, if that quadrant has been subdivided.
+	pub fn get_leaf(&self, node:NodeId, x:u32, y:u32) -> Option<NodeId> {
+		let node = self.node(node);
+		node.leaves[node.quadrant(x, y)]
+	}
+
+	/// Get the child of `node` in `quadrant`, creating it if needed.
+	fn child_or_insert(&mut self, node:NodeId, quadrant:usize) -> NodeId {
+		if let Some(child) = self.nodes[node as usize].leaves[quadrant] {
+			return child;
+		}
+		let child = self.nodes[node as usize].make_child(quadrant);
+		let id = self.nodes.len() as NodeId;
+		self.nodes.push(child);
+		self.nodes[node as usize].leaves[quadrant] = Some(id);
+		id
+	}
+
+	/// Make sure the cell containing x, y is subdivided `depth` levels below the root.
+	/// Only the cells along the way are created.  Any existing subtrees are left intact.
+	/// Stops early at the max depth or min cell size, if set.
+	pub fn subdivide_leaf(&mut self, x:u32, y:u32, depth:u32) {
+		let depth = depth.min(self.max_depth.unwrap_or(u32::MAX));
+		let mut current = self.root();
+		for _ in 0..depth {
+			let node = self.node(current);
+			if !node.can_split(self.min_cell_size) {
+				return;
+			}
+			let quadrant = node.quadrant(x, y);
+			current = self.child_or_insert(current, quadrant);
+		}
+	}
+
+	/// Build the tree top-down: starting from the root, split every cell for which `should_split(cell, depth)` is true
+	/// and then consider its children in turn.  The root is at depth 0.  Respects the max depth and min cell size.
+	pub fn subdivide_while<F:Fn(&Node, u32) -> bool>(&mut self, should_split:F) {
+		self.subdivide_from(self.root(), 0, &should_split);
+	}
+
+	/// `subdivide_while` for the subtree under `start`, where `start` is at depth `start_depth`.
+	fn subdivide_from<F:Fn(&Node, u32) -> bool>(&mut self, start:NodeId, start_depth:u32, should_split:&F) {
+		let max_depth = self.max_depth.unwrap_or(u32::MAX);
+		let mut stack = vec![(start, start_depth)];
+		while let Some((id, depth)) = stack.pop() {
+			let node = self.node(id);
+			if depth >= max_depth || !node.can_split(self.min_cell_size) || !should_split(node, depth) {
+				continue;
+			}
+			for quadrant in 0..4 {
+				let child = self.child_or_insert(id, quadrant);
+				stack.push((child, depth + 1));
+			}
+		}
+	}
+
+	/// Split cells until no cell is more than one level shallower than any cell beside it.
+	/// This softens the seams where a dark region meets a light one.  Cells that the max depth or min cell size
+	/// won't allow to split are left as they are.
+	pub fn balance(&mut self) {
+		loop {
+			let mut changed = false;
+			for (node, quadrant, depth) in self.unsplit_quadrants() {
+				let (left, top, right, bottom) = self.node(node).quadrant_bounds(quadrant);
+				// Sample just past the middle of each side.  A bigger neighbor always covers that whole side.
+				let nudge = (right - left).min(bottom - top) * 1e-3f32;
+				let mid_x = (left + right) / 2f32;
+				let mid_y = (top + bottom) / 2f32;
+				let probes = [(left - nudge, mid_y), (right + nudge, mid_y), (mid_x, top - nudge), (mid_x, bottom + nudge)];
+				for &(x, y) in &probes {
+					if let Some((neighbor, neighbor_quadrant, neighbor_depth)) = self.unsplit_quadrant_at(x, y) {
+						if neighbor_depth + 1 < depth && self.can_split_quadrant(neighbor, neighbor_depth) {
+							self.child_or_insert(neighbor, neighbor_quadrant);
+							changed = true;
+						}
+					}
+				}
 			}
-		});
+			if !changed {
+				return;
+			}
+		}
+	}
 
+	/// Every quadrant without a child, as (parent, quadrant, depth of the quadrant).
+	fn unsplit_quadrants(&self) -> Vec<(NodeId, usize, u32)> {
+		let mut result = vec![];
+		let mut stack = vec![(self.root(), 0u32)];
+		while let Some((id, depth)) = stack.pop() {
+			for (quadrant, leaf) in self.node(id).leaves.iter().enumerate() {
+				match leaf {
+					Some(leaf) => stack.push((*leaf, depth + 1)),
+					None => result.push((id, quadrant, depth + 1)),
+				}
+			}
+		}
 		result
 	}
+
+	/// The quadrant without a child that covers the point x, y, as (parent, quadrant, depth of the quadrant).
+	fn unsplit_quadrant_at(&self, x:f32, y:f32) -> Option<(NodeId, usize, u32)> {
+		let (left, top, right, bottom) = self.node(self.root()).bounds();
+		if x < left || x > right || y < top || y > bottom {
+			return None;
+		}
+		let mut current = self.root();
+		let mut depth = 0;
+		loop {
+			let quadrant = self.node(current).quadrant_of_point(x, y);
+			depth += 1;
+			match self.node(current).leaves[quadrant] {
+				Some(leaf) => current = leaf,
+				None => return Some((current, quadrant, depth)),
+			}
+		}
+	}
+
+	/// Whether the limits allow turning the given quadrant into a node.
+	fn can_split_quadrant(&self, node:NodeId, depth:u32) -> bool {
+		// Same rules as `subdivide_leaf`: the new node's depth is capped and its parent has to be big enough to split.
+		depth <= self.max_depth.unwrap_or(u32::MAX) && self.node(node).can_split(self.min_cell_size)
+	}
+
+	/// The same as `subdivide_while`, but the subtrees below the top few levels are built in parallel.
+	pub fn par_subdivide_while<F:Fn(&Node, u32) -> bool + Sync>(&mut self, should_split:F) {
+		// Split the top levels as usual and collect the cells where that stopped.
+		let max_depth = self.max_depth.unwrap_or(u32::MAX);
+		let mut frontier = vec![];
+		let mut stack = vec![(self.root(), 0u32)];
+		while let Some((id, depth)) = stack.pop() {
+			if depth == PARALLEL_DEPTH {
+				frontier.push(id);
+				continue;
+			}
+			let node = self.node(id);
+			if depth >= max_depth || !node.can_split(self.min_cell_size) || !should_split(node, depth) {
+				continue;
+			}
+			for quadrant in 0..4 {
+				let child = self.child_or_insert(id, quadrant);
+				stack.push((child, depth + 1));
+			}
+		}
+
+		// Each frontier cell gets a private arena to grow in, which is grafted back onto the main tree when done.
+		let subtrees:Vec<Hilbert> = frontier.par_iter().map(|&id| {
+			let mut subtree = self.extract(id);
+			subtree.subdivide_from(subtree.root(), PARALLEL_DEPTH, &should_split);
+			subtree
+		}).collect();
+		let had_children = frontier.iter().any(|&id| self.node(id).leaves.iter().any(|l| l.is_some()));
+		for (id, subtree) in frontier.into_iter().zip(subtrees) {
+			self.graft(id, subtree);
+		}
+		if had_children {
+			// The grafted copies replaced some existing subtrees.  Drop the originals.
+			*self = self.extract(self.root());
+		}
+	}
+
+	/// Copy the subtree under `id` into a tree of its own.  The limits come along too.
+	fn extract(&self, id:NodeId) -> Hilbert {
+		let mut nodes = vec![];
+		let mut stack = vec![(id, None)];
+		while let Some((old_id, parent)) = stack.pop() {
+			let new_id = nodes.len() as NodeId;
+			let node = self.node(old_id);
+			nodes.push(Node { leaves: [None; 4], ..*node });
+			if let Some((parent, quadrant)) = parent {
+				let parent:&mut Node = &mut nodes[parent as usize];
+				parent.leaves[quadrant] = Some(new_id);
+			}
+			for (quadrant, leaf) in node.leaves.iter().enumerate() {
+				if let Some(leaf) = leaf {
+					stack.push((*leaf, Some((new_id, quadrant))));
+				}
+			}
+		}
+		Hilbert { nodes, max_depth: self.max_depth, min_cell_size: self.min_cell_size }
+	}
+
+	/// Replace the node `id` with the root of `subtree`, appending the rest of `subtree` to this arena.
+	fn graft(&mut self, id:NodeId, subtree:Hilbert) {
+		// Node 0 of the subtree becomes `id` and node k becomes offset + k.  The root is never anyone's child.
+		let offset = self.nodes.len() as NodeId - 1;
+		let mut nodes = subtree.nodes.into_iter();
+		let mut root = nodes.next().unwrap();
+		for leaf in root.leaves.iter_mut().flatten() {
+			*leaf += offset;
+		}
+		self.nodes[id as usize] = root;
+		self.nodes.extend(nodes.map(|mut node| {
+			for leaf in node.leaves.iter_mut().flatten() {
+				*leaf += offset;
+			}
+			node
+		}));
+	}
+
+	/// Fill in any missing children of the root cell.  Existing children are kept.
+	pub fn subdivide(&mut self) {
+		if self.max_depth == Some(0) || !self.node(self.root()).can_split(self.min_cell_size) {
+			return;
+		}
+		for quadrant in 0..4 {
+			self.child_or_insert(self.root(), quadrant);
+		}
+	}
+
+	pub fn rasterize(&self) -> Vec<(f32, f32)> {
+		self.iter_points().collect()
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.nodes.len()
+	}
+
+	/// Number of nodes at each depth, starting with the root at depth 0.
+	pub fn depth_histogram(&self) -> Vec<usize> {
+		let mut histogram = vec![];
+		let mut level = vec![self.root()];
+		while !level.is_empty() {
+			histogram.push(level.len());
+			level = level.iter().flat_map(|&id| self.node(id).leaves.iter().flatten().copied()).collect();
+		}
+		histogram
+	}
+
+	/// Depth of the deepest node.  A lone root has depth 0.
+	pub fn tree_depth(&self) -> usize {
+		self.depth_histogram().len() - 1
+	}
+
+	/// Number of points `rasterize` will produce: one for every quadrant that isn't subdivided.
+	pub fn point_count(&self) -> usize {
+		self.nodes.iter().map(|n| n.leaves.iter().filter(|l| l.is_none()).count()).sum()
+	}
+
+	/// Length of the rasterized curve in canvas units, measured without materializing it.
+	pub fn path_length(&self) -> f32 {
+		let mut points = self.iter_points();
+		let mut length = 0f32;
+		if let Some(mut previous) = points.next() {
+			for p in points {
+				let dx = p.0 - previous.0;
+				let dy = p.1 - previous.1;
+				length += (dx*dx + dy*dy).sqrt();
+				previous = p;
+			}
+		}
+		length
+	}
+
+	/// Write the whole tree out so it can be reloaded with `load` instead of being rebuilt.
+	/// The format is a magic number and version followed by the limits and a flat dump of the arena, all little-endian.
+	pub fn save<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		out.write_all(TREE_MAGIC)?;
+		out.write_all(&[TREE_VERSION])?;
+		out.write_all(&self.max_depth.unwrap_or(u32::MAX).to_le_bytes())?;
+		out.write_all(&self.min_cell_size.to_le_bytes())?;
+		out.write_all(&(self.nodes.len() as u32).to_le_bytes())?;
+		for node in &self.nodes {
+			out.write_all(&[node.orientation as u8])?;
+			for leaf in &node.leaves {
+				out.write_all(&leaf.unwrap_or(NodeId::MAX).to_le_bytes())?;
+			}
+			for bound in &[node.left, node.right, node.top, node.bottom] {
+				out.write_all(&bound.to_le_bytes())?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Read back a tree written by `save`.
+	pub fn load<R:Read>(input:&mut R) -> io::Result<Self> {
+		let invalid = |msg:&str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+		let mut magic = [0u8; 4];
+		input.read_exact(&mut magic)?;
+		if &magic != TREE_MAGIC {
+			return Err(invalid("not a saved Hilbert tree"));
+		}
+		if read_u8(input)? != TREE_VERSION {
+			return Err(invalid("unsupported Hilbert tree version"));
+		}
+		let max_depth = match read_u32(input)? {
+			u32::MAX => None,
+			d => Some(d),
+		};
+		let min_cell_size = read_u32(input)?;
+		let count = read_u32(input)?;
+		if count == 0 {
+			return Err(invalid("Hilbert tree has no root"));
+		}
+
+		// Children always come after their parents, and each belongs to only one, so a tree that points back up or
+		// shares a branch can't make the walks along it go round forever.
+		let mut nodes = vec![];
+		let mut has_parent = vec![false; count as usize];
+		for id in 0..count {
+			let orientation = match read_u8(input)? {
+				0 => QuadOrientation::A,
+				1 => QuadOrientation::B,
+				2 => QuadOrientation::C,
+				3 => QuadOrientation::D,
+				4 => QuadOrientation::Moore,
+				_ => return Err(invalid("bad quadrant orientation")),
+			};
+			let mut leaves = [None; 4];
+			for leaf in leaves.iter_mut() {
+				*leaf = match read_u32(input)? {
+					NodeId::MAX => None,
+					child if child >= count => return Err(invalid("child index out of range")),
+					child if child <= id || has_parent[child as usize] => return Err(invalid("child index makes a loop or a shared branch")),
+					child => {
+						has_parent[child as usize] = true;
+						Some(child)
+					},
+				};
+			}
+			let left = f32::from_bits(read_u32(input)?);
+			let right = f32::from_bits(read_u32(input)?);
+			let top = f32::from_bits(read_u32(input)?);
+			let bottom = f32::from_bits(read_u32(input)?);
+			nodes.push(Node { orientation, leaves, left, right, top, bottom });
+		}
+
+		Ok(Hilbert { nodes, max_depth, min_cell_size })
+	}
+
+	/// Walk the curve in visit order without building any intermediate lists.
+	pub fn iter_points(&self) -> Points<'_> {
+		Points { cells: self.iter_cells() }
+	}
+
+	fn iter_points_from(&self, id:NodeId, depth:u32) -> Points<'_> {
+		Points { cells: self.iter_cells_from(id, depth) }
+	}
+
+	/// Like `iter_points`, but also says which cell each point stands for.
+	pub fn iter_cells(&self) -> Cells<'_> {
+		self.iter_cells_from(self.root(), 0)
+	}
+
+	fn iter_cells_from(&self, id:NodeId, depth:u32) -> Cells<'_> {
+		Cells {
+			tree: self,
+			stack: vec![Frame::new(id, self.node(id), depth)],
+		}
+	}
+
+	/// Rasterize, leaving out every cell for which `keep` is false.  The curve is broken wherever cells are left out,
+	/// so this gives back a list of separate polylines in visit order.
+	pub fn rasterize_where<F:Fn(&Cell) -> bool>(&self, keep:F) -> Vec<Vec<(f32, f32)>> {
+		self.rasterize_with_depth(keep).into_iter().map(|path| path.into_iter().map(|(point, _)| point).collect()).collect()
+	}
+
+	/// `rasterize_where` with every point tagged with the depth of the cell it stands for.
+	pub fn rasterize_with_depth<F:Fn(&Cell) -> bool>(&self, keep:F) -> Vec<Vec<((f32, f32), u32)>> {
+		let mut paths = vec![];
+		let mut current = vec![];
+		for cell in self.iter_cells() {
+			if keep(&cell) {
+				current.push((cell.point, cell.depth));
+			} else if !current.is_empty() {
+				paths.push(std::mem::take(&mut current));
+			}
+		}
+		if !current.is_empty() {
+			paths.push(current);
+		}
+		paths
+	}
+
+	/// The same as `rasterize`, but the subtrees below the top few levels are walked in parallel.
+	pub fn par_rasterize(&self) -> Vec<(f32, f32)> {
+		let mut pieces = vec![];
+		self.collect_pieces(self.root(), 0, &mut pieces);
+		let chunks:Vec<Vec<(f32, f32)>> = pieces.par_iter().map(|piece| match piece {
+			Piece::Point(p) => vec![*p],
+			Piece::Subtree(id) => self.iter_points_from(*id, PARALLEL_DEPTH).collect(),
+		}).collect();
+		chunks.concat()
+	}
+
+	/// Walk the top levels in visit order, noting each point and each subtree at `PARALLEL_DEPTH` along the way.
+	fn collect_pieces(&self, id:NodeId, depth:u32, pieces:&mut Vec<Piece>) {
+		let node = self.node(id);
+		let (visit_ordering, backup_pt) = node.visit_order();
+		for (order, back_pt) in visit_ordering.iter().zip(backup_pt.iter()) {
+			match node.leaves[*order] {
+				Some(leaf) if depth + 1 >= PARALLEL_DEPTH => pieces.push(Piece::Subtree(leaf)),
+				Some(leaf) => self.collect_pieces(leaf, depth + 1, pieces),
+				None => pieces.push(Piece::Point(*back_pt)),
+			}
+		}
+	}
 }
 
-/*
-//convert (x,y) to d
-int xy2d (int n, int x, int y) {
-    int rx, ry, s, d=0;
-    for (s=n/2; s>0; s/=2) {
-        rx = (x & s) > 0;
-        ry = (y & s) > 0;
-        d += s * s * ((3 * rx) ^ ry);
-        rot(n, &x, &y, rx, ry);
-    }
-    return d;
-}
-
-//convert d to (x,y)
-void d2xy(int n, int d, int *x, int *y) {
-    int rx, ry, s, t=d;
-    *x = *y = 0;
-    for (s=1; s<n; s*=2) {
-        rx = 1 & (t/2);
-        ry = 1 & (t ^ rx);
-        rot(s, x, y, rx, ry);
-        *x += s * rx;
-        *y += s * ry;
-        t /= 4;
-    }
-}
-
-//rotate/flip a quadrant appropriately
-void rot(int n, int *x, int *y, int rx, int ry) {
-    if (ry == 0) {
-        if (rx == 1) {
-            *x = n-1 - *x;
-            *y = n-1 - *y;
-        }
-
-        //Swap x and y
-        int t  = *x;
-        *x = *y;
-        *y = t;
-    }
-}
- */
\ No newline at end of file
+/// How many levels to split before handing subtrees off to separate threads.  Depth 3 gives up to 64 subtrees.
+const PARALLEL_DEPTH:u32 = 3;
+
+enum Piece {
+	Point((f32, f32)),
+	Subtree(NodeId),
+}
+
+const TREE_MAGIC:&[u8; 4] = b"HLBT";
+const TREE_VERSION:u8 = 1;
+
+fn read_u8<R:Read>(input:&mut R) -> io::Result<u8> {
+	let mut buf = [0u8; 1];
+	input.read_exact(&mut buf)?;
+	Ok(buf[0])
+}
+
+fn read_u32<R:Read>(input:&mut R) -> io::Result<u32> {
+	let mut buf = [0u8; 4];
+	input.read_exact(&mut buf)?;
+	Ok(u32::from_le_bytes(buf))
+}
+
+struct Frame {
+	node: NodeId,
+	depth: u32,
+	step: usize,
+	visit_ordering: [usize; 4],
+	backup_pt: [(f32, f32); 4],
+}
+
+impl Frame {
+	fn new(id:NodeId, node:&Node, depth:u32) -> Self {
+		let (visit_ordering, backup_pt) = node.visit_order();
+		Frame { node: id, depth, step: 0, visit_ordering, backup_pt }
+	}
+}
+
+/// One stop along the curve: an unsplit quadrant and the point drawn for it.
+#[derive(Copy, Clone, Debug)]
+pub struct Cell {
+	pub point: (f32, f32),
+	/// (left, top, right, bottom)
+	pub bounds: (f32, f32, f32, f32),
+	/// How deep the quadrant is.  The root's quadrants are at depth 1.
+	pub depth: u32,
+}
+
+/// Iterator over the cells of a `Hilbert` curve in visit order.  See `Hilbert::iter_cells`.
+pub struct Cells<'a> {
+	tree: &'a Hilbert,
+	stack: Vec<Frame>,
+}
+
+impl Iterator for Cells<'_> {
+	type Item = Cell;
+
+	fn next(&mut self) -> Option<Cell> {
+		loop {
+			let frame = self.stack.last_mut()?;
+			if frame.step == 4 {
+				self.stack.pop();
+				continue;
+			}
+			let quadrant = frame.visit_ordering[frame.step];
+			let backup = frame.backup_pt[frame.step];
+			frame.step += 1;
+			let node = self.tree.node(frame.node);
+			if let Some(leaf) = node.leaves[quadrant] {
+				let depth = frame.depth + 1;
+				self.stack.push(Frame::new(leaf, self.tree.node(leaf), depth));
+			} else {
+				return Some(Cell { point: backup, bounds: node.quadrant_bounds(quadrant), depth: frame.depth + 1 });
+			}
+		}
+	}
+}
+
+/// Iterator over the points of a `Hilbert` curve.  See `Hilbert::iter_points`.
+pub struct Points<'a> {
+	cells: Cells<'a>,
+}
+
+impl Iterator for Points<'_> {
+	type Item = (f32, f32);
+
+	fn next(&mut self) -> Option<(f32, f32)> {
+		self.cells.next().map(|cell| cell.point)
+	}
+}
+
+/// Convert (x, y) on an n-by-n grid to its distance along a Hilbert curve covering that grid.
+/// `n` must be a power of two and x, y must be less than n.
+pub fn xy_to_index(n:u32, x:u32, y:u32) -> u64 {
+	let mut x = x;
+	let mut y = y;
+	let mut d = 0u64;
+	let mut s = n / 2;
+	while s > 0 {
+		let rx = ((x & s) > 0) as u32;
+		let ry = ((y & s) > 0) as u32;
+		d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+		rotate_quadrant(n, &mut x, &mut y, rx, ry);
+		s /= 2;
+	}
+	d
+}
+
+/// Convert a distance along the Hilbert curve back to (x, y) on an n-by-n grid.
+/// The inverse of `xy_to_index`.
+pub fn index_to_xy(n:u32, d:u64) -> (u32, u32) {
+	let mut t = d;
+	let mut x = 0u32;
+	let mut y = 0u32;
+	let mut s = 1u32;
+	while s < n {
+		let rx = (1 & (t / 2)) as u32;
+		let ry = (1 & (t ^ rx as u64)) as u32;
+		rotate_quadrant(s, &mut x, &mut y, rx, ry);
+		x += s * rx;
+		y += s * ry;
+		t /= 4;
+		s *= 2;
+	}
+	(x, y)
+}
+
+/// Rotate/flip a quadrant appropriately.
+fn rotate_quadrant(n:u32, x:&mut u32, y:&mut u32, rx:u32, ry:u32) {
+	if ry == 0 {
+		if rx == 1 {
+			*x = n - 1 - *x;
+			*y = n - 1 - *y;
+		}
+		std::mem::swap(x, y);
+	}
+}
+
+/// Gives the indices of `points` in the order a Hilbert curve over their bounding box would visit them.
+/// The result can be used anywhere a tour is expected, like `hamiltonian::tour_length`.
+/// This is nowhere near optimal, but it's near-instant and never produces very long jumps.
+pub fn hilbert_order(points:&[Point]) -> Vec<usize> {
+	// Resolution of the curve.  Points that land in the same cell keep their input order.
+	let n = 1u32 << 16;
+	let mut order:Vec<usize> = (0..points.len()).collect();
+	if points.is_empty() {
+		return order;
+	}
+
+	let mut min = points[0];
+	let mut max = points[0];
+	for p in points {
+		min.x = min.x.min(p.x);
+		min.y = min.y.min(p.y);
+		max.x = max.x.max(p.x);
+		max.y = max.y.max(p.y);
+	}
+	// Use the same extent on both axes so the curve isn't stretched on non-square inputs.
+	let extent = (max.x - min.x).max(max.y - min.y);
+	if extent <= 0f32 {
+		return order;
+	}
+
+	let scale = (n - 1) as f32 / extent;
+	order.sort_by_cached_key(|&idx| {
+		let p = points[idx];
+		xy_to_index(n, ((p.x - min.x) * scale) as u32, ((p.y - min.y) * scale) as u32)
+	});
+	order
+}
+
+/// Return a copy of `points` sorted by Hilbert index.  See `hilbert_order`.
+pub fn hilbert_sort(points:&[Point]) -> Vec<Point> {
+	hilbert_order(points).into_iter().map(|idx| points[idx]).collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_index_round_trip() {
+		let n = 16;
+		for d in 0..(n*n) as u64 {
+			let (x, y) = index_to_xy(n, d);
+			assert_eq!(xy_to_index(n, x, y), d);
+		}
+	}
+
+	#[test]
+	fn test_subdivide_leaf_keeps_siblings() {
+		let mut h = Hilbert::new(16, 0, 0, 16, None);
+		h.subdivide_leaf(0, 0, 2);
+		let before = h.rasterize().len();
+		h.subdivide_leaf(15, 15, 1);
+		// The far corner gains three points (one becomes four) and the first subtree stays as it was.
+		assert_eq!(h.rasterize().len(), before + 3);
+		let first = h.get_leaf(h.root(), 0, 0).unwrap();
+		assert!(h.get_leaf(first, 0, 0).is_some());
+	}
+
+	#[test]
+	fn test_subdivision_stops_at_one_pixel() {
+		let mut h = Hilbert::new(8, 0, 0, 8, None);
+		h.subdivide_leaf(0, 0, 100);
+		// 8 -> 4 -> 2 -> 1, so three levels each adding three points.
+		assert_eq!(h.rasterize().len(), 4 + 3*3);
+		h.set_max_depth(Some(1));
+		h.subdivide_leaf(7, 7, 100);
+		assert_eq!(h.rasterize().len(), 4 + 3*3 + 3);
+	}
+
+	#[test]
+	fn test_subdivide_while() {
+		let mut h = Hilbert::new(16, 0, 0, 16, None);
+		h.subdivide_while(|cell, depth| depth < 2 || cell.bounds() == (0f32, 0f32, 4f32, 4f32));
+		assert_eq!(h.depth_histogram(), vec![1, 4, 16, 4]);
+	}
+
+	#[test]
+	fn test_parallel_matches_sequential() {
+		let should_split = |cell:&Node, depth:u32| {
+			let (left, top, _, _) = cell.bounds();
+			(depth as f32) < 7f32 - (left + top) / 20f32
+		};
+		let mut sequential = Hilbert::new(100, 0, 0, 60, None);
+		sequential.subdivide_leaf(90, 50, 5);
+		let mut parallel = Hilbert::new(100, 0, 0, 60, None);
+		parallel.subdivide_leaf(90, 50, 5);
+		sequential.subdivide_while(should_split);
+		parallel.par_subdivide_while(should_split);
+		assert_eq!(parallel.node_count(), sequential.node_count());
+		assert_eq!(parallel.par_rasterize(), sequential.rasterize());
+	}
+
+	#[test]
+	fn test_balance() {
+		let mut h = Hilbert::new(64, 0, 0, 64, None);
+		h.subdivide_leaf(0, 0, 6);
+		h.balance();
+		for (node, quadrant, depth) in h.unsplit_quadrants() {
+			let (left, top, right, bottom) = h.node(node).quadrant_bounds(quadrant);
+			for &(x, y) in &[(right + 0.01f32, (top + bottom) / 2f32), ((left + right) / 2f32, bottom + 0.01f32)] {
+				if let Some((_, _, neighbor_depth)) = h.unsplit_quadrant_at(x, y) {
+					assert!((neighbor_depth as i32 - depth as i32).abs() <= 1);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_rasterize_where() {
+		let mut h = Hilbert::new(16, 0, 0, 16, None);
+		h.subdivide();
+		let cells:Vec<Cell> = h.iter_cells().collect();
+		assert!(cells.iter().all(|c| c.depth == 2));
+		// Orientation A runs DL, UL, UR, DR, so dropping the upper right leaves two strokes.
+		let paths = h.rasterize_where(|c| !(c.bounds.0 >= 8f32 && c.bounds.1 < 8f32));
+		assert_eq!(paths.len(), 2);
+		assert_eq!(paths[0].len() + paths[1].len(), 12);
+		// Splitting one quadrant again puts its four points a level deeper.
+		h.subdivide_leaf(1, 1, 2);
+		let tagged = h.rasterize_with_depth(|_| true);
+		assert_eq!(tagged.len(), 1);
+		assert_eq!(tagged[0].iter().filter(|&&(_, depth)| depth == 3).count(), 4);
+	}
+
+	#[test]
+	fn test_stats() {
+		let mut h = Hilbert::new(16, 0, 0, 16, None);
+		h.subdivide();
+		h.subdivide_leaf(0, 0, 2);
+		assert_eq!(h.node_count(), 6);
+		assert_eq!(h.depth_histogram(), vec![1, 4, 1]);
+		assert_eq!(h.tree_depth(), 2);
+		assert_eq!(h.point_count(), h.rasterize().len());
+		assert!(h.path_length() > 0f32);
+	}
+
+	#[test]
+	fn test_save_and_load() {
+		let mut h = Hilbert::new(37, 0, 0, 21, Some(QuadOrientation::C));
+		h.set_max_depth(Some(5));
+		h.subdivide_leaf(3, 4, 4);
+		h.subdivide_leaf(30, 20, 2);
+		let mut buffer = vec![];
+		h.save(&mut buffer).unwrap();
+		let loaded = Hilbert::load(&mut buffer.as_slice()).unwrap();
+		assert_eq!(loaded.rasterize(), h.rasterize());
+		assert!(Hilbert::load(&mut &buffer[..buffer.len()-1]).is_err());
+		// Point a child of the root's first child back at the root, after the 17 byte header and 33 bytes a node.
+		let mut cyclic = buffer.clone();
+		let leaf = 17 + 33 + 1;
+		assert_ne!(&cyclic[leaf..leaf + 4], &NodeId::MAX.to_le_bytes());
+		cyclic[leaf..leaf + 4].copy_from_slice(&0u32.to_le_bytes());
+		assert_eq!(Hilbert::load(&mut cyclic.as_slice()).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+		// Or have two nodes share one child.
+		let mut shared = buffer.clone();
+		let (first, second) = (&buffer[17 + 1..17 + 5], 17 + 5);
+		let first = first.to_vec();
+		shared[second..second + 4].copy_from_slice(&first);
+		assert!(Hilbert::load(&mut shared.as_slice()).is_err());
+	}
+
+	#[test]
+	fn test_iter_points_visits_every_cell() {
+		let mut h = Hilbert::new(16, 0, 0, 16, None);
+		h.subdivide();
+		let points:Vec<(f32, f32)> = h.iter_points().collect();
+		assert_eq!(points.len(), 16);
+		// Consecutive cells on a Hilbert curve are always neighbors.
+		for pair in points.windows(2) {
+			let dx = (pair[0].0 - pair[1].0).abs();
+			let dy = (pair[0].1 - pair[1].1).abs();
+			assert_eq!(dx.min(dy), 0f32);
+			assert!(dx.max(dy) <= 4.5f32);
+		}
+	}
+
+	#[test]
+	fn test_moore_curve_is_closed() {
+		let mut h = Hilbert::new(16, 0, 0, 16, Some(QuadOrientation::Moore));
+		h.subdivide();
+		h.subdivide_leaf(0, 0, 2);
+		h.subdivide_leaf(15, 15, 3);
+		let points = h.rasterize();
+		let first = points.first().unwrap();
+		let last = points.last().unwrap();
+		assert!((first.0 - last.0).abs() + (first.1 - last.1).abs() <= 4.5f32);
+	}
+
+	#[test]
+	fn test_hilbert_sort_is_permutation() {
+		let pts = vec![Point::new(1f32, 1f32), Point::new(0f32, 0f32), Point::new(1f32, 0f32), Point::new(0f32, 1f32)];
+		let mut order = hilbert_order(&pts);
+		assert_eq!(hilbert_sort(&pts)[0], Point::new(0f32, 0f32));
+		order.sort();
+		assert_eq!(order, vec![0, 1, 2, 3]);
+	}
+}
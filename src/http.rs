@@ -0,0 +1,88 @@
+use std::io::{self, BufRead, Write};
+
+/// Just enough of an HTTP/1.1 request for the render server: the request line, headers and a body sized by
+/// `Content-Length`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+	pub method: String,
+	pub path: String,
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+}
+
+impl Request {
+	/// The value of the first header called `name`, whatever its case.
+	pub fn header(&self, name:&str) -> Option<&str> {
+		self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+	}
+}
+
+fn invalid(msg:&str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Read one request, refusing bodies longer than `max_body` bytes.
+pub fn read_request<R:BufRead>(input:&mut R, max_body:usize) -> io::Result<Request> {
+	let mut line = String::new();
+	input.read_line(&mut line)?;
+	let mut words = line.split_whitespace();
+	let (method, path) = match (words.next(), words.next()) {
+		(Some(method), Some(path)) => (method.to_string(), path.to_string()),
+		_ => return Err(invalid("expected a request line")),
+	};
+	let mut headers = vec![];
+	loop {
+		line.clear();
+		if input.read_line(&mut line)? == 0 {
+			return Err(invalid("headers ended early"));
+		}
+		let header = line.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		let idx = header.find(':').ok_or_else(|| invalid("expected name: value"))?;
+		headers.push((header[..idx].trim().to_string(), header[idx+1..].trim().to_string()));
+	}
+	let mut request = Request { method, path, headers, body: vec![] };
+	let length = match request.header("Content-Length") {
+		Some(length) => length.parse::<usize>().map_err(|_| invalid("bad Content-Length"))?,
+		None => 0,
+	};
+	if length > max_body {
+		return Err(invalid("body too large"));
+	}
+	request.body = vec![0u8; length];
+	input.read_exact(&mut request.body)?;
+	Ok(request)
+}
+
+pub fn write_response<W:Write>(out:&mut W, status:u16, content_type:&str, body:&[u8]) -> io::Result<()> {
+	let reason = match status {
+		200 => "OK",
+		400 => "Bad Request",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		_ => "Internal Server Error",
+	};
+	write!(out, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, reason, content_type, body.len())?;
+	out.write_all(body)?;
+	out.flush()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_request_and_response() {
+		let raw = b"POST /render HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\nX-Parameters: {\"points\": 10}\r\n\r\nhello";
+		let request = read_request(&mut &raw[..], 1024).unwrap();
+		assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/render"));
+		assert_eq!(request.header("x-parameters"), Some("{\"points\": 10}"));
+		assert_eq!(request.body, b"hello");
+		assert!(read_request(&mut &raw[..], 4).is_err());
+		let mut out = vec![];
+		write_response(&mut out, 404, "text/plain", b"nope").unwrap();
+		assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope");
+	}
+}
@@ -0,0 +1,116 @@
+use crate::point::Point;
+use crate::polyline::Polyline;
+
+/// True if segments ab and cd cross at a point inside both of them.  Touching at an end or running along each other
+/// doesn't count.
+pub fn segments_cross(a:Point, b:Point, c:Point, d:Point) -> bool {
+	// Which side of pq r is on, with 0 for right on the line.  (f32::signum would call that positive.)
+	let side = |p:Point, q:Point, r:Point| {
+		let turn = (q - p).cross(r - p);
+		(turn > 0f32) as i8 - (turn < 0f32) as i8
+	};
+	side(a, b, c) * side(a, b, d) < 0 && side(c, d, a) * side(c, d, b) < 0
+}
+
+/// Where the lines through ab and cd meet.  Only meaningful if they aren't parallel.
+pub fn crossing_point(a:Point, b:Point, c:Point, d:Point) -> Point {
+	let ab = b - a;
+	let cd = d - c;
+	a + ab * ((c - a).cross(cd) / ab.cross(cd))
+}
+
+/// Every pair of segments that cross, found by sweeping left to right and only comparing segments whose x spans
+/// overlap.  Pairs come back as (later in the sweep, earlier in the sweep).
+pub fn crossings(segments:&[(Point, Point)]) -> Vec<(usize, usize)> {
+	let span = |s:usize| {
+		let (a, b) = segments[s];
+		(a.x.min(b.x), a.x.max(b.x))
+	};
+	let mut order:Vec<usize> = (0..segments.len()).collect();
+	order.sort_by(|&s, &t| span(s).0.total_cmp(&span(t).0));
+	let mut active:Vec<usize> = vec![];
+	let mut found = vec![];
+	for s in order {
+		let left = span(s).0;
+		active.retain(|&t| span(t).1 >= left);
+		let (a, b) = segments[s];
+		for &t in &active {
+			let (c, d) = segments[t];
+			if segments_cross(a, b, c, d) {
+				found.push((s, t));
+			}
+		}
+		active.push(s);
+	}
+	found
+}
+
+/// Two segments of a path that cross, by the index of their first points, and where.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Intersection {
+	pub first: usize,
+	pub second: usize,
+	pub at: Point,
+}
+
+/// Every place a path crosses itself, sorted by where along the path it happens.
+pub fn self_intersections(path:&Polyline) -> Vec<Intersection> {
+	let segments:Vec<(Point, Point)> = path.points.windows(2).map(|pair| (pair[0], pair[1])).collect();
+	let mut found:Vec<Intersection> = crossings(&segments).into_iter().map(|(s, t)| {
+		let (first, second) = (s.min(t), s.max(t));
+		let (a, b) = segments[first];
+		let (c, d) = segments[second];
+		Intersection { first, second, at: crossing_point(a, b, c, d) }
+	}).collect();
+	found.sort_by_key(|i| (i.first, i.second));
+	found
+}
+
+/// Undo every self-intersection by drawing the stretch between each crossing pair of segments backwards, the same
+/// way 2-opt uncrosses a tour.  Each fix makes the path shorter, so this always finishes.  The ends stay put.
+/// Returns how many crossings were undone.
+pub fn resolve_intersections(path:&mut Polyline) -> usize {
+	let mut resolved = 0;
+	loop {
+		let found = self_intersections(path);
+		if found.is_empty() {
+			return resolved;
+		}
+		// Reversals can overlap, so only take crossings whose stretches don't, then look again.
+		let mut reversed_up_to = 0;
+		let before = resolved;
+		for crossing in found {
+			let (i, j) = (crossing.first, crossing.second);
+			let p = &path.points;
+			// Rounding can make nearly parallel segments look crossed; only go ahead if it really is shorter.
+			let saved = (p[i+1] - p[i]).length() + (p[j+1] - p[j]).length() - (p[j] - p[i]).length() - (p[j+1] - p[i+1]).length();
+			if i < reversed_up_to || saved <= 0f32 {
+				continue;
+			}
+			path.points[i+1..=j].reverse();
+			reversed_up_to = j + 1;
+			resolved += 1;
+		}
+		if resolved == before {
+			return resolved;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_find_and_resolve() {
+		// A bow tie crosses itself once in the middle.
+		let mut path = Polyline::from(vec![(0f32, 0f32), (2f32, 2f32), (2f32, 0f32), (0f32, 2f32)]);
+		let found = self_intersections(&path);
+		assert_eq!(found, vec![Intersection { first: 0, second: 2, at: Point::new(1f32, 1f32) }]);
+		assert_eq!(resolve_intersections(&mut path), 1);
+		assert!(self_intersections(&path).is_empty());
+		assert_eq!((path.points[0], path.points[3]), (Point::new(0f32, 0f32), Point::new(0f32, 2f32)));
+		// Meeting end to end isn't crossing.
+		assert!(self_intersections(&Polyline::from(vec![(0f32, 0f32), (1f32, 0f32), (1f32, 1f32), (0f32, 0f32)])).is_empty());
+	}
+}
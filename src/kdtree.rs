@@ -0,0 +1,198 @@
+use crate::point::Point;
+
+/// A static two-dimensional k-d tree over a fixed set of points.
+/// Points can be removed after construction so that queries skip them, which is what growing a spanning
+/// tree or a greedy tour needs: "give me the closest point I haven't used yet."
+pub struct KdTree {
+	points: Vec<Point>,
+	// Indices into `points` laid out as an implicit tree: the node for the range [lo, hi) is at (lo+hi)/2.
+	nodes: Vec<usize>,
+	// For each slot in `nodes`, how many live points are in the subtree rooted there.
+	alive: Vec<usize>,
+	// Slot in `nodes` for each point.
+	slot: Vec<usize>,
+	removed: Vec<bool>,
+}
+
+impl KdTree {
+	pub fn new(points:&[Point]) -> Self {
+		let mut nodes:Vec<usize> = (0..points.len()).collect();
+		build(points, &mut nodes, 0);
+		let mut slot = vec![0; points.len()];
+		for (s, &idx) in nodes.iter().enumerate() {
+			slot[idx] = s;
+		}
+		let mut alive = vec![0; points.len()];
+		count_alive(&mut alive, 0, points.len());
+
+		KdTree {
+			points: points.to_vec(),
+			nodes,
+			alive,
+			slot,
+			removed: vec![false; points.len()],
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.alive.get(self.points.len() / 2).copied().unwrap_or(0)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn is_removed(&self, idx:usize) -> bool {
+		self.removed[idx]
+	}
+
+	/// Exclude the point with index `idx` from all future queries.
+	pub fn remove(&mut self, idx:usize) {
+		if self.removed[idx] {
+			return;
+		}
+		self.removed[idx] = true;
+		let target = self.slot[idx];
+		let mut lo = 0;
+		let mut hi = self.points.len();
+		loop {
+			let mid = (lo + hi) / 2;
+			self.alive[mid] -= 1;
+			if target == mid {
+				break;
+			} else if target < mid {
+				hi = mid;
+			} else {
+				lo = mid + 1;
+			}
+		}
+	}
+
+	/// Find the live point closest to `query`.  Returns its index and the squared distance to it.
+	pub fn nearest(&self, query:Point) -> Option<(usize, f32)> {
+		let mut best = None;
+		self.nearest_in(0, self.points.len(), 0, query, &mut best);
+		best
+	}
+
+	/// Find the `k` live points closest to `query`, nearest first, with their squared distances.
+	pub fn k_nearest(&self, query:Point, k:usize) -> Vec<(usize, f32)> {
+		let mut found = Vec::with_capacity(k + 1);
+		if k > 0 {
+			self.k_nearest_in(0, self.points.len(), 0, query, k, &mut found);
+		}
+		found
+	}
+
+	fn k_nearest_in(&self, lo:usize, hi:usize, depth:usize, query:Point, k:usize, found:&mut Vec<(usize, f32)>) {
+		if lo >= hi {
+			return;
+		}
+		let mid = (lo + hi) / 2;
+		if self.alive[mid] == 0 {
+			return;
+		}
+
+		let idx = self.nodes[mid];
+		let p = self.points[idx];
+		if !self.removed[idx] {
+			let d = distance_squared(p, query);
+			if found.len() < k || d < found[k-1].1 {
+				// `found` stays sorted, so the worst candidate is always last.
+				let at = found.partition_point(|&(_, other)| other <= d);
+				found.insert(at, (idx, d));
+				found.truncate(k);
+			}
+		}
+
+		let diff = if depth.is_multiple_of(2) { query.x - p.x } else { query.y - p.y };
+		let (near, far) = if diff < 0f32 { ((lo, mid), (mid+1, hi)) } else { ((mid+1, hi), (lo, mid)) };
+		self.k_nearest_in(near.0, near.1, depth+1, query, k, found);
+		if found.len() < k || diff*diff < found[k-1].1 {
+			self.k_nearest_in(far.0, far.1, depth+1, query, k, found);
+		}
+	}
+
+	fn nearest_in(&self, lo:usize, hi:usize, depth:usize, query:Point, best:&mut Option<(usize, f32)>) {
+		if lo >= hi {
+			return;
+		}
+		let mid = (lo + hi) / 2;
+		if self.alive[mid] == 0 {
+			return;
+		}
+
+		let idx = self.nodes[mid];
+		let p = self.points[idx];
+		if !self.removed[idx] {
+			let d = distance_squared(p, query);
+			if best.is_none_or(|(_, best_d)| d < best_d) {
+				*best = Some((idx, d));
+			}
+		}
+
+		let diff = if depth.is_multiple_of(2) { query.x - p.x } else { query.y - p.y };
+		let (near, far) = if diff < 0f32 { ((lo, mid), (mid+1, hi)) } else { ((mid+1, hi), (lo, mid)) };
+		self.nearest_in(near.0, near.1, depth+1, query, best);
+		if best.is_none_or(|(_, best_d)| diff*diff < best_d) {
+			self.nearest_in(far.0, far.1, depth+1, query, best);
+		}
+	}
+}
+
+pub fn distance_squared(p:Point, q:Point) -> f32 {
+	let dx = q.x - p.x;
+	let dy = q.y - p.y;
+	dx*dx + dy*dy
+}
+
+fn build(points:&[Point], nodes:&mut [usize], depth:usize) {
+	if nodes.len() <= 1 {
+		return;
+	}
+	let mid = nodes.len() / 2;
+	if depth.is_multiple_of(2) {
+		nodes.select_nth_unstable_by(mid, |&a, &b| points[a].x.total_cmp(&points[b].x));
+	} else {
+		nodes.select_nth_unstable_by(mid, |&a, &b| points[a].y.total_cmp(&points[b].y));
+	}
+	let (left, right) = nodes.split_at_mut(mid);
+	build(points, left, depth+1);
+	build(points, &mut right[1..], depth+1);
+}
+
+fn count_alive(alive:&mut [usize], lo:usize, hi:usize) -> usize {
+	if lo >= hi {
+		return 0;
+	}
+	let mid = (lo + hi) / 2;
+	let count = 1 + count_alive(alive, lo, mid) + count_alive(alive, mid+1, hi);
+	alive[mid] = count;
+	count
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_nearest_matches_brute_force() {
+		let pts:Vec<Point> = (0..200).map(|i| Point::new(((i * 37) % 101) as f32, ((i * 53) % 89) as f32)).collect();
+		let mut tree = KdTree::new(&pts);
+		for i in (0..200).step_by(3) {
+			tree.remove(i);
+		}
+		let query = Point::new(40.5f32, 12.25f32);
+		let expected = (0..200).filter(|i| i % 3 != 0).map(|i| distance_squared(pts[i], query)).fold(f32::MAX, f32::min);
+		assert_eq!(tree.nearest(query).unwrap().1, expected);
+		assert_eq!(tree.len(), 200 - 67);
+	}
+
+	#[test]
+	fn test_k_nearest_sorted() {
+		let pts:Vec<Point> = (0..10).map(|i| Point::new(i as f32, 0f32)).collect();
+		let tree = KdTree::new(&pts);
+		let found:Vec<usize> = tree.k_nearest(Point::new(3.1f32, 0f32), 3).iter().map(|&(idx, _)| idx).collect();
+		assert_eq!(found, vec![3, 4, 2]);
+	}
+}
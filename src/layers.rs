@@ -0,0 +1,121 @@
+use crate::point::Point;
+use crate::polyline::Polyline;
+
+/// Share the drawing out between `pens` pens by how dark it is underneath, lightest pen first.  `tone` gives the
+/// darkness at a point from 0 to 1, and each pen takes an equal band of it.  A path that wanders between bands is cut
+/// where it crosses, each segment going to the pen for the tone at its middle, so the layers still line up when
+/// plotted one over the other.
+pub fn split_by_tone<F:Fn(Point) -> f32>(paths:&[Polyline], pens:usize, tone:F) -> Vec<Vec<Polyline>> {
+	let pens = pens.max(1);
+	let band = |p:Point| ((tone(p) * pens as f32).max(0f32) as usize).min(pens - 1);
+	let mut layers = vec![vec![]; pens];
+	for path in paths.iter().filter(|path| !path.is_empty()) {
+		if path.len() == 1 {
+			layers[band(path.points[0])].push(path.clone());
+			continue;
+		}
+		let mut run = vec![path.points[0]];
+		let mut run_band = band(path.points[0].lerp(path.points[1], 0.5f32));
+		for pair in path.points.windows(2) {
+			let pen = band(pair[0].lerp(pair[1], 0.5f32));
+			if pen != run_band {
+				layers[run_band].push(Polyline::new(std::mem::replace(&mut run, vec![pair[0]])));
+				run_band = pen;
+			}
+			run.push(pair[1]);
+		}
+		layers[run_band].push(Polyline::new(run));
+	}
+	layers
+}
+
+/// Sort a path of points, each tagged with something like the depth of the cell it came from, into one set of paths per
+/// tag, in order of tag.  Each segment goes with the larger of the tags at its ends, so the detail is all in the deeper
+/// layers, and the path is cut wherever that changes, the pieces either side sharing the point.
+pub fn split_by_depth<T:Copy>(paths:&[Vec<(T, u32)>]) -> Vec<(u32, Vec<Vec<T>>)> {
+	let mut layers:Vec<(u32, Vec<Vec<T>>)> = vec![];
+	let mut push = |depth:u32, run:Vec<T>| match layers.binary_search_by_key(&depth, |&(d, _)| d) {
+		Ok(i) => layers[i].1.push(run),
+		Err(i) => layers.insert(i, (depth, vec![run])),
+	};
+	for path in paths.iter().filter(|path| !path.is_empty()) {
+		if path.len() == 1 {
+			push(path[0].1, vec![path[0].0]);
+			continue;
+		}
+		let mut run = vec![path[0].0];
+		let mut run_depth = path[0].1.max(path[1].1);
+		for pair in path.windows(2) {
+			let depth = pair[0].1.max(pair[1].1);
+			if depth != run_depth {
+				push(run_depth, std::mem::replace(&mut run, vec![pair[0].0]));
+				run_depth = depth;
+			}
+			run.push(pair[1].0);
+		}
+		push(run_depth, run);
+	}
+	layers
+}
+
+/// Cut the drawing into `chunks` runs, one after another, each with about the same share of the work, so a long plot
+/// can be done a run at a time.  `cost` is what a segment drawn from one point to the next counts for, like its length
+/// or the time it takes.  Runs can end part way along a path, and the next one picks up from the same point.
+pub fn split_into_chunks<F:Fn(Point, Point) -> f32>(paths:&[Polyline], chunks:usize, cost:F) -> Vec<Vec<Polyline>> {
+	let chunks = chunks.max(1);
+	let total:f32 = paths.iter().flat_map(|path| path.points.windows(2)).map(|pair| cost(pair[0], pair[1])).sum();
+	let mut result = vec![vec![]];
+	let mut done = 0f32;
+	for path in paths {
+		let mut rest = path.clone();
+		let mut i = 1;
+		while i < rest.len() {
+			done += cost(rest.points[i - 1], rest.points[i]);
+			if result.len() < chunks && done >= total * result.len() as f32 / chunks as f32 {
+				let (head, tail) = rest.split_at(i);
+				result.last_mut().unwrap().push(head);
+				result.push(vec![]);
+				rest = tail;
+				i = 1;
+			} else {
+				i += 1;
+			}
+		}
+		if rest.len() > 1 || path.len() == 1 {
+			result.last_mut().unwrap().push(rest);
+		}
+	}
+	// A boundary that fell at the very end leaves nothing after it.
+	if result.len() > 1 && result.last().is_some_and(|chunk| chunk.is_empty()) {
+		result.pop();
+	}
+	result
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_split_by_tone() {
+		// Darker to the right, so a line across gets cut in three.
+		let line = Polyline::new((0..=6).map(|x| Point::new(x as f32, 0f32)).collect());
+		let layers = split_by_tone(&[line], 3, |p| p.x / 6f32);
+		assert_eq!(layers.len(), 3);
+		let xs:Vec<Vec<f32>> = layers.iter().map(|layer| layer[0].points.iter().map(|p| p.x).collect()).collect();
+		assert_eq!(xs, vec![vec![0f32, 1f32, 2f32], vec![2f32, 3f32, 4f32], vec![4f32, 5f32, 6f32]]);
+		assert!(layers.iter().all(|layer| layer.len() == 1));
+		// Two shallow points, three deep ones, and back out: the deep run keeps both its ends.
+		let tagged = vec![vec![(0, 1), (1, 1), (2, 2), (3, 2), (4, 2), (5, 1)]];
+		assert_eq!(split_by_depth(&tagged), vec![(1, vec![vec![0, 1]]), (2, vec![vec![1, 2, 3, 4, 5]])]);
+	}
+
+	#[test]
+	fn test_split_into_chunks() {
+		let line = |x0:i32, x1:i32| Polyline::new((x0..=x1).map(|x| Point::new(x as f32, 0f32)).collect());
+		let chunks = split_into_chunks(&[line(0, 4), line(10, 12)], 3, |a, b| (b - a).length());
+		assert_eq!(chunks.len(), 3);
+		let ends:Vec<Vec<(f32, f32)>> = chunks.iter().map(|chunk| chunk.iter().map(|path| (path.points[0].x, path.points.last().unwrap().x)).collect()).collect();
+		assert_eq!(ends, vec![vec![(0f32, 2f32)], vec![(2f32, 4f32)], vec![(10f32, 12f32)]]);
+	}
+}
@@ -0,0 +1,100 @@
+use crate::error::{Error, Result};
+use crate::paper;
+use crate::point::Point;
+use crate::transform::Transform2D;
+use log::debug;
+
+/// How a drawing goes onto its canvas: moved about by hand, then sized to paper or a print size if there is one.
+#[derive(Copy, Clone, Debug)]
+pub struct LayoutOptions {
+	/// Mirroring to do first, about the middle of the drawing.
+	pub flip: Transform2D,
+	/// Degrees to turn by about the middle of the drawing.
+	pub rotation: f32,
+	pub scale: f32,
+	pub offset: (f32, f32),
+	/// The sheet, wide by high in millimetres.  The drawing goes in the middle of it as big as it'll go.
+	pub paper: Option<(f32, f32)>,
+	pub paper_orientation: paper::Orientation,
+	/// Dots per inch of the drawing's own units, to size it for print.
+	pub dpi: Option<f32>,
+	/// Millimetres wide to print the drawing, for a size on paper without a sheet.
+	pub print_width: Option<f32>,
+	/// Canvas pixels to leave clear all round.
+	pub margin: Option<f32>,
+}
+
+impl Default for LayoutOptions {
+	fn default() -> Self {
+		LayoutOptions {
+			flip: Transform2D::identity(), rotation: 0f32, scale: 1f32, offset: (0f32, 0f32),
+			paper: None, paper_orientation: paper::Orientation::Auto, dpi: None, print_width: None, margin: None,
+		}
+	}
+}
+
+/// Where a drawing ends up: the canvas it's drawn on, and what moves it there.
+#[derive(Copy, Clone, Debug)]
+pub struct Layout {
+	pub transform: Transform2D,
+	pub width: u32,
+	pub height: u32,
+}
+
+/// Lay out a drawing `drawing` wide by high, or say why it can't go on the canvas.
+pub fn lay_out(drawing:(f32, f32), options:&LayoutOptions) -> Result<Layout> {
+	let (width, height) = drawing;
+	// Flip, turn and scale about the middle of the canvas, then shift.
+	let center = Point::new(width / 2f32, height / 2f32);
+	let transform = Transform2D::about(
+		options.flip.then(Transform2D::rotate(options.rotation.to_radians())).then(Transform2D::scale(options.scale, options.scale)),
+		center,
+	).then(Transform2D::translate(options.offset.0, options.offset.1));
+	// On paper, the canvas is the sheet, and the drawing goes in the middle of it as big as it'll go.  Otherwise it's the
+	// image, at the size `dpi` or `print_width` make it if they're given.
+	let print_scale = match (options.dpi, options.print_width) {
+		(Some(dpi), _) => 25.4f32 * paper::PX_PER_MM / dpi,
+		(None, Some(print_width)) => print_width * paper::PX_PER_MM / width,
+		(None, None) => 1f32,
+	};
+	let (canvas_width, canvas_height) = match options.paper {
+		Some((sheet_width, sheet_height)) => ((sheet_width * paper::PX_PER_MM).round() as u32, (sheet_height * paper::PX_PER_MM).round() as u32),
+		None => ((width * print_scale).round() as u32, (height * print_scale).round() as u32),
+	};
+	let transform = match options.paper {
+		None if print_scale != 1f32 => transform.then(Transform2D::scale(print_scale, print_scale)),
+		Some(_) => {
+			let sheet = (canvas_width as f32, canvas_height as f32);
+			let rotate = paper::should_rotate(drawing, sheet, options.paper_orientation);
+			if rotate {
+				debug!("Turning the drawing a quarter turn to fit the paper");
+			}
+			transform.then(paper::fit(drawing, sheet, rotate))
+		},
+		None => transform,
+	};
+	// A margin shrinks the drawing into the middle of the canvas, since plotters clip whatever's right on the edge.
+	let transform = match options.margin {
+		Some(margin) if 2f32 * margin >= canvas_width.min(canvas_height) as f32 => {
+			return Err(Error::Usage(format!("A --margin of {:.0} px leaves nothing of a {}x{} canvas", margin, canvas_width, canvas_height)));
+		},
+		Some(margin) => transform.then(paper::inset((canvas_width as f32, canvas_height as f32), margin)),
+		None => transform,
+	};
+	Ok(Layout { transform, width: canvas_width, height: canvas_height })
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_lay_out() {
+		let plain = lay_out((40f32, 20f32), &LayoutOptions::default()).unwrap();
+		assert!(plain.transform.is_identity());
+		assert_eq!((plain.width, plain.height), (40, 20));
+		let printed = lay_out((40f32, 20f32), &LayoutOptions { dpi: Some(48f32), ..Default::default() }).unwrap();
+		assert_eq!((printed.width, printed.height), (80, 40));
+		assert!(lay_out((40f32, 20f32), &LayoutOptions { margin: Some(10f32), ..Default::default() }).is_err());
+	}
+}
@@ -0,0 +1,45 @@
+//! Turn images into plottable line drawings: space-filling curves, tours through stippled points, and the passes that
+//! decorate and lay out the paths.  The `tessellate_image` binary is a command line front end over these modules:
+//! `modes` traces an image the way each `--mode` does, with `solver` for the tour, and `decorate` runs the stages
+//! that follow, from `--smooth` to `--order`.
+//!
+//! `render::render_svg` goes from image bytes to SVG text without touching any files, which is what a browser build
+//! wants.  On wasm32 it traces on the calling thread with no progress bar, since there are no threads or clock there,
+//! but nothing builds that target regularly, so check it before relying on it.  `ffi` has the same curve for C, and
+//! a tour through points, declared in `include/tessellate_image.h`.
+
+pub mod arcs;
+pub mod bezier;
+pub mod config;
+pub mod decorate;
+pub mod delaunay;
+pub mod distances;
+pub mod error;
+pub mod ffi;
+pub mod gcode;
+pub mod graph;
+pub mod grid;
+pub mod hamiltonian;
+pub mod hilbert;
+pub mod http;
+pub mod intersect;
+pub mod kdtree;
+pub mod layers;
+pub mod layout;
+pub mod modes;
+pub mod output;
+pub mod paper;
+pub mod point;
+pub mod pointfile;
+pub mod polyline;
+pub mod postprocess;
+pub mod progress;
+pub mod quality;
+pub mod render;
+pub mod solver;
+pub mod stats;
+pub mod stipple;
+pub mod tessellation;
+pub mod transform;
+pub mod travel;
+pub mod voronoi;
@@ -1,226 +1,1339 @@
-
-use image;
 use image::{Luma, GrayImage};
-use imageproc; // For fast integral image.
-use plotters::prelude::*;
-use rand::random;
-use std::collections::{HashSet, HashMap};
 use std::env::args;
-use std::io::Write;
+use std::io::{BufReader, BufWriter, Write};
 use std::fs::File;
+use std::str::FromStr;
+use std::time::Instant;
+
+use log::{debug, info, warn};
+
+use tessellate_image::{config, decorate, error, gcode, hamiltonian, hilbert, http, intersect, layers, layout, modes, output, paper, point, pointfile, postprocess, quality, solver, stats, tessellation, transform, travel};
+use tessellate_image::error::Error;
+use tessellate_image::point::Point;
+use tessellate_image::pointfile::ToJson;
+use tessellate_image::polyline::{self, Polyline};
+use tessellate_image::render::{adjust_levels, darkness_at};
+
+/// Set once the drawing is being written to stdout, where the reports mustn't go.
+static DRAWING_TO_STDOUT:std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// `println!` for the reports that were asked for, like `--stats`, unless stdout is taken by the drawing.
+macro_rules! report {
+	($($arg:tt)*) => {
+		if DRAWING_TO_STDOUT.load(std::sync::atomic::Ordering::Relaxed) {
+			eprintln!($($arg)*)
+		} else {
+			println!($($arg)*)
+		}
+	};
+}
 
-mod hilbert;
-mod point;
+/// The words that can come first on the command line to pick what to do.
+const SUBCOMMANDS:[&str; 10] = ["hilbert", "mst", "delaunay", "voronoi", "tsp", "stipple", "tessellate", "solve", "serve", "help"];
 
-use point::Point;
+/// The subcommands that are also `--mode`s, drawing an image one way or another.
+const IMAGE_MODES:[&str; 5] = ["hilbert", "mst", "delaunay", "voronoi", "tsp"];
+
+const HILBERT_OPTIONS:&str = "[--max-depth D] [--min-cell PX] [--fit stretch|pad] [--orientation a|b|c|d|moore] [--closed] [--depth-groups] [--rotate DEGREES] [--balance] [--edge-weight W] [--skip-blank BRIGHTNESS] [--save-tree FILE] [--load-tree FILE]";
+const STIPPLE_OPTIONS:&str = "[--points N] [--min-spacing PX] [--dedup EPSILON]";
+const SOLVER_OPTIONS:&str = "[--solver hilbert|nn|greedy|christofides|ga|sa|lk|aco] [--open] [--two-opt] [--or-opt] [--uncross] [--neighbors K] [--distance-cache MB] [--iterations N] [--stall N] [--time-limit SECONDS] [--min-improvement LENGTH] [--cooling exponential|linear] [--population N] [--elites N] [--selection tournament|top-two] [--tournament-size N] [--mutation-rate ODDS] [--islands N] [--migration-interval G] [--migrants N] [--threads N] [--ants N] [--evaporation RATE] [--objective length|curvature|plot-time] [--curvature-weight W] [--max-speed UNITS/S] [--acceleration UNITS/S^2] [--checkpoint FILE] [--checkpoint-every N]";
+const DECORATE_OPTIONS:&str = "[--pipeline STAGE,...] [--smooth chaikin|catmull-rom] [--smooth-iterations 0-8] [--simplify TOLERANCE] [--pattern none|bolt|hex|square|tee|w|fake-hilbert|cross-stitch|meander|coil|NAME:WEIGHT,...] [--pattern-file FILE.json] [--pattern-seed N] [--pattern-depth 0-8] [--pattern-min-length PX] [--pattern-alternate] [--stitch-size PX] [--meander-turns N] [--coil-loops N] [--coil-radius PX] [--jitter AMPLITUDE] [--jitter-wavelength PX] [--jitter-seed N] [--jitter-double] [--dash PERIOD] [--dash-duty FRACTION] [--merge DISTANCE] [--order greedy|two-opt] [--pens N]";
+const SHADE_OPTIONS:&str = "[--pattern-modulate] [--coil-modulate] [--dash-modulate]";
+const OUTPUT_OPTIONS:&str = "[--flip horizontal|vertical|both] [--output-rotate DEGREES] [--scale S] [--offset X,Y] [--paper a4|a3|letter|...|WxH] [--paper-orientation auto|portrait|landscape] [--dpi DPI] [--print-width MM] [--margin LENGTH[mm|in|px]] [--stroke-width LENGTH[mm|in|px]] [--stroke-color COLOR] [--background COLOR|transparent] [--svg-units px|mm|in] [--precision DIGITS] [--overlay OPACITY] [--resample SPACING] [--dedup EPSILON] [--check-crossings] [--resolve-crossings] [--close-loop] [--save-paths FILE.json] [--curves] [--arcs TOLERANCE] [--dry-run] [--stats] [--stats-json] [--quality] [--quality-blur PX] [--quality-grid N] [--pen-lift SECONDS] [--chunks K] [--chunk-by length|time] [--mm-per-unit MM] [--machine z-axis|servo|laser] [--gcode-header FILE] [--gcode-footer FILE] [--pen-up-command GCODE] [--pen-down-command GCODE] [--z-up MM] [--z-down MM] [--travel-feed MM/MIN] [--draw-feed MM/MIN] [--gcode-offset X,Y]";
+
+/// Print how to use one subcommand, or a summary of them all.
+fn print_usage(program:&str, topic:Option<&str>) {
+	let image = "<input image> <output.svg|.gcode> [gray levels | --levels N]";
+	match topic {
+		Some("hilbert") => println!("Usage: {} hilbert {} {} {} {} {}", program, image, HILBERT_OPTIONS, DECORATE_OPTIONS, SHADE_OPTIONS, OUTPUT_OPTIONS),
+		Some("mst") | Some("voronoi") => println!("Usage: {} {} {} {} {} {} {}", program, topic.unwrap(), image, STIPPLE_OPTIONS, DECORATE_OPTIONS, SHADE_OPTIONS, OUTPUT_OPTIONS),
+		Some("delaunay") => println!("Usage: {} delaunay {} {} [--max-edge L] {} {} {}", program, image, STIPPLE_OPTIONS, DECORATE_OPTIONS, SHADE_OPTIONS, OUTPUT_OPTIONS),
+		Some("tsp") => println!("Usage: {} tsp {} {} {} {} {} {}", program, image, STIPPLE_OPTIONS, SOLVER_OPTIONS, DECORATE_OPTIONS, SHADE_OPTIONS, OUTPUT_OPTIONS),
+		Some("stipple") => println!("Usage: {} stipple <input image> <output.csv|.json> [gray levels] {}", program, STIPPLE_OPTIONS),
+		Some("tessellate") => println!("Usage: {} tessellate <paths.json> <output.svg> {} {}", program, DECORATE_OPTIONS, OUTPUT_OPTIONS),
+		Some("solve") => println!("Usage: {} solve <points.csv|points.json> <output.svg|.csv|.json> [--dedup EPSILON] {}", program, SOLVER_OPTIONS),
+		Some("serve") => {
+			println!("Usage: {} serve [--listen ADDRESS:PORT]", program);
+			println!("POST an image to /render, with any options as a JSON object in an X-Parameters header, and the SVG comes back.");
+			println!("The keys are the options without their dashes, as in a config file: {{\"mode\": \"tsp\", \"points\": 2000, \"levels\": 8}}.");
+		},
+		_ => {
+			println!("Usage: {} <subcommand> <input> <output> [options]", program);
+			println!("  hilbert     Draw an image as a space-filling curve (the default, also used without a subcommand)");
+			println!("  mst         Join stippled points with their minimum spanning tree");
+			println!("  delaunay    Draw the Delaunay triangulation of stippled points");
+			println!("  voronoi     Fill the Voronoi cells of stippled points with small curves");
+			println!("  tsp         Draw one tour through stippled points");
+			println!("  stipple     Write the stippled points themselves");
+			println!("  tessellate  Decorate paths saved with --save-paths");
+			println!("  solve       Find a tour through points from a file");
+			println!("  serve       Render images sent over HTTP");
+			println!("Any of them can take --config FILE.toml for settings kept in a file, or --preset sketch|scribble|stitch|smooth.");
+			println!("--preview FRACTION traces a copy of the image shrunk by that much, for quick looks at new settings.");
+			println!("An output of - writes the drawing to stdout, keeping everything else to stderr.");
+			println!("--watch draws again whenever the input or config file changes.");
+			println!("--tune tries other gray levels, max depths and smoothing, one at a time, and keeps whichever matches the image's");
+			println!("tone best, as --quality scores it, without drawing more than --length-budget if that's given.");
+			println!("--seed N fixes everything random, so the same command draws the same picture again.");
+			println!("--dry-run prints the length and plot time instead of writing, at --max-speed and --acceleration in units per second");
+			println!("and --pen-lift seconds to lower and raise the pen for each path.  --stats prints them as well as writing.");
+			println!("Add -v to time each stage, -vv for everything, or -q to only hear about problems.");
+			println!("See {} help <subcommand> for its options.", program);
+		},
+	}
+}
 
 fn main() {
+	let mut arguments:Vec<String> = args().collect::<Vec<String>>();
+	if take_switch(&mut arguments, "--watch") {
+		watch(arguments);
+	}
+	if take_switch(&mut arguments, "--tune") {
+		if let Err(e) = tune(arguments) {
+			eprintln!("{}", e);
+			std::process::exit(e.exit_code());
+		}
+		return;
+	}
+	if let Err(e) = run(arguments, &mut vec![]) {
+		eprintln!("{}", e);
+		std::process::exit(e.exit_code());
+	}
+}
+
+/// Render, then render again whenever one of the files it was drawn from changes, until interrupted.  Failures are
+/// reported and waited out, since the next save might fix them.
+fn watch(mut arguments:Vec<String>) -> ! {
+	// Keep to one seed, so only changes to the files change the picture.
+	if !arguments.iter().any(|a| a == "--seed") {
+		arguments.extend(vec!["--seed".to_string(), rand::random::<u64>().to_string()]);
+	}
+	loop {
+		let mut watched = vec![];
+		if let Err(e) = run(arguments.clone(), &mut watched) {
+			eprintln!("{}", e);
+		}
+		if watched.is_empty() {
+			eprintln!("Nothing to watch");
+			std::process::exit(2);
+		}
+		info!("Watching {} for changes.", watched.join(", "));
+		let modified = |watched:&[String]| -> Vec<Option<std::time::SystemTime>> {
+			watched.iter().map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok()).collect()
+		};
+		let before = modified(&watched);
+		while modified(&watched) == before {
+			std::thread::sleep(WATCH_INTERVAL);
+		}
+		// Give whatever is saving the file a moment to finish.
+		std::thread::sleep(WATCH_INTERVAL);
+	}
+}
+
+const WATCH_INTERVAL:std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Gray levels `tune` tries besides the default.
+const TUNE_LEVELS:[u8; 5] = [2, 4, 6, 8, 16];
+/// Max depths `tune` tries besides no limit at all.
+const TUNE_DEPTHS:[u32; 5] = [5, 6, 7, 8, 9];
+/// Rounds of Chaikin smoothing `tune` tries besides none.
+const TUNE_SMOOTHING:[u32; 3] = [1, 2, 3];
+/// How many times `tune` goes back over every setting before settling.
+const TUNE_ROUNDS:usize = 3;
+
+/// `--tune`: draw the image again and again, changing one of the gray levels, max depth or smoothing at a time, and
+/// keep whatever matches its tone best within `--length-budget`, if there is one.  That drawing is then written as
+/// normal.  Settings given on the command line stay as they are, since they're taken as already decided.
+fn tune(mut arguments:Vec<String>) -> Result<(), Error> {
+	let budget = take_parsed::<f32>(&mut arguments, "--length-budget")?;
+	// One seed for every try, so it's only the settings that make them differ.
+	if !arguments.iter().any(|a| a == "--seed") {
+		arguments.extend(vec!["--seed".to_string(), rand::random::<u64>().to_string()]);
+	}
+	set_up_logging(&mut arguments.clone());
+	let level = log::max_level();
+	let given = |flag:&str| arguments.iter().any(|a| a == flag);
+	let flags = |words:&[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<String>>();
+	let hilbert = match arguments.get(1).map(|a| a.as_str()) {
+		Some(sub) if SUBCOMMANDS.contains(&sub) => sub == "hilbert",
+		_ => arguments.iter().position(|a| a == "--mode").and_then(|i| arguments.get(i + 1)).is_none_or(|mode| mode == "hilbert"),
+	};
+	// Each setting is a list of choices, the first of them what's drawn without being told.
+	let mut settings:Vec<Vec<Vec<String>>> = vec![];
+	if !given("--levels") {
+		settings.push(std::iter::once(vec![]).chain(TUNE_LEVELS.iter().map(|l| flags(&["--levels", &l.to_string()]))).collect());
+	}
+	if hilbert && !given("--max-depth") {
+		settings.push(std::iter::once(vec![]).chain(TUNE_DEPTHS.iter().map(|d| flags(&["--max-depth", &d.to_string()]))).collect());
+	}
+	if !given("--smooth") {
+		settings.push(std::iter::once(vec![]).chain(TUNE_SMOOTHING.iter().map(|n| flags(&["--smooth", "chaikin", "--smooth-iterations", &n.to_string()]))).collect());
+	}
+	if settings.is_empty() {
+		return Err(Error::Usage("--tune has nothing left to search, since the levels, max depth and smoothing were all given".to_string()));
+	}
+	let chosen = |choice:&[usize]| -> Vec<String> { choice.iter().zip(&settings).flat_map(|(&i, options)| options[i].clone()).collect() };
+
+	let mut tried:std::collections::HashMap<Vec<usize>, (f32, f32)> = std::collections::HashMap::new();
+	let mut attempt = |choice:&[usize]| -> Result<(f32, f32), Error> {
+		if let Some(&result) = tried.get(choice) {
+			return Ok(result);
+		}
+		let mut trial = arguments.clone();
+		trial.extend(chosen(choice));
+		TRIAL.with(|t| t.set(None));
+		TUNING.with(|t| t.set(true));
+		log::set_max_level(level.min(log::LevelFilter::Warn));
+		let outcome = run(trial, &mut vec![]);
+		TUNING.with(|t| t.set(false));
+		log::set_max_level(level);
+		outcome?;
+		let result = TRIAL.with(|t| t.get()).ok_or_else(|| Error::Usage("--tune needs a drawing traced from an image".to_string()))?;
+		info!("{}: tone error {:.4}, length {:.0}", describe(&chosen(choice)), result.0, result.1);
+		tried.insert(choice.to_vec(), result);
+		Ok(result)
+	};
+	// Anything within the budget beats anything over it, and past that it's the tone that counts, or the length if
+	// nothing fits.
+	let better = |(error, length):(f32, f32), (best_error, best_length):(f32, f32)| {
+		match (budget.is_none_or(|b| length <= b), budget.is_none_or(|b| best_length <= b)) {
+			(true, false) => true,
+			(false, true) => false,
+			(true, true) => error < best_error,
+			(false, false) => length < best_length,
+		}
+	};
+	let mut choice = vec![0; settings.len()];
+	let mut best = attempt(&choice)?;
+	for _ in 0..TUNE_ROUNDS {
+		let mut improved = false;
+		for setting in 0..settings.len() {
+			for option in 0..settings[setting].len() {
+				let mut candidate = choice.clone();
+				candidate[setting] = option;
+				let result = attempt(&candidate)?;
+				if better(result, best) {
+					best = result;
+					choice = candidate;
+					improved = true;
+				}
+			}
+		}
+		if !improved {
+			break;
+		}
+	}
+	if budget.is_some_and(|b| best.1 > b) {
+		warn!("Nothing tried kept within the length budget, so going with the shortest");
+	}
+	report!("Best of {} tries: {} (tone error {:.4}, length {:.0})", tried.len(), describe(&chosen(&choice)), best.0, best.1);
+	arguments.extend(chosen(&choice));
+	run(arguments, &mut vec![])
+}
+
+/// Extra flags as they'd be typed, or a word for none.
+fn describe(flags:&[String]) -> String {
+	if flags.is_empty() { "the defaults".to_string() } else { flags.join(" ") }
+}
+
+/// Draw whatever `arguments` ask for.  Each file the drawing is made from goes into `watched` as soon as it's known.
+fn run(arguments:Vec<String>, watched:&mut Vec<String>) -> Result<(), Error> {
 	// Process CLI.
-	let arguments:Vec<String> = args().collect::<Vec<String>>();
+	let mut arguments = arguments;
+	let subcommand = match arguments.get(1) {
+		Some(word) if SUBCOMMANDS.contains(&word.as_str()) => Some(arguments.remove(1)),
+		_ => None,
+	};
+	set_up_logging(&mut arguments);
+	if arguments.get(2).map(|output| output.as_str()) == Some(output::STDOUT) {
+		keep_stdout_for_drawing();
+	}
+	if subcommand.as_deref() == Some("help") || take_switch(&mut arguments, "--help") || take_switch(&mut arguments, "-h") {
+		let topic = if subcommand.as_deref() == Some("help") { arguments.get(1).map(|t| t.as_str()) } else { subcommand.as_deref() };
+		print_usage(&arguments[0], topic);
+		return Ok(());
+	}
+	if subcommand.as_deref() == Some("serve") {
+		return serve(arguments);
+	}
+	// Settings from a file or preset fill in whatever wasn't typed, and the file wins over the preset.
+	let config_file = take_flag(&mut arguments, "--config")?;
+	let preset = take_flag(&mut arguments, "--preset")?;
+	if let Some(filename) = config_file {
+		watched.push(filename.clone());
+		let settings = std::fs::read_to_string(&filename).and_then(|text| config::parse(&text)).map_err(|e| Error::io(filename, e))?;
+		config::merge(&mut arguments, &settings);
+	}
+	if let Some(name) = preset {
+		match config::preset(&name) {
+			Some(settings) => config::merge(&mut arguments, &settings),
+			None => return Err(Error::Usage(format!("Unknown preset {}, try one of {}", name, config::PRESETS.iter().map(|&(name, _)| name).collect::<Vec<&str>>().join(", ")))),
+		}
+	}
+	// Everything the render was asked for, kept for its stats.
+	let mut given = arguments[1..].to_vec();
+	STAGE_SECONDS.with(|stages| stages.borrow_mut().clear());
+	// The render gets a pool of its own rather than the global one, which can only be set up once, since --watch,
+	// --tune and serve all render again and again.
+	if let Some(threads) = take_parsed::<usize>(&mut arguments, "--threads")? {
+		let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()
+			.map_err(|e| Error::Usage(format!("Failed to set up {} threads: {}", threads, e)))?;
+		if let Some(subcommand) = subcommand {
+			arguments.insert(1, subcommand);
+		}
+		// The pool's own thread does the render, so a try for --tune has to be passed over and its result brought back.
+		let tuning = TUNING.with(|t| t.get());
+		let (result, trial) = pool.install(|| {
+			TUNING.with(|t| t.set(tuning));
+			let result = run(arguments, watched);
+			TUNING.with(|t| t.set(false));
+			(result, TRIAL.with(|t| t.take()))
+		});
+		TRIAL.with(|t| t.set(trial));
+		return result;
+	}
+	let mode = match (subcommand.as_deref(), take_flag(&mut arguments, "--mode")?) {
+		(Some(sub), Some(flag)) if IMAGE_MODES.contains(&sub) && sub != flag => return Err(Error::Usage(format!("--mode {} doesn't go with the {} subcommand", flag, sub))),
+		(Some(sub), _) if IMAGE_MODES.contains(&sub) => sub.to_string(),
+		(_, flag) => flag.unwrap_or_else(|| "hilbert".to_string()),
+	};
+	let levels = take_parsed::<u8>(&mut arguments, "--levels")?;
+	// One seed for everything random, picked fresh and reported when it isn't given, so any run can be made again.
+	let seed = match take_parsed::<u64>(&mut arguments, "--seed")? {
+		Some(seed) => seed,
+		None => {
+			let seed = rand::random::<u64>();
+			info!("Using --seed {}", seed);
+			given.extend(vec!["--seed".to_string(), seed.to_string()]);
+			seed
+		},
+	};
+	let num_points = take_parsed::<usize>(&mut arguments, "--points")?.unwrap_or(5000);
+	let min_spacing = take_parsed::<f32>(&mut arguments, "--min-spacing")?.unwrap_or(0f32);
+	let max_edge = take_parsed::<f32>(&mut arguments, "--max-edge")?;
+	let max_depth = take_parsed::<u32>(&mut arguments, "--max-depth")?;
+	let min_cell_size = take_parsed::<u32>(&mut arguments, "--min-cell")?.unwrap_or(1);
+	let fit = match take_flag(&mut arguments, "--fit")?.as_deref() {
+		None | Some("stretch") => hilbert::Fit::Stretch,
+		Some("pad") => hilbert::Fit::Pad,
+		Some(other) => return Err(Error::BadValue { name: "--fit".to_string(), value: other.to_string() }),
+	};
+	let save_tree = take_flag(&mut arguments, "--save-tree")?;
+	let load_tree = take_flag(&mut arguments, "--load-tree")?;
+	let tuning = TUNING.with(|t| t.get());
+	let print_stats = take_switch(&mut arguments, "--stats") && !tuning;
+	let stats_json = take_switch(&mut arguments, "--stats-json");
+	let score_quality = take_switch(&mut arguments, "--quality");
+	let quality_blur = take_parsed::<usize>(&mut arguments, "--quality-blur")?.unwrap_or(4);
+	let quality_grid = take_parsed::<usize>(&mut arguments, "--quality-grid")?.unwrap_or(4);
+	let closed = take_switch(&mut arguments, "--closed");
+	let close_loop = take_switch(&mut arguments, "--close-loop");
+	let depth_groups = take_switch(&mut arguments, "--depth-groups");
+	let balance = take_switch(&mut arguments, "--balance");
+	let edge_weight = take_parsed::<f32>(&mut arguments, "--edge-weight")?;
+	if edge_weight.is_some_and(|weight| !(0f32..=1f32).contains(&weight)) {
+		return Err(Error::Usage("--edge-weight is the share of the depth that comes from edges, from 0 to 1".to_string()));
+	}
+	let skip_blank = take_parsed::<f32>(&mut arguments, "--skip-blank")?;
+	let solver = take_flag(&mut arguments, "--solver")?.unwrap_or_else(|| "hilbert".to_string());
+	let iterations = take_parsed::<u64>(&mut arguments, "--iterations")?.unwrap_or(100);
+	let open_path = take_switch(&mut arguments, "--open");
+	let use_two_opt = take_switch(&mut arguments, "--two-opt");
+	let use_or_opt = take_switch(&mut arguments, "--or-opt");
+	let use_uncross = take_switch(&mut arguments, "--uncross");
+	let distance_cache = take_parsed::<usize>(&mut arguments, "--distance-cache")?.unwrap_or(0);
+	let neighbor_count = take_parsed::<usize>(&mut arguments, "--neighbors")?.unwrap_or(10);
+	let stop = hamiltonian::StopCriteria {
+		max_generations: Some(iterations),
+		stall_generations: take_parsed::<u64>(&mut arguments, "--stall")?,
+		time_budget: take_parsed::<f32>(&mut arguments, "--time-limit")?.map(std::time::Duration::from_secs_f32),
+		min_improvement: take_parsed::<f32>(&mut arguments, "--min-improvement")?.unwrap_or(0f32),
+	};
+	let ga_config = hamiltonian::GaConfig {
+		population: take_parsed::<usize>(&mut arguments, "--population")?.unwrap_or(500),
+		elites: take_parsed::<usize>(&mut arguments, "--elites")?.unwrap_or(2),
+		selection: match take_flag(&mut arguments, "--selection")?.as_deref() {
+			None | Some("tournament") => hamiltonian::Selection::Tournament(take_parsed::<usize>(&mut arguments, "--tournament-size")?.unwrap_or(3)),
+			Some("top-two") => hamiltonian::Selection::TopTwo,
+			Some(other) => return Err(Error::BadValue { name: "--selection".to_string(), value: other.to_string() }),
+		},
+		mutation_rate: take_checked::<f64, _>(&mut arguments, "--mutation-rate", |odds| (0f64..=1f64).contains(odds))?.unwrap_or(0.2f64),
+	};
+	if ga_config.population < 2 || ga_config.elites >= ga_config.population {
+		return Err(Error::Usage("--population must be at least 2 and more than --elites".to_string()));
+	}
+	let islands = hamiltonian::IslandConfig {
+		islands: take_parsed::<usize>(&mut arguments, "--islands")?.unwrap_or(1),
+		migration_interval: take_parsed::<u64>(&mut arguments, "--migration-interval")?.unwrap_or(20),
+		migrants: take_parsed::<usize>(&mut arguments, "--migrants")?.unwrap_or(2),
+	};
+	let aco_config = hamiltonian::AcoConfig {
+		ants: take_parsed::<usize>(&mut arguments, "--ants")?.unwrap_or(20),
+		evaporation: take_checked::<f32, _>(&mut arguments, "--evaporation", |rate| *rate > 0f32 && *rate <= 1f32)?.unwrap_or(0.1f32),
+		..Default::default()
+	};
+	let checkpoint_every = take_parsed::<u64>(&mut arguments, "--checkpoint-every")?.unwrap_or(50);
+	let checkpoint = take_flag(&mut arguments, "--checkpoint")?.map(|path| hamiltonian::Checkpoint { path: path.into(), every: checkpoint_every });
+	let cooling = take_flag(&mut arguments, "--cooling")?.unwrap_or_else(|| "exponential".to_string());
+	let objective = take_flag(&mut arguments, "--objective")?.unwrap_or_else(|| "length".to_string());
+	let curvature_weight = take_parsed::<f32>(&mut arguments, "--curvature-weight")?.unwrap_or(1f32);
+	let max_speed = take_parsed::<f32>(&mut arguments, "--max-speed")?.unwrap_or(100f32);
+	let acceleration = take_parsed::<f32>(&mut arguments, "--acceleration")?.unwrap_or(1000f32);
+	let orientation = match take_flag(&mut arguments, "--orientation")?.as_deref() {
+		// The Moore curve ends next to where it began, so closing the loop only adds a short step.
+		None if closed || close_loop => Some(hilbert::QuadOrientation::Moore),
+		None => None,
+		Some("a") => Some(hilbert::QuadOrientation::A),
+		Some("b") => Some(hilbert::QuadOrientation::B),
+		Some("c") => Some(hilbert::QuadOrientation::C),
+		Some("d") => Some(hilbert::QuadOrientation::D),
+		Some("moore") => Some(hilbert::QuadOrientation::Moore),
+		Some(other) => return Err(Error::BadValue { name: "--orientation".to_string(), value: other.to_string() }),
+	};
+	let rotation = take_parsed::<f32>(&mut arguments, "--rotate")?.unwrap_or(0f32);
+	let preview = take_parsed::<f32>(&mut arguments, "--preview")?;
+	if preview.is_some_and(|fraction| !(fraction > 0f32 && fraction <= 1f32)) {
+		return Err(Error::Usage("--preview takes a fraction of the image size, above 0 and up to 1".to_string()));
+	}
+	let smoothing = take_flag(&mut arguments, "--smooth")?;
+	if let Some(other) = smoothing.as_deref().filter(|&smoothing| smoothing != "chaikin" && smoothing != "catmull-rom") {
+		return Err(Error::BadValue { name: "--smooth".to_string(), value: other.to_string() });
+	}
+	let smooth_iterations = take_checked::<u32, _>(&mut arguments, "--smooth-iterations", |passes| *passes <= postprocess::MAX_SMOOTH_ITERATIONS)?.unwrap_or(2);
+	let simplify_tolerance = take_checked::<f32, _>(&mut arguments, "--simplify", |tolerance| *tolerance >= 0f32)?;
+	let pattern_options = tessellation::SpecOptions {
+		seed: Some(take_parsed::<u64>(&mut arguments, "--pattern-seed")?.unwrap_or(seed)),
+		stitch_size: take_checked::<f32, _>(&mut arguments, "--stitch-size", |size| *size > 0f32)?.unwrap_or(4f32),
+		meander_turns: take_parsed::<u32>(&mut arguments, "--meander-turns")?.unwrap_or(4),
+		coil_loops: take_parsed::<u32>(&mut arguments, "--coil-loops")?.unwrap_or(3),
+		coil_radius: take_parsed::<f32>(&mut arguments, "--coil-radius")?.unwrap_or(2f32),
+		shade: None,
+	};
+	let coil_modulate = take_switch(&mut arguments, "--coil-modulate");
+	let pattern_file = take_flag(&mut arguments, "--pattern-file")?;
+	let pattern_spec = take_flag(&mut arguments, "--pattern")?.filter(|name| name != "none");
+	if pattern_spec.is_some() && pattern_file.is_some() {
+		return Err(Error::Usage("--pattern and --pattern-file can't be used together".to_string()));
+	}
+	if let Some(name) = &pattern_spec {
+		if tessellation::from_spec(name, &pattern_options).is_none() {
+			return Err(Error::BadValue { name: "--pattern".to_string(), value: name.clone() });
+		}
+	}
+	let custom_pattern = match pattern_file {
+		None => None,
+		Some(filename) => {
+			watched.push(filename.clone());
+			Some(std::fs::read_to_string(&filename).and_then(|text| pointfile::from_json::<tessellation::CustomPattern>(&text)).map_err(|e| Error::io(filename, e))?)
+		},
+	};
+	let pattern_depth = take_checked::<u32, _>(&mut arguments, "--pattern-depth", |depth| *depth <= tessellation::MAX_DEPTH)?.unwrap_or(1);
+	let pattern_min_length = take_parsed::<f32>(&mut arguments, "--pattern-min-length")?.unwrap_or(0f32);
+	let pattern_modulate = take_switch(&mut arguments, "--pattern-modulate");
+	let pattern_alternate = take_switch(&mut arguments, "--pattern-alternate");
+	let pipeline:Vec<String> = match take_flag(&mut arguments, "--pipeline")? {
+		Some(stages) => stages.split(',').map(|stage| stage.trim().to_string()).collect(),
+		None => decorate::STAGES.iter().map(|stage| stage.to_string()).collect(),
+	};
+	if let Some(stage) = pipeline.iter().find(|stage| !decorate::STAGES.contains(&stage.as_str())) {
+		return Err(Error::BadValue { name: "--pipeline".to_string(), value: stage.clone() });
+	}
+	let resample_spacing = take_checked::<f32, _>(&mut arguments, "--resample", |spacing| *spacing > 0f32)?;
+	let curves = take_switch(&mut arguments, "--curves");
+	let arc_tolerance = take_parsed::<f32>(&mut arguments, "--arcs")?;
+	if curves && arc_tolerance.is_some() {
+		return Err(Error::Usage("--curves and --arcs can't be used together".to_string()));
+	}
+	let jitter_amplitude = take_parsed::<f32>(&mut arguments, "--jitter")?;
+	let jitter_wavelength = take_checked::<f32, _>(&mut arguments, "--jitter-wavelength", |wavelength| *wavelength > 0f32)?.unwrap_or(20f32);
+	let jitter_seed = take_parsed::<u64>(&mut arguments, "--jitter-seed")?.unwrap_or(seed);
+	let jitter_double = take_switch(&mut arguments, "--jitter-double");
+	let dash_period = take_checked::<f32, _>(&mut arguments, "--dash", |period| *period > 0f32)?;
+	let dash_duty = take_parsed::<f32>(&mut arguments, "--dash-duty")?.unwrap_or(0.5f32);
+	let dash_modulate = take_switch(&mut arguments, "--dash-modulate");
+	let merge_distance = take_parsed::<f32>(&mut arguments, "--merge")?;
+	let order = take_flag(&mut arguments, "--order")?;
+	let pens = take_parsed::<usize>(&mut arguments, "--pens")?;
+	let overlay = take_parsed::<f32>(&mut arguments, "--overlay")?;
+	let two_opt = match order.as_deref() {
+		None | Some("greedy") => false,
+		Some("two-opt") => true,
+		Some(other) => return Err(Error::BadValue { name: "--order".to_string(), value: other.to_string() }),
+	};
+	let flip = match take_flag(&mut arguments, "--flip")?.as_deref() {
+		None => transform::Transform2D::identity(),
+		Some("horizontal") => transform::Transform2D::flip_x(),
+		Some("vertical") => transform::Transform2D::flip_y(),
+		Some("both") => transform::Transform2D::flip_x().then(transform::Transform2D::flip_y()),
+		Some(other) => return Err(Error::BadValue { name: "--flip".to_string(), value: other.to_string() }),
+	};
+	let save_paths = take_flag(&mut arguments, "--save-paths")?;
+	let dedup_epsilon = take_checked::<f32, _>(&mut arguments, "--dedup", |epsilon| *epsilon > 0f32)?;
+	let check_crossings = take_switch(&mut arguments, "--check-crossings") && !tuning;
+	let resolve_crossings = take_switch(&mut arguments, "--resolve-crossings");
+	let dry_run = take_switch(&mut arguments, "--dry-run");
+	let paper = match take_flag(&mut arguments, "--paper")? {
+		Some(name) => Some(paper::paper_size(&name).ok_or(Error::BadValue { name: "--paper".to_string(), value: name })?),
+		None => None,
+	};
+	let paper_orientation = take_parsed::<paper::Orientation>(&mut arguments, "--paper-orientation")?.unwrap_or(paper::Orientation::Auto);
+	let dpi = take_parsed::<f32>(&mut arguments, "--dpi")?;
+	let print_width = take_parsed::<f32>(&mut arguments, "--print-width")?;
+	if paper.is_some() && (dpi.is_some() || print_width.is_some()) {
+		return Err(Error::Usage("--paper sizes the drawing to fit the sheet, so it can't have --dpi or --print-width as well".to_string()));
+	}
+	if dpi.is_some() && print_width.is_some() {
+		return Err(Error::Usage("--dpi and --print-width can't be used together".to_string()));
+	}
+	// At a physical size, the drawing is laid out in SVG pixels, so that's what a unit is unless told otherwise.
+	let physical = paper.is_some() || dpi.is_some() || print_width.is_some();
+	let mm_per_unit = take_parsed::<f32>(&mut arguments, "--mm-per-unit")?.or(if physical { Some(1f32 / paper::PX_PER_MM) } else { None });
+	let px_per_mm = mm_per_unit.map_or(paper::PX_PER_MM, |mm| 1f32 / mm);
+	let margin = match take_flag(&mut arguments, "--margin")? {
+		Some(text) => Some(paper::parse_length(&text, px_per_mm).ok_or(Error::BadValue { name: "--margin".to_string(), value: text })?),
+		None => None,
+	};
+	let mut style = output::Style::default();
+	if let Some(text) = take_flag(&mut arguments, "--stroke-width")? {
+		style.stroke_width = paper::parse_length(&text, px_per_mm).filter(|width| *width > 0f32).ok_or(Error::BadValue { name: "--stroke-width".to_string(), value: text })?;
+	}
+	if let Some(text) = take_flag(&mut arguments, "--stroke-color")? {
+		style.stroke = output::parse_color(&text).ok_or(Error::BadValue { name: "--stroke-color".to_string(), value: text })?;
+	}
+	// A drawing with a size on paper says so, and one without stays in pixels.
+	let mm_per_px = 1f32 / px_per_mm;
+	style.units = match take_flag(&mut arguments, "--svg-units")?.as_deref() {
+		None if physical => output::Units::Millimetres(mm_per_px),
+		None | Some("px") => output::Units::Pixels,
+		Some("mm") => output::Units::Millimetres(mm_per_px),
+		Some("in") => output::Units::Inches(mm_per_px / 25.4f32),
+		Some(other) => return Err(Error::BadValue { name: "--svg-units".to_string(), value: other.to_string() }),
+	};
+	style.precision = take_parsed::<usize>(&mut arguments, "--precision")?.unwrap_or(style.precision);
+	style.background = match take_flag(&mut arguments, "--background")? {
+		None => None,
+		Some(text) if text == "transparent" || text == "none" => None,
+		Some(text) => Some(output::parse_color(&text).ok_or(Error::BadValue { name: "--background".to_string(), value: text })?),
+	};
+	let pen_lift = take_parsed::<f32>(&mut arguments, "--pen-lift")?.unwrap_or(0f32);
+	let chunks = take_parsed::<usize>(&mut arguments, "--chunks")?;
+	let chunk_by_time = match take_flag(&mut arguments, "--chunk-by")?.as_deref() {
+		None | Some("length") => false,
+		Some("time") => true,
+		Some(other) => return Err(Error::BadValue { name: "--chunk-by".to_string(), value: other.to_string() }),
+	};
+	let placement = layout::LayoutOptions {
+		flip,
+		rotation: take_parsed::<f32>(&mut arguments, "--output-rotate")?.unwrap_or(0f32),
+		scale: take_parsed::<f32>(&mut arguments, "--scale")?.unwrap_or(1f32),
+		offset: take_pair(&mut arguments, "--offset")?.unwrap_or((0f32, 0f32)),
+		paper,
+		paper_orientation,
+		dpi,
+		print_width,
+		margin,
+	};
+	let mut gcode_profile = match take_flag(&mut arguments, "--machine")? {
+		Some(name) => gcode::machine(&name).ok_or(Error::BadValue { name: "--machine".to_string(), value: name })?,
+		None => gcode::Profile::default(),
+	};
+	for (flag, template) in [("--gcode-header", &mut gcode_profile.header), ("--gcode-footer", &mut gcode_profile.footer)] {
+		if let Some(filename) = take_flag(&mut arguments, flag)? {
+			watched.push(filename.clone());
+			*template = std::fs::read_to_string(&filename).map_err(|e| Error::io(filename, e))?;
+		}
+	}
+	if let Some(command) = take_flag(&mut arguments, "--pen-up-command")? {
+		gcode_profile.pen_up = command;
+	}
+	if let Some(command) = take_flag(&mut arguments, "--pen-down-command")? {
+		gcode_profile.pen_down = command;
+	}
+	gcode_profile.z_up = take_parsed::<f32>(&mut arguments, "--z-up")?.unwrap_or(gcode_profile.z_up);
+	gcode_profile.z_down = take_parsed::<f32>(&mut arguments, "--z-down")?.unwrap_or(gcode_profile.z_down);
+	gcode_profile.travel_feed = take_parsed::<f32>(&mut arguments, "--travel-feed")?.unwrap_or(gcode_profile.travel_feed);
+	gcode_profile.draw_feed = take_parsed::<f32>(&mut arguments, "--draw-feed")?.unwrap_or(gcode_profile.draw_feed);
+	gcode_profile.offset = take_pair(&mut arguments, "--gcode-offset")?.unwrap_or(gcode_profile.offset);
+	gcode_profile.scale = mm_per_unit.unwrap_or(gcode_profile.scale);
+	gcode_profile.arc_tolerance = arc_tolerance;
+	if let Some(unknown) = arguments.iter().skip(1).find(|a| a.starts_with("--")) {
+		return Err(Error::Usage(format!("Unknown option {}, see --help", unknown)));
+	}
+	let tessellating = subcommand.as_deref() == Some("tessellate");
+	if tessellating && (pattern_modulate || coil_modulate || dash_modulate) {
+		return Err(Error::Usage("The modulate options follow an image, which the tessellate subcommand doesn't have".to_string()));
+	}
+	if tessellating && pens.is_some() {
+		return Err(Error::Usage("--pens splits by the tone of an image, which the tessellate subcommand doesn't have".to_string()));
+	}
+	if tessellating && (score_quality || tuning) {
+		return Err(Error::Usage("Scoring the drawing against an image, for --quality or --tune, isn't something the tessellate subcommand can do".to_string()));
+	}
+	if tessellating && overlay.is_some() {
+		return Err(Error::Usage("--overlay draws over an image, which the tessellate subcommand doesn't have".to_string()));
+	}
+	if depth_groups && mode != "hilbert" {
+		return Err(Error::Usage("--depth-groups groups by the depth of the Hilbert tree, so it only goes with --mode hilbert".to_string()));
+	}
+	if depth_groups && (pens.is_some() || chunks.is_some()) {
+		return Err(Error::Usage("--depth-groups puts every depth in the one file, so it can't be split with --pens or --chunks".to_string()));
+	}
+	let tsp = solver::TspOptions { solver, iterations, open_path, use_two_opt, use_or_opt, use_uncross, neighbor_count, distance_cache, stop, ga_config, islands, aco_config, checkpoint, cooling, objective, curvature_weight, max_speed, acceleration, dedup: dedup_epsilon, seed };
 	if arguments.len() < 3 {
-		println!("Usage: {} <input filename> <output filename>", &arguments[0]);
-		return;
+		print_usage(&arguments[0], subcommand.as_deref());
+		return Err(Error::Usage("Need an input and an output file".to_string()));
 	}
 	let input_filename = &arguments[1];
+	watched.push(input_filename.clone());
 	let output_filename = &arguments[2];
-	let gray_levels = if arguments.len() < 4 {
-		10
+	if pens.is_some_and(|pens| pens > 1) && output_filename == output::STDOUT {
+		return Err(Error::Usage("--pens writes a file per pen, so it needs an output filename".to_string()));
+	}
+	if chunks.is_some_and(|chunks| chunks > 1) && output_filename == output::STDOUT {
+		return Err(Error::Usage("--chunks writes a file per chunk, so it needs an output filename".to_string()));
+	}
+	if stats_json && output_filename == output::STDOUT {
+		return Err(Error::Usage("--stats-json writes its stats beside the output, so it needs an output filename".to_string()));
+	}
+	if overlay.is_some() && output_filename == output::STDOUT {
+		return Err(Error::Usage("--overlay writes its preview beside the output, so it needs an output filename".to_string()));
+	}
+	// Usually caught straight after the logging is set up, but flags typed before the file names hide it until now.
+	if output_filename == output::STDOUT {
+		keep_stdout_for_drawing();
+	}
+	if subcommand.as_deref() == Some("solve") {
+		return solve_command(input_filename, output_filename, &tsp);
+	}
+	// The levels can come from --levels, a config file or the fourth argument, so the error names whichever it was.
+	let (gray_levels, levels_name) = if arguments.len() < 4 {
+		(levels.unwrap_or(10), "--levels")
 	} else {
-		arguments[3].parse::<u8>().unwrap()
+		(error::parse_value::<u8>("gray_levels", &arguments[3])?, "gray_levels")
 	};
+	if gray_levels == 0 {
+		return Err(Error::BadValue { name: levels_name.to_string(), value: gray_levels.to_string() });
+	}
+	let trace = modes::TraceOptions { gray_levels, num_points, min_spacing, max_edge, seed, max_depth, min_cell_size, fit, orientation, rotation, balance, edge_weight, skip_blank };
 
-	// Load image.
-	println!("Loading image.");
-	let mut img = image::open(input_filename);
-	if img.is_err() {
-		println!("Failed to open {}", input_filename);
-		return;
+	// The tessellate subcommand decorates paths saved by an earlier run, on a canvas just big enough for them.  Nothing
+	// it does looks at the image, so the canvas is only a size, and the paths can reach as far out as they like.
+	let mut saved_paths = None;
+	let mut saved_size = None;
+	let img = if tessellating {
+		let paths = std::fs::read_to_string(input_filename).and_then(|text| pointfile::from_json::<Vec<Polyline>>(&text)).map_err(|e| Error::io(input_filename, e))?;
+		let corner = paths.iter().filter_map(|path| path.bounding_box()).fold((1f32, 1f32), |(w, h), (_, hi)| (w.max(hi.x), h.max(hi.y)));
+		saved_paths = Some(paths);
+		saved_size = Some((corner.0.ceil(), corner.1.ceil()));
+		GrayImage::from_pixel(1, 1, Luma([255u8]))
+	} else {
+		// Load image.
+		info!("Loading image.");
+		timed("load", || image::open(input_filename)).map_err(|source| Error::Image { path: input_filename.clone(), source })?.to_luma8()
+	};
+	// A preview traces a shrunken copy of the image, which is quicker to try settings on.
+	let mut img = match preview {
+		Some(fraction) if !tessellating => {
+			let (width, height) = ((img.width() as f32 * fraction).round().max(1f32) as u32, (img.height() as f32 * fraction).round().max(1f32) as u32);
+			timed("preview", || image::imageops::resize(&img, width, height, image::imageops::FilterType::Triangle))
+		},
+		_ => img,
+	};
+	let (image_width, image_height) = saved_size.unwrap_or((img.width() as f32, img.height() as f32));
+	// The score is against the image as it looked before its tones were crushed.
+	let source = if score_quality || tuning { Some(img.clone()) } else { None };
+	timed("quantize", || adjust_levels(&mut img, gray_levels));
+
+	if subcommand.as_deref() == Some("stipple") {
+		return stipple_command(&img, &trace, dedup_epsilon, output_filename);
 	}
-	let mut img = img.unwrap().to_luma8();
-	adjust_levels(&mut img, gray_levels);
-	//let integral:imageproc::definitions::Image<image::Luma<u8>> = imageproc::integral_image::integral_image(&img);
 
-	let mut hilbert_curve = hilbert::Hilbert::new(img.width(), 0, 0, img.height(), None);
-	hilbert_curve.subdivide();
-	for y in 0..img.height() {
-		for x in 0..img.width() {
-			let luma = img.get_pixel(x, y)[0];
-			hilbert_curve.subdivide_leaf(x, y, (gray_levels - luma) as u32);
+	// Grouping by depth splits the curve as it comes off the tree, since only the tree knows the depths.
+	let mut depth_layers:Option<Vec<(u32, Vec<Polyline>)>> = None;
+	let paths:Vec<Polyline> = match mode.as_str() {
+		_ if saved_paths.is_some() => saved_paths.take().unwrap(),
+		"hilbert" => {
+			let canvas = modes::hilbert_canvas(&img, &trace);
+			let hilbert_curve = if let Some(tree_filename) = &load_tree {
+				info!("Loading tree from {}", tree_filename);
+				let mut tree = timed("load tree", || File::open(tree_filename).and_then(|f| hilbert::Hilbert::load(&mut BufReader::new(f)))).map_err(|e| Error::io(tree_filename, e))?;
+				if balance {
+					tree.balance();
+				}
+				tree
+			} else {
+				timed("build tree", || modes::hilbert_tree(&canvas, &trace))
+			};
+			if let Some(tree_filename) = &save_tree {
+				let result = File::create(tree_filename).and_then(|f| {
+					let mut writer = BufWriter::new(f);
+					hilbert_curve.save(&mut writer)?;
+					writer.flush()
+				});
+				result.map_err(|e| Error::io(tree_filename, e))?;
+			}
+			if print_stats {
+				print_tree_stats(&hilbert_curve);
+			}
+			if depth_groups {
+				depth_layers = Some(timed("rasterize", || modes::hilbert_depth_groups(&hilbert_curve, &canvas, &img, &trace)));
+				vec![]
+			} else {
+				timed("rasterize", || modes::hilbert_curves(&hilbert_curve, &canvas, &img, &trace))
+			}
+		},
+		"mst" => timed("mst", || modes::mst_paths(&img, &trace)),
+		"delaunay" => timed("delaunay", || modes::delaunay_paths(&img, &trace)),
+		"voronoi" => timed("voronoi", || modes::voronoi_paths(&img, &trace)),
+		"tsp" => {
+			let resumed = solver::resume_checkpoint(tsp.checkpoint.as_ref())?;
+			// An existing checkpoint brings its own points, since a fresh stippling wouldn't match its tours.
+			let points:Vec<(f32, f32)> = match &resumed {
+				Some(state) => state.points.clone(),
+				None => timed("stipple", || modes::stipple_points(&img, &trace, tsp.dedup)).into_iter().map(|p| p.into()).collect(),
+			};
+			// A white image, or --points 1, leaves nothing to make a tour of.
+			if points.len() < 2 {
+				return Err(Error::Usage(format!("Need at least two points to solve, got {}", points.len())));
+			}
+			let tour = timed("solve", || solver::solve_tour(&points, resumed, &tsp))?;
+			vec![tour.into_iter().map(|idx| points[idx]).collect::<Vec<(f32, f32)>>().into()]
+		},
+		_ => return Err(Error::BadValue { name: "--mode".to_string(), value: mode }),
+	};
+
+	// The darkness of the image, for the options that follow its tone.
+	let shade = || -> tessellation::Shade {
+		let img = img.clone();
+		std::sync::Arc::new(move |p| darkness_at(&img, gray_levels, p))
+	};
+	// The spec was checked up front; it's only built now so the patterns that follow the image can see it.
+	let pattern = match (pattern_spec, custom_pattern) {
+		(Some(name), _) => {
+			let mut options = pattern_options;
+			if coil_modulate {
+				options.shade = Some(shade());
+			}
+			tessellation::from_spec(&name, &options)
+		},
+		(None, Some(custom)) => Some(Box::new(custom) as Box<dyn tessellation::SegmentTessellator>),
+		(None, None) => None,
+	};
+	let decorating = decorate::DecorateOptions {
+		pipeline,
+		smoothing,
+		smooth_iterations,
+		simplify_tolerance,
+		tessellator: pattern.map(|pattern| decorate::tessellator(pattern, pattern_modulate.then(shade), pattern_alternate, pattern_depth, pattern_min_length)),
+		jitter_amplitude,
+		jitter_wavelength,
+		jitter_seed,
+		jitter_double,
+		dash_period,
+		dash_duty,
+		dash_shade: dash_modulate.then(shade),
+		merge_distance,
+		order: order.map(|_| two_opt),
+	};
+	for stage in decorating.skipped() {
+		warn!("Skipping {}, which isn't in the pipeline", stage);
+	}
+	// With more than one pen, each takes its own band of tone and goes through the stages as a drawing of its own, so
+	// that, among other things, the order stage plans each pen's run separately.
+	// Depth groups go through them the same way.
+	let depths:Option<Vec<u32>> = depth_layers.as_ref().map(|groups| groups.iter().map(|&(depth, _)| depth).collect());
+	let layers = match (pens, depth_layers) {
+		(_, Some(groups)) => groups.into_iter().map(|(_, paths)| paths).collect(),
+		(Some(pens), None) => layers::split_by_tone(&paths, pens, |p| darkness_at(&img, gray_levels, p)),
+		(None, None) => vec![paths],
+	};
+	let mut layers:Vec<Vec<Polyline>> = layers.into_iter().map(|mut paths| {
+		for stage in &decorating.pipeline {
+			paths = timed(stage, || decorate::run_stage(stage, paths, &decorating));
+		}
+		paths
+	}).collect();
+
+	// Scored in the image's own frame, before anything moves the drawing onto the canvas.
+	let mut tone_error = None;
+	if let Some(source) = &source {
+		let score = timed("quality", || quality::score(&layers.concat(), source, quality_blur, quality_grid));
+		if score_quality && !tuning {
+			report_quality(&score);
 		}
+		tone_error = Some(score.rms_error);
 	}
-	let mut lines:Vec<(f32, f32)> = hilbert_curve.rasterize();
 
-	// Convert 'lines' to points.
-	let points:Vec<(f32, f32)> = lines.iter().map(|&p| { p.into() }).collect();
+	let layout::Layout { transform: output_transform, width: canvas_width, height: canvas_height } = layout::lay_out((image_width, image_height), &placement)?;
+	if !output_transform.is_identity() {
+		for path in layers.iter_mut().flatten() {
+			*path = path.transform(|p| output_transform.apply(p));
+		}
+	}
 
-	// Write output!
-	println!("Saving output.");
-	draw_image(points, output_filename, img.width(), img.height());
+	// Even spacing comes after the transform, so it's measured in output units.
+	if let Some(spacing) = resample_spacing {
+		let points = layers.iter().flatten().map(|path| path.length() as f64).sum::<f64>() / spacing as f64;
+		if points > polyline::MAX_RESAMPLED_POINTS as f64 {
+			return Err(Error::Usage(format!("--resample {} would make {:.0} points, more than the {} allowed", spacing, points, polyline::MAX_RESAMPLED_POINTS)));
+		}
+		for path in layers.iter_mut().flatten() {
+			*path = path.resample(spacing);
+		}
+	}
+
+	if let Some(epsilon) = dedup_epsilon {
+		let dropped:usize = layers.iter_mut().flatten().map(|path| path.dedup(epsilon)).sum();
+		info!("Dropped {} repeated points", dropped);
+	}
+	if check_crossings {
+		report_crossings(&layers.concat());
+	}
+	if resolve_crossings {
+		let resolved:usize = layers.iter_mut().flatten().map(intersect::resolve_intersections).sum();
+		info!("Resolved {} self-intersections", resolved);
+	}
+	// Closing comes last, so nothing after it can move the end away from the start again.
+	if close_loop {
+		for paths in layers.iter_mut() {
+			travel::close_loop(paths);
+		}
+	}
+
+	// A dry run stops short of writing anything, with what the plot would take instead.
+	let plotter = hamiltonian::PlotTime { close: false, max_speed, acceleration };
+	let layer_stats:Vec<stats::PlotStats> = layers.iter().map(|paths| stats::plot_stats(paths, &plotter, pen_lift)).collect();
+	if tuning {
+		let total:stats::PlotStats = layer_stats.iter().copied().sum();
+		TRIAL.with(|t| t.set(tone_error.map(|error| (error, total.pen_down))));
+		return Ok(());
+	}
+	if dry_run || print_stats {
+		if layer_stats.len() > 1 {
+			for (pen, plot) in layer_stats.iter().enumerate() {
+				match &depths {
+					Some(depths) => report!("Depth {}:", depths[pen]),
+					None => report!("Pen {}:", pen + 1),
+				}
+				for line in plot_stats_lines(plot, mm_per_unit) {
+					report!("  {}", line);
+				}
+			}
+			report!("Total:");
+		}
+		for line in plot_stats_lines(&layer_stats.iter().copied().sum(), mm_per_unit) {
+			report!("{}", line);
+		}
+	}
+	if dry_run {
+		return Ok(());
+	}
+
+	if let Some(paths_filename) = &save_paths {
+		let result = File::create(paths_filename).and_then(|f| {
+			let mut writer = BufWriter::new(f);
+			layers.concat().write_json(&mut writer)?;
+			writer.flush()
+		});
+		result.map_err(|e| Error::io(paths_filename, e))?;
+	}
 
-	println!("Saved output to {}", output_filename);
+	// Write output!  Every pen's file, and every chunk of one if it's split up, gets the whole canvas, so they can be
+	// plotted one over another.  Depth groups all go into the one file, a group apiece.
+	info!("Saving output.");
+	if let Some(depths) = &depths {
+		timed("write", || if output::is_gcode(output_filename) {
+			output::draw_gcode(&layers.concat(), output_filename, &gcode_profile, canvas_width, canvas_height)
+		} else {
+			let groups:Vec<output::Group> = depths.iter().zip(&layers).map(|(depth, paths)| output::Group { id: format!("depth-{}", depth), paths }).collect();
+			output::draw_groups(&groups, output_filename, canvas_width, canvas_height, curves, arc_tolerance, &style)
+		})?;
+		output::add_metadata(output_filename, &plot_stats_lines(&layer_stats.iter().copied().sum(), mm_per_unit))?;
+		info!("Saved output to {}", output_filename);
+	} else {
+		for (pen, paths) in layers.iter().enumerate() {
+			let layer_filename = if layers.len() == 1 { output_filename.to_string() } else { output::layer_filename(output_filename, pen + 1) };
+			let pieces = match chunks {
+				Some(chunks) if chunk_by_time => layers::split_into_chunks(paths, chunks, |a, b| plotter.segment_time((b - a).length())),
+				Some(chunks) => layers::split_into_chunks(paths, chunks, |a, b| (b - a).length()),
+				None => vec![paths.clone()],
+			};
+			for (chunk, paths) in pieces.iter().enumerate() {
+				let filename = if pieces.len() == 1 { layer_filename.clone() } else { output::chunk_filename(&layer_filename, chunk + 1) };
+				timed("write", || if output::is_gcode(&filename) {
+					output::draw_gcode(paths, &filename, &gcode_profile, canvas_width, canvas_height)
+				} else if let Some(tolerance) = arc_tolerance {
+					output::draw_arcs(paths, &filename, canvas_width, canvas_height, tolerance, &style)
+				} else if curves {
+					output::draw_curves(paths, &filename, canvas_width, canvas_height, &style)
+				} else {
+					output::draw_image(paths, &filename, canvas_width, canvas_height, &style)
+				})?;
+				output::add_metadata(&filename, &plot_stats_lines(&stats::plot_stats(paths, &plotter, pen_lift), mm_per_unit))?;
+				info!("Saved output to {}", filename);
+			}
+		}
+	}
+	// The preview shows the image as it was traced, tones crushed to the gray levels and all, back at full range.
+	if let Some(opacity) = overlay {
+		let filename = output::overlay_filename(output_filename);
+		let mut shown = img.clone();
+		shown.pixels_mut().for_each(|p| p[0] = (p[0] as u32 * 255 / gray_levels.max(1) as u32).min(255) as u8);
+		let backdrop = output::Backdrop { image: &shown, placement: output_transform, opacity };
+		output::draw_overlay(&layers.concat(), &filename, canvas_width, canvas_height, &style, &backdrop)?;
+		info!("Saved the preview over the image to {}", filename);
+	}
+	if stats_json {
+		let filename = output::stats_filename(output_filename);
+		let all = layers.concat();
+		let stages = STAGE_SECONDS.with(|stages| stages.borrow().clone());
+		let render = stats::RenderStats::new(&all, layer_stats.iter().copied().sum(), given, stages);
+		let result = File::create(&filename).and_then(|f| {
+			let mut writer = BufWriter::new(f);
+			render.write_json(&mut writer)?;
+			writer.flush()
+		});
+		result.map_err(|e| Error::io(&filename, e))?;
+		info!("Saved stats to {}", filename);
+	}
+	Ok(())
 }
 
-fn adjust_levels(img:&mut GrayImage, steps:u8) {
-	// Crush the image luminance from 0-255 to `steps` distinct values from 0 to `steps`.
-	img.enumerate_pixels_mut().for_each(|(_px, _py, value)| {
-		// Bit-crushing.
-		let floatval:f32 = value[0] as f32 / 255f32;
-		*value = Luma([(floatval*steps as f32) as u8]);
-	});
+/// Print how closely the drawing matches its image, overall and region by region.
+fn report_quality(score:&quality::Quality) {
+	report!("Tone error (RMS): {:.4}", score.rms_error);
+	report!("Structural similarity: {:.4}", score.ssim);
+	report!("Ink gain: {:.2}", score.gain);
+	report!("Tone error by region:");
+	for row in score.regions.chunks(score.grid.0) {
+		report!("  {}", row.iter().map(|error| format!("{:.3}", error)).collect::<Vec<String>>().join(" "));
+	}
 }
 
-fn tessellate(line_start:Point, line_end:Point) -> Vec<Point> {
-	if line_end == line_start {
-		vec![line_start]
-	} else {
-		tessellate_bolt(line_start, line_end)
+/// Print how many times each path crosses itself, and where the first few crossings are.
+fn report_crossings(paths:&[Polyline]) {
+	const SHOWN:usize = 10;
+	let mut total = 0;
+	for (i, path) in paths.iter().enumerate() {
+		let found = intersect::self_intersections(path);
+		for crossing in found.iter().take(SHOWN.saturating_sub(total)) {
+			report!("Path {} crosses itself at ({:.2}, {:.2}), segments {} and {}", i, crossing.at.x, crossing.at.y, crossing.first, crossing.second);
+		}
+		total += found.len();
 	}
+	if total > SHOWN {
+		report!("...and {} more", total - SHOWN);
+	}
+	report!("{} self-intersections", total);
 }
 
-fn tessellate_bolt(line_start:Point, line_end:Point) -> Vec<Point> {
-	// Replace ----
-	// With/\
-	//    /  \
-	//        \/
-	// One segment becomes four of 1/4th size.  We could also do three with different sizes.
-	let dpos = line_end - line_start;
-	let left = Point::new(-dpos.y, dpos.x) * 0.75; // Lob-sided Left-hand normal.
-	let right = Point::new(dpos.y, -dpos.x) * 0.25f32;
-	let fwd = dpos * 0.5f32;
-	vec![
-		line_start,
-		line_start + fwd + left,
-		line_start + fwd,
-		line_start + fwd + right,
-		line_end
-	]
+/// `serve`: answer render requests over HTTP until killed, one thread per connection.  Each request runs the same
+/// pipeline as the command line, on the uploaded image saved to a scratch directory.
+fn serve(mut arguments:Vec<String>) -> Result<(), Error> {
+	let address = take_flag(&mut arguments, "--listen")?.unwrap_or_else(|| "127.0.0.1:8080".to_string());
+	if let Some(unknown) = arguments.get(1) {
+		return Err(Error::Usage(format!("Unexpected {}, see help serve", unknown)));
+	}
+	let listener = std::net::TcpListener::bind(&address).map_err(|e| Error::io(address.clone(), e))?;
+	info!("Listening on http://{}/render", address);
+	let program = arguments[0].clone();
+	let counter = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+	for stream in listener.incoming() {
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(e) => {
+				warn!("Failed to accept a connection: {}", e);
+				continue;
+			},
+		};
+		let (program, counter) = (program.clone(), counter.clone());
+		std::thread::spawn(move || {
+			let id = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+			let mut reader = BufReader::new(&stream);
+			let (status, content_type, body) = match http::read_request(&mut reader, MAX_UPLOAD) {
+				Ok(request) => answer(&request, &program, id),
+				Err(e) => (400, "text/plain", e.to_string().into_bytes()),
+			};
+			if let Err(e) = http::write_response(&mut &stream, status, content_type, &body) {
+				warn!("Failed to answer request {}: {}", id, e);
+			}
+		});
+	}
+	Ok(())
 }
 
-fn tessellate_hex(line_start:Point, line_end:Point) -> Vec<Point> {
-	//  e f
-	// a d g
-	//  b c
-	let dpos = line_end - line_start;
-	let left_normal = Point::new(-dpos.y, dpos.x)*0.3f32;
-	let forward = dpos*0.3;
-	let right_normal = Point::new(dpos.y, -dpos.x)*0.3f32;
-	vec![
-		line_start,
-		line_start + forward + right_normal,
-		line_start + forward + right_normal + forward,
-		line_start + forward + forward,
-		line_start + forward + left_normal,
-		line_start + forward + left_normal + forward,
-		line_end
-	]
+/// The only options a render request may set.  Anything naming a file to read or write, writing more than the one
+/// drawing, or changing the server itself, like `--threads`, is left out.
+const SERVE_SETTINGS:&[&str] = &[
+	"--mode", "--levels", "--seed", "--preset", "--preview",
+	"--points", "--min-spacing", "--max-edge", "--dedup",
+	"--max-depth", "--min-cell", "--fit", "--orientation", "--closed", "--depth-groups", "--rotate", "--balance", "--edge-weight", "--skip-blank",
+	"--solver", "--open", "--two-opt", "--or-opt", "--uncross", "--neighbors", "--distance-cache", "--iterations", "--stall", "--time-limit",
+	"--min-improvement", "--cooling", "--population", "--elites", "--selection", "--tournament-size", "--mutation-rate", "--islands",
+	"--migration-interval", "--migrants", "--ants", "--evaporation", "--objective", "--curvature-weight", "--max-speed", "--acceleration",
+	"--pipeline", "--smooth", "--smooth-iterations", "--simplify", "--pattern", "--pattern-seed", "--pattern-depth", "--pattern-min-length",
+	"--pattern-alternate", "--stitch-size", "--meander-turns", "--coil-loops", "--coil-radius", "--jitter", "--jitter-wavelength", "--jitter-seed",
+	"--jitter-double", "--dash", "--dash-duty", "--merge", "--order", "--pattern-modulate", "--coil-modulate", "--dash-modulate",
+	"--flip", "--output-rotate", "--scale", "--offset", "--paper", "--paper-orientation", "--dpi", "--print-width", "--margin", "--stroke-width",
+	"--stroke-color", "--background", "--svg-units", "--precision", "--resample", "--check-crossings", "--resolve-crossings", "--close-loop",
+	"--curves", "--arcs", "--stats", "--quality", "--quality-blur", "--quality-grid", "--pen-lift", "--mm-per-unit", "--machine",
+	"--pen-up-command", "--pen-down-command", "--z-up", "--z-down", "--travel-feed", "--draw-feed", "--gcode-offset",
+];
+
+/// The range a render request may give each setting that multiplies the work, as (option, least, most).  One
+/// request that runs the server out of memory takes every other request down with it, so these are kept well inside
+/// what the command line allows.
+const SERVE_LIMITS:&[(&str, f64, f64)] = &[
+	("--points", 0f64, 100_000f64), ("--iterations", 0f64, 10_000f64), ("--population", 0f64, 2_000f64), ("--islands", 0f64, 16f64),
+	("--ants", 0f64, 200f64), ("--neighbors", 0f64, 50f64), ("--smooth-iterations", 0f64, 4f64), ("--pattern-depth", 0f64, 4f64),
+	("--meander-turns", 0f64, 16f64), ("--coil-loops", 0f64, 16f64), ("--stitch-size", 0.5f64, f64::MAX), ("--dash", 0.5f64, f64::MAX),
+	("--resample", 0.1f64, f64::MAX), ("--precision", 0f64, 6f64),
+];
+
+/// Whether every option in a render request's settings is one it may set, with a value inside its `SERVE_LIMITS`.
+/// Short switches like `-q` change the whole server's logging, so they're refused too, but values like `-5,3` are fine.
+fn allowed_settings(settings:&[String]) -> Result<(), String> {
+	for (i, setting) in settings.iter().enumerate() {
+		let option = setting.starts_with("--") || (setting.starts_with('-') && setting[1..].starts_with(|c:char| c.is_ascii_alphabetic()));
+		if option && !SERVE_SETTINGS.contains(&setting.as_str()) {
+			return Err(format!("{} can't be set over HTTP", setting));
+		}
+		if let Some(&(_, least, most)) = SERVE_LIMITS.iter().find(|&&(name, _, _)| name == setting) {
+			let value = settings.get(i + 1).and_then(|value| value.parse::<f64>().ok());
+			if !value.is_some_and(|value| (least..=most).contains(&value)) {
+				return Err(match most {
+					most if most == f64::MAX => format!("{} must be at least {} over HTTP", setting, least),
+					most => format!("{} must be from {} to {} over HTTP", setting, least, most),
+				});
+			}
+		}
+	}
+	Ok(())
 }
 
-fn tessellate_square(line_start:Point, line_end:Point) -> Vec<Point> {
-	// Replace
-	// ----
-	// With
-	// bc
-	// adg
-	//  ef
-	let dpos = line_end - line_start;
-	let left_normal = Point::new(-dpos.y, dpos.x)*0.5f32;
-	let forward = dpos*0.5;
-	let right_normal = Point::new(dpos.y, -dpos.x)*0.5f32;
-	vec![
-		line_start,
-		line_start + left_normal,
-		line_start + left_normal + forward,
-		line_start + forward,
-		line_start + right_normal + forward,
-		line_start + right_normal + forward + forward,
-		line_end
-	]
+/// The most an uploaded image may take.
+const MAX_UPLOAD:usize = 64 * 1024 * 1024;
+
+/// Status, content type and body for one request.
+fn answer(request:&http::Request, program:&str, id:usize) -> (u16, &'static str, Vec<u8>) {
+	match (request.method.as_str(), request.path.as_str()) {
+		("POST", "/render") => {},
+		(_, "/render") => return (405, "text/plain", b"POST an image to /render\n".to_vec()),
+		_ => return (404, "text/plain", b"Nothing here, try POST /render\n".to_vec()),
+	}
+	let settings = match request.header("X-Parameters").map(config::parse_json).unwrap_or_else(|| Ok(vec![])) {
+		Ok(settings) => settings,
+		Err(e) => return (400, "text/plain", format!("Bad X-Parameters: {}\n", e).into_bytes()),
+	};
+	if let Err(message) = allowed_settings(&settings) {
+		return (400, "text/plain", format!("Bad X-Parameters: {}\n", message).into_bytes());
+	}
+	// The pipeline reads and writes files, so give it a directory of its own.
+	let extension = image::guess_format(&request.body).ok().and_then(|format| format.extensions_str().first().copied()).unwrap_or("png");
+	let scratch = std::env::temp_dir().join(format!("tessellate-serve-{}-{}", std::process::id(), id));
+	let input = scratch.join(format!("input.{}", extension)).to_string_lossy().into_owned();
+	let output = scratch.join("output.svg").to_string_lossy().into_owned();
+	let result = std::fs::create_dir_all(&scratch).and_then(|()| std::fs::write(&input, &request.body)).map_err(|e| Error::io(scratch.display().to_string(), e)).and_then(|()| {
+		let mut arguments = vec![program.to_string(), input.clone(), output.clone()];
+		arguments.extend(settings);
+		info!("Request {}: {}", id, arguments[3..].join(" "));
+		run(arguments, &mut vec![])
+	}).and_then(|()| std::fs::read(&output).map_err(|e| Error::io(output.clone(), e)));
+	let _ = std::fs::remove_dir_all(&scratch);
+	match result {
+		Ok(svg) => (200, "image/svg+xml", svg),
+		Err(e) => (if e.exit_code() == 2 { 400 } else { 500 }, "text/plain", format!("{}\n", e).into_bytes()),
+	}
 }
 
-fn tessellate_tee(line_start:Point, line_end:Point) -> Vec<Point> {
-	// g     h
-	// f e j i
-	// a x x m
-	//   b l
-	let dpos = line_end - line_start;
-	let a = line_start;
-	let l = Point::new(-dpos.y, dpos.x)*0.25f32;
-	let f = dpos*0.25;
-	let r = Point::new(dpos.y, -dpos.x)*0.25f32;
-	vec![
-		a,
-		a + f + r,
-		//a + f,
-		a + f + l,
-		a + l,
-		a + l + l,
-		a + l + l + f + f + f,
-		a + l + f + f + f,
-		a + l + f + f,
-		//a + f + f,
-		a + r + f + f,
-		a + f + f + f
-	]
+/// Writes log messages to stderr, so stdout keeps only the reports that were asked for.  Progress reads as plain lines and
+/// anything else is marked with its level.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+	fn enabled(&self, metadata:&log::Metadata) -> bool {
+		metadata.level() <= log::max_level()
+	}
+
+	fn log(&self, record:&log::Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+		match record.level() {
+			log::Level::Info => eprintln!("{}", record.args()),
+			level => eprintln!("[{}] {}", level.to_string().to_lowercase(), record.args()),
+		}
+	}
+
+	fn flush(&self) {}
 }
 
-fn tessellate_w(line_start:Point, line_end:Point) -> Vec<Point> {
-	//   c
-	// a   e
-	//  b d
-	let dpos = line_end - line_start;
-	let a = line_start;
-	let l = Point::new(-dpos.y, dpos.x)*0.4f32;
-	let f = dpos*0.2;
-	let r = Point::new(dpos.y, -dpos.x)*0.2f32;
-	vec![
-		a,
-		a + f + r,
-		a + f + f + l,
-		a + f + f + f + r,
-		line_end
-	]
+static LOGGER:StderrLogger = StderrLogger;
+
+/// Take the verbosity switches and start logging: `-q` for warnings only, `-v` to add the time each stage takes, `-vv`
+/// for everything.
+fn set_up_logging(arguments:&mut Vec<String>) {
+	let quiet = take_switch(arguments, "-q") | take_switch(arguments, "--quiet");
+	let mut verbosity = 0;
+	while take_switch(arguments, "-v") || take_switch(arguments, "--verbose") {
+		verbosity += 1;
+	}
+	while take_switch(arguments, "-vv") {
+		verbosity += 2;
+	}
+	let level = match (quiet, verbosity) {
+		(true, _) => log::LevelFilter::Warn,
+		(false, 0) => log::LevelFilter::Info,
+		(false, 1) => log::LevelFilter::Debug,
+		(false, _) => log::LevelFilter::Trace,
+	};
+	if log::set_logger(&LOGGER).is_ok() {
+		log::set_max_level(level);
+	}
+}
+
+/// With the drawing going to stdout, everything else keeps out of its way: chatter is dropped, reports go to stderr.
+fn keep_stdout_for_drawing() {
+	DRAWING_TO_STDOUT.store(true, std::sync::atomic::Ordering::Relaxed);
+	if log::max_level() == log::LevelFilter::Info {
+		log::set_max_level(log::LevelFilter::Warn);
+	}
+}
+
+thread_local! {
+	/// Seconds spent in each stage of the render running on this thread, for `--stats-json`.
+	static STAGE_SECONDS:std::cell::RefCell<Vec<(String, f32)>> = const { std::cell::RefCell::new(vec![]) };
+	/// Set while `tune` tries settings out, when renders are scored and stop before they write or report anything.
+	static TUNING:std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+	/// The tone error and length of the last try.
+	static TRIAL:std::cell::Cell<Option<(f32, f32)>> = const { std::cell::Cell::new(None) };
+}
+
+/// Run one stage and log how long it took.  A stage that runs more than once, like one for every pen, adds up.
+fn timed<T, F:FnOnce() -> T>(stage:&str, f:F) -> T {
+	let start = Instant::now();
+	let result = f();
+	let elapsed = start.elapsed();
+	debug!("{} took {:.2?}", stage, elapsed);
+	STAGE_SECONDS.with(|stages| {
+		let mut stages = stages.borrow_mut();
+		match stages.iter_mut().find(|(name, _)| name == stage) {
+			Some((_, seconds)) => *seconds += elapsed.as_secs_f32(),
+			None => stages.push((stage.to_string(), elapsed.as_secs_f32())),
+		}
+	});
+	result
+}
+
+/// Remove `--name value` from the argument list, giving back the value if it was there.
+fn take_flag(arguments:&mut Vec<String>, name:&str) -> Result<Option<String>, Error> {
+	let idx = match arguments.iter().position(|a| a == name) {
+		Some(idx) => idx,
+		None => return Ok(None),
+	};
+	if idx + 1 >= arguments.len() {
+		return Err(Error::Usage(format!("Missing value for {}", name)));
+	}
+	arguments.remove(idx);
+	Ok(Some(arguments.remove(idx)))
+}
+
+/// Remove `--name value` and read the value as a `T`.
+fn take_parsed<T:FromStr>(arguments:&mut Vec<String>, name:&str) -> Result<Option<T>, Error> {
+	take_flag(arguments, name)?.map(|value| error::parse_value(name, &value)).transpose()
 }
 
-fn tessellate_fake_hilbert(line_start:Point, line_end:Point) -> Vec<Point> {
-	// e fi j
-	// dcghlk
-	// ab  mn
-	let dpos = line_end - line_start;
-	let a = line_start;
-	let l = Point::new(-dpos.y, dpos.x)*0.25f32;
-	let f = dpos*0.15;
+/// `take_parsed` for a value that also has to pass `valid`, like a size that has to be above zero.
+fn take_checked<T:FromStr, F:Fn(&T) -> bool>(arguments:&mut Vec<String>, name:&str, valid:F) -> Result<Option<T>, Error> {
+	match take_flag(arguments, name)? {
+		Some(value) => match error::parse_value::<T>(name, &value)? {
+			parsed if valid(&parsed) => Ok(Some(parsed)),
+			_ => Err(Error::BadValue { name: name.to_string(), value }),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Remove a `--name X,Y` pair of numbers from the argument list.
+fn take_pair(arguments:&mut Vec<String>, name:&str) -> Result<Option<(f32, f32)>, Error> {
+	match take_flag(arguments, name)?.map(|n| n.split(',').map(|v| v.trim().parse::<f32>()).collect::<Result<Vec<f32>, _>>()) {
+		None => Ok(None),
+		Some(Ok(values)) if values.len() == 2 => Ok(Some((values[0], values[1]))),
+		Some(_) => Err(Error::Usage(format!("{} takes X,Y", name))),
+	}
+}
+
+/// Remove a `--name` switch from the argument list, giving back whether it was there.
+fn take_switch(arguments:&mut Vec<String>, name:&str) -> bool {
+	if let Some(idx) = arguments.iter().position(|a| a == name) {
+		arguments.remove(idx);
+		true
+	} else {
+		false
+	}
+}
+
+fn plot_stats_lines(plot:&stats::PlotStats, mm_per_unit:Option<f32>) -> Vec<String> {
+	let distance = |units:f32| match mm_per_unit {
+		Some(mm) => format!("{:.1} px ({:.1} mm)", units, units * mm),
+		None => format!("{:.1} px", units),
+	};
 	vec![
-		a,
-		a + f,
-		a + f + l,
-		a + l,
-		a + l + l,
-		a + l + l + f + f,
-		a + l + f + f,
-		a + l + f + f + f,
-		a + l + l + f + f + f,
-		a + l + l + f + f + f + f + f,
-		a + l + f + f + f + f + f,
-		a + l + f + f + f + f,
-		a + f + f + f + f,
-		line_end
+		format!("Paths: {}", plot.paths),
+		format!("Points: {}", plot.points),
+		format!("Pen down: {}", distance(plot.pen_down)),
+		format!("Pen up: {}", distance(plot.pen_up)),
+		format!("Pen lifts: {}", plot.lifts),
+		format!("Estimated plot time: {}", stats::clock(plot.seconds)),
 	]
 }
 
-
-fn draw_image(points:Vec<(f32, f32)>, filename:&str, canvas_width:u32, canvas_height:u32) -> Result<(), Box<dyn std::error::Error>> {
-	let mut backend = SVGBackend::new(filename, (canvas_width, canvas_height));
-	//chart.draw_series(LineSeries::new(vec![(0.0, 0.0), (5.0, 5.0), (8.0, 7.0)],&RED,))?;
-	for i in 0..points.len()-1 {
-		backend.draw_line((points[i].0 as i32, points[i].1 as i32), (points[i+1].0 as i32, points[i+1].1 as i32), &BLACK);
-		//backend.draw_circle((points[i].0 as i32, points[i].1 as i32), 1u32, &BLACK, false);
+fn print_tree_stats(hilbert_curve:&hilbert::Hilbert) {
+	report!("Nodes: {}", hilbert_curve.node_count());
+	report!("Depth: {}", hilbert_curve.tree_depth());
+	for (depth, count) in hilbert_curve.depth_histogram().iter().enumerate() {
+		report!("  Depth {}: {} nodes", depth, count);
 	}
-	//backend.draw_rect((50, 50), (200, 150), &RED, true)?;
+	report!("Points: {}", hilbert_curve.point_count());
+	report!("Path length: {}", hilbert_curve.path_length());
+}
 
-	let mut fout = File::create(std::path::Path::new(&("raw_".to_owned() + &filename.to_owned()))).unwrap();
-	points.iter().for_each(|&p|{
-		fout.write(format!("{},{}\n", p.0, p.1).as_ref());
+/// Write the stippled points of an image out as CSV or JSON, by the output's extension.
+fn stipple_command(img:&GrayImage, options:&modes::TraceOptions, dedup:Option<f32>, output_filename:&str) -> Result<(), Error> {
+	let points:Vec<(f32, f32)> = modes::stipple_points(img, options, dedup).into_iter().map(|p| p.into()).collect();
+	let extension = std::path::Path::new(output_filename).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+	let result = output::create(output_filename).and_then(|mut writer| {
+		match extension.as_deref() {
+			Some("json") => pointfile::write_json(&points, &mut writer)?,
+			_ => pointfile::write_csv(&points, &mut writer)?,
+		}
+		writer.flush()
 	});
+	result.map_err(|e| Error::io(output_filename, e))?;
+	info!("Saved {} points to {}", points.len(), output_filename);
+	Ok(())
+}
+
+/// `solve`: order the points in a CSV or JSON file and write the path out.  The output format follows the extension:
+/// .csv or .txt for a point list, .json for a JSON array, anything else for an SVG.
+fn solve_command(points_filename:&str, output_filename:&str, options:&solver::TspOptions) -> Result<(), Error> {
+	let resumed = solver::resume_checkpoint(options.checkpoint.as_ref())?;
+	let points = match &resumed {
+		Some(state) => state.points.clone(),
+		None => {
+			let points = std::fs::read_to_string(points_filename).and_then(|text| pointfile::parse_points(&text)).map_err(|e| Error::io(points_filename, e))?;
+			match options.dedup {
+				Some(epsilon) => point::dedup(&points.into_iter().map(Point::from).collect::<Vec<Point>>(), epsilon).into_iter().map(|p| p.into()).collect(),
+				None => points,
+			}
+		},
+	};
+	if points.len() < 2 {
+		return Err(Error::Usage(format!("Need at least two points to solve, got {}", points.len())));
+	}
+	info!("Solving {} points.", points.len());
+	let tour = timed("solve", || solver::solve_tour(&points, resumed, options))?;
+	let ordered:Vec<(f32, f32)> = tour.into_iter().map(|idx| points[idx]).collect();
 
+	let extension = std::path::Path::new(output_filename).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+	let written = |result:std::io::Result<()>| result.map_err(|e| Error::io(output_filename, e));
+	match extension.as_deref() {
+		Some("csv") | Some("txt") => written(output::create(output_filename).and_then(|mut writer| {
+			pointfile::write_csv(&ordered, &mut writer)?;
+			writer.flush()
+		}))?,
+		Some("json") => written(output::create(output_filename).and_then(|mut writer| {
+			pointfile::write_json(&ordered, &mut writer)?;
+			writer.flush()
+		}))?,
+		_ => {
+			let width = ordered.iter().map(|p| p.0).fold(1f32, f32::max).ceil() as u32;
+			let height = ordered.iter().map(|p| p.1).fold(1f32, f32::max).ceil() as u32;
+			output::draw_image(&[ordered.into()], output_filename, width, height, &output::Style::default())?
+		},
+	}
+	info!("Saved output to {}", output_filename);
 	Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// What `run` makes of a command line, without any files behind it.
+	fn run_with(words:&[&str]) -> Result<(), Error> {
+		run(words.iter().map(|w| w.to_string()).collect(), &mut vec![])
+	}
+
+	fn bad_value(result:Result<(), Error>) -> Option<(String, String)> {
+		match result {
+			Err(Error::BadValue { name, value }) => Some((name, value)),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn test_bad_values() {
+		assert_eq!(bad_value(run_with(&["prog", "in.png", "out.svg", "--levels", "0"])), Some(("--levels".to_string(), "0".to_string())));
+		assert_eq!(bad_value(run_with(&["prog", "in.png", "out.svg", "0"])), Some(("gray_levels".to_string(), "0".to_string())));
+		assert_eq!(bad_value(run_with(&["prog", "in.png", "out.svg", "--close-loop", "--orientation", "bogus"])), Some(("--orientation".to_string(), "bogus".to_string())));
+		for (flag, value) in [("--simplify", "-1"), ("--stitch-size", "0"), ("--jitter-wavelength", "0"), ("--resample", "0"), ("--resample", "-2"), ("--dash", "0"), ("--mutation-rate", "2"), ("--dedup", "0"), ("--pattern-depth", "9"), ("--evaporation", "2"), ("--evaporation", "-1"), ("--smooth-iterations", "40")] {
+			assert_eq!(bad_value(run_with(&["prog", "in.png", "out.svg", flag, value])), Some((flag.to_string(), value.to_string())), "{}", flag);
+		}
+	}
+
+	/// A white image `size` pixels square in the temporary directory, and somewhere to write what's drawn from it.
+	fn blank_image(name:&str, size:u32) -> (String, String) {
+		let dir = std::env::temp_dir();
+		let (input, output) = (dir.join(format!("{}.png", name)), dir.join(format!("{}.svg", name)));
+		GrayImage::from_pixel(size, size, Luma([255u8])).save(&input).unwrap();
+		(input.to_string_lossy().into_owned(), output.to_string_lossy().into_owned())
+	}
+
+	#[test]
+	fn test_resample_limit() {
+		let (input, output) = blank_image("tessellate_resample_limit", 16);
+		assert!(matches!(run_with(&["prog", &input, &output, "--resample", "1e-30"]), Err(Error::Usage(_))));
+		assert!(run_with(&["prog", &input, &output, "--resample", "0.5"]).is_ok());
+		let _ = (std::fs::remove_file(input), std::fs::remove_file(output));
+	}
+
+	#[test]
+	fn test_tour_of_blank_image() {
+		let (input, output) = blank_image("tessellate_blank_tour", 16);
+		for solver in ["ga", "sa", "aco", "lk", "nn"] {
+			let result = run_with(&["prog", "tsp", &input, &output, "--solver", solver, "--iterations", "2"]);
+			assert!(matches!(result, Err(Error::Usage(_))), "{}", solver);
+		}
+		let _ = std::fs::remove_file(input);
+	}
+
+	#[test]
+	fn test_tessellate_far_out_paths() {
+		// A canvas a billion units across is fine as long as nothing makes a bitmap of it.
+		let dir = std::env::temp_dir();
+		let (input, output) = (dir.join("tessellate_far_paths.json"), dir.join("tessellate_far_paths.svg"));
+		std::fs::write(&input, "[[[0, 0], [1000000000, 1000000000]]]").unwrap();
+		let (input, output) = (input.to_string_lossy().into_owned(), output.to_string_lossy().into_owned());
+		assert!(run_with(&["prog", "tessellate", &input, &output, "--pattern", "bolt"]).is_ok());
+		assert!(std::fs::read_to_string(&output).unwrap().starts_with("<svg width=\"1000000000\""));
+		let _ = (std::fs::remove_file(input), std::fs::remove_file(output));
+	}
+
+	#[test]
+	fn test_serve_limits() {
+		let settings = |words:&[&str]| allowed_settings(&words.iter().map(|w| w.to_string()).collect::<Vec<String>>());
+		assert!(settings(&["--levels", "4", "--smooth", "chaikin", "--smooth-iterations", "4", "--offset", "-5,3"]).is_ok());
+		for words in [&["--smooth-iterations", "40"][..], &["--resample", "1e-30"], &["--points", "1e9"], &["--precision", "x"], &["--coil-loops"], &["--threads", "2"], &["-q"]] {
+			assert!(settings(words).is_err(), "{:?}", words);
+		}
+	}
+
+	#[test]
+	fn test_threads_again() {
+		// Each render sets up its own pool, so a second one with --threads gets as far as the missing image.
+		for _ in 0..2 {
+			assert!(matches!(run_with(&["prog", "missing.png", "out.svg", "--threads", "2"]), Err(Error::Image { .. })));
+		}
+	}
+}
@@ -0,0 +1,235 @@
+use crate::delaunay;
+use crate::graph;
+use crate::hilbert;
+use crate::kdtree;
+use crate::layers;
+use crate::point::{self, Point};
+use crate::polyline::Polyline;
+use crate::render::{self, build_hilbert, mean_darkness, IntegralImage};
+use crate::stipple;
+use crate::voronoi;
+use image::{GrayImage, Luma};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// The settings that trace an image into paths, for every `--mode` but the tour, which also takes a
+/// `solver::TspOptions`.  Each builder here takes an image already crushed to `gray_levels` by `render::adjust_levels`.
+#[derive(Copy, Clone)]
+pub struct TraceOptions {
+	pub gray_levels: u8,
+	/// How many points to stipple, for the modes that start from points.
+	pub num_points: usize,
+	/// How close stippled points may land to each other.  0 lets them land anywhere.
+	pub min_spacing: f32,
+	/// The longest Delaunay edge to keep.
+	pub max_edge: Option<f32>,
+	pub seed: u64,
+	pub max_depth: Option<u32>,
+	pub min_cell_size: u32,
+	pub fit: hilbert::Fit,
+	pub orientation: Option<hilbert::QuadOrientation>,
+	/// Degrees to turn the Hilbert curve by over the image.
+	pub rotation: f32,
+	/// Split cells of the tree until none is more than a level shallower than one beside it.
+	pub balance: bool,
+	/// The share of the tree's depth that follows edges in the image rather than its darkness, from 0 to 1.
+	pub edge_weight: Option<f32>,
+	/// Leave out the cells at least this bright, from 0 for black to 1 for white.
+	pub skip_blank: Option<f32>,
+}
+
+impl Default for TraceOptions {
+	fn default() -> Self {
+		TraceOptions {
+			gray_levels: 10, num_points: 5000, min_spacing: 0f32, max_edge: None, seed: 0,
+			max_depth: None, min_cell_size: 1, fit: hilbert::Fit::Stretch, orientation: None, rotation: 0f32, balance: false, edge_weight: None, skip_blank: None,
+		}
+	}
+}
+
+/// The image turned by `options.rotation` onto a canvas of its own, which is what the Hilbert tree is built over.
+pub fn hilbert_canvas(img:&GrayImage, options:&TraceOptions) -> GrayImage {
+	rotate_image(img, options.rotation, options.gray_levels)
+}
+
+/// Build the Hilbert tree over a canvas from `hilbert_canvas`, deepest where it's darkest or, with an edge weight,
+/// partly where its edges are.
+pub fn hilbert_tree(canvas:&GrayImage, options:&TraceOptions) -> hilbert::Hilbert {
+	let gray_levels = options.gray_levels;
+	let integral:IntegralImage = imageproc::integral_image::integral_image(canvas);
+	let mut hilbert_curve = match options.edge_weight {
+		// Edges pull the depth their way by the weight, and the darkness keeps the rest of the say.
+		Some(weight) => {
+			let edges = render::edge_levels(canvas, gray_levels);
+			let edge_integral:IntegralImage = imageproc::integral_image::integral_image(&edges);
+			let wanted_depth = |bounds| {
+				(1f32 - weight) * mean_darkness(canvas, &integral, gray_levels, bounds) + weight * mean_darkness(&edges, &edge_integral, gray_levels, bounds)
+			};
+			render::build_hilbert_by(canvas.width(), canvas.height(), wanted_depth, options.max_depth, options.min_cell_size, options.fit, options.orientation)
+		},
+		None => build_hilbert(canvas, &integral, gray_levels, options.max_depth, options.min_cell_size, options.fit, options.orientation),
+	};
+	if options.balance {
+		hilbert_curve.balance();
+	}
+	hilbert_curve
+}
+
+/// Whether `options.skip_blank` keeps a cell of the tree over `canvas`.
+fn keeps<'a>(canvas:&'a GrayImage, options:&'a TraceOptions) -> impl Fn(&hilbert::Cell) -> bool + 'a {
+	let integral:IntegralImage = imageproc::integral_image::integral_image(canvas);
+	// Brightness runs from 0 for black to 1 for white.
+	move |cell| options.skip_blank.is_none_or(|threshold| {
+		1f32 - mean_darkness(canvas, &integral, options.gray_levels, cell.bounds) / options.gray_levels as f32 <= threshold
+	})
+}
+
+/// Walk a tree from `hilbert_tree` into paths over `img`, leaving out any blank cells.
+pub fn hilbert_curves(tree:&hilbert::Hilbert, canvas:&GrayImage, img:&GrayImage, options:&TraceOptions) -> Vec<Polyline> {
+	let curves = if options.skip_blank.is_some() {
+		tree.rasterize_where(keeps(canvas, options))
+	} else {
+		vec![tree.par_rasterize()]
+	};
+	hilbert_paths(curves, img, options.fit, options.rotation)
+}
+
+/// `hilbert_curves`, split into groups by the depth of the cells each piece passes through, shallowest first.
+pub fn hilbert_depth_groups(tree:&hilbert::Hilbert, canvas:&GrayImage, img:&GrayImage, options:&TraceOptions) -> Vec<(u32, Vec<Polyline>)> {
+	let tagged = tree.rasterize_with_depth(keeps(canvas, options));
+	layers::split_by_depth(&tagged).into_iter().map(|(depth, curves)| (depth, hilbert_paths(curves, img, options.fit, options.rotation))).collect()
+}
+
+/// The whole of `--mode hilbert`: canvas, tree and curve.
+pub fn hilbert(img:&GrayImage, options:&TraceOptions) -> Vec<Polyline> {
+	let canvas = hilbert_canvas(img, options);
+	hilbert_curves(&hilbert_tree(&canvas, options), &canvas, img, options)
+}
+
+/// Move rasterized curves back into the frame of `img`.
+/// `rotation` (in degrees) must match what was given to `rotate_image` when the tree was built.
+pub fn hilbert_paths(curves:Vec<Vec<(f32, f32)>>, img:&GrayImage, fit:hilbert::Fit, rotation:f32) -> Vec<Polyline> {
+	curves.into_iter().flat_map(|points| {
+		let mut path = Polyline::from(points);
+		if rotation != 0f32 {
+			let (canvas_width, canvas_height) = rotated_size(img.width(), img.height(), rotation);
+			let from = Point::new(canvas_width as f32 / 2f32, canvas_height as f32 / 2f32);
+			let to = Point::new(img.width() as f32 / 2f32, img.height() as f32 / 2f32);
+			path = path.transform(|p| rotate_about(p, from, to, rotation));
+		}
+		if fit == hilbert::Fit::Pad || rotation != 0f32 {
+			clip_path(&path, img.width() as f32, img.height() as f32)
+		} else {
+			vec![path]
+		}
+	}).collect()
+}
+
+/// Size of the smallest canvas that holds the image after rotating it.
+pub fn rotated_size(width:u32, height:u32, degrees:f32) -> (u32, u32) {
+	let (sin, cos) = degrees.to_radians().sin_cos();
+	let w = width as f32;
+	let h = height as f32;
+	// Round before taking the ceiling so quarter turns come out exact despite float noise.
+	let rotated_width = ((w*cos.abs() + h*sin.abs()) * 1000f32).round() / 1000f32;
+	let rotated_height = ((w*sin.abs() + h*cos.abs()) * 1000f32).round() / 1000f32;
+	(rotated_width.ceil() as u32, rotated_height.ceil() as u32)
+}
+
+/// Rotate `p` by `degrees` around `from`, then move it so `from` lands on `to`.
+pub fn rotate_about(p:Point, from:Point, to:Point, degrees:f32) -> Point {
+	to + (p - from).rotate(degrees.to_radians())
+}
+
+/// Turn the image by `-degrees` onto a canvas big enough to hold it, filling the corners with white.
+/// Building a curve on this and then turning its points by `degrees` gives a rotated curve over the original image.
+pub fn rotate_image(img:&GrayImage, degrees:f32, white:u8) -> GrayImage {
+	if degrees == 0f32 {
+		return img.clone();
+	}
+	let (canvas_width, canvas_height) = rotated_size(img.width(), img.height(), degrees);
+	let from = Point::new(canvas_width as f32 / 2f32, canvas_height as f32 / 2f32);
+	let to = Point::new(img.width() as f32 / 2f32, img.height() as f32 / 2f32);
+	GrayImage::from_fn(canvas_width, canvas_height, |x, y| {
+		let p = rotate_about(Point::new(x as f32 + 0.5f32, y as f32 + 0.5f32), from, to, degrees);
+		if p.x >= 0f32 && p.y >= 0f32 && (p.x as u32) < img.width() && (p.y as u32) < img.height() {
+			*img.get_pixel(p.x as u32, p.y as u32)
+		} else {
+			Luma([white])
+		}
+	})
+}
+
+/// Split a path into the runs of points that fall inside the rectangle from the origin to width, height.
+pub fn clip_path(path:&Polyline, width:f32, height:f32) -> Vec<Polyline> {
+	let mut paths = vec![];
+	let mut current = vec![];
+	for &p in &path.points {
+		if p.x >= 0f32 && p.x <= width && p.y >= 0f32 && p.y <= height {
+			current.push(p);
+		} else if !current.is_empty() {
+			paths.push(Polyline::new(std::mem::take(&mut current)));
+		}
+	}
+	if !current.is_empty() {
+		paths.push(Polyline::new(current));
+	}
+	paths
+}
+
+/// Stipple the image from `options.seed`, dropping points within `dedup` of one already kept if that's given.
+pub fn stipple_points(img:&GrayImage, options:&TraceOptions, dedup:Option<f32>) -> Vec<Point> {
+	let stipples = stipple::stipple(img, options.gray_levels, options.num_points, options.min_spacing, &mut StdRng::seed_from_u64(options.seed));
+	match dedup {
+		Some(epsilon) => point::dedup(&stipples, epsilon),
+		None => stipples,
+	}
+}
+
+/// Stipple the image and connect the dots with their minimum spanning tree.  Each edge is its own stroke.
+pub fn mst_paths(img:&GrayImage, options:&TraceOptions) -> Vec<Polyline> {
+	let points = stipple_points(img, options, None);
+	graph::minimum_spanning_tree(&points).into_iter().map(|(a, b)| {
+		Polyline::new(vec![points[a], points[b]])
+	}).collect()
+}
+
+/// Stipple the image and draw the edges of the Delaunay triangulation, skipping any longer than `options.max_edge`.
+pub fn delaunay_paths(img:&GrayImage, options:&TraceOptions) -> Vec<Polyline> {
+	let points = stipple_points(img, options, None);
+	let triangles = delaunay::triangulate(&points);
+	delaunay::triangle_edges(&triangles).into_iter().filter(|&(a, b)| {
+		options.max_edge.is_none_or(|max| kdtree::distance_squared(points[a], points[b]) <= max*max)
+	}).map(|(a, b)| {
+		Polyline::new(vec![points[a], points[b]])
+	}).collect()
+}
+
+/// Stipple the image and use the points as Voronoi sites, filling each cell with a small curve.
+pub fn voronoi_paths(img:&GrayImage, options:&TraceOptions) -> Vec<Polyline> {
+	let sites = stipple_points(img, options, None);
+	voronoi::fill_cells(img, options.gray_levels, &sites).into_iter().map(Polyline::new).collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_rotated_size() {
+		assert_eq!(rotated_size(40, 20, 0f32), (40, 20));
+		assert_eq!(rotated_size(40, 20, 90f32), (20, 40));
+		assert_eq!(rotated_size(10, 10, 45f32), (15, 15));
+	}
+
+	#[test]
+	fn test_hilbert_stays_on_image() {
+		let img = GrayImage::from_fn(32, 24, |x, _| Luma([(x / 4) as u8]));
+		for rotation in [0f32, 30f32] {
+			let options = TraceOptions { gray_levels: 8, rotation, ..Default::default() };
+			let paths = hilbert(&img, &options);
+			assert!(!paths.is_empty());
+			assert!(paths.iter().flat_map(|path| &path.points).all(|p| p.x >= 0f32 && p.x <= 32f32 && p.y >= 0f32 && p.y <= 24f32), "{}", rotation);
+		}
+	}
+}
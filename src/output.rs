@@ -0,0 +1,391 @@
+use crate::error::{Error, Result};
+use crate::polyline::Polyline;
+use crate::progress::ProgressBar;
+use crate::transform::Transform2D;
+use image::GrayImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The filename that means standard output, for sending a drawing down a pipe.
+pub const STDOUT:&str = "-";
+
+/// Open `filename` for writing, or standard output if it's `STDOUT`.
+pub fn create(filename:&str) -> std::io::Result<Box<dyn Write>> {
+	if filename == STDOUT {
+		Ok(Box::new(std::io::stdout().lock()))
+	} else {
+		Ok(Box::new(BufWriter::new(File::create(filename)?)))
+	}
+}
+
+/// An RGB colour.
+pub type Rgb = (u8, u8, u8);
+
+/// What an SVG's width and height are given in.  Everything inside it stays in drawing units, through the viewBox.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Units {
+	Pixels,
+	/// Millimetres, with this many to a drawing unit.
+	Millimetres(f32),
+	/// Inches, with this many to a drawing unit.
+	Inches(f32),
+}
+
+impl Units {
+	fn size(self, length:u32) -> String {
+		match self {
+			Units::Pixels => length.to_string(),
+			Units::Millimetres(per_unit) => format!("{}mm", trimmed(length as f32 * per_unit, 3)),
+			Units::Inches(per_unit) => format!("{}in", trimmed(length as f32 * per_unit, 4)),
+		}
+	}
+}
+
+/// How the drawing looks, and how its SVG is written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+	/// In drawing units, like everything else in the SVG.
+	pub stroke_width: f32,
+	pub stroke: Rgb,
+	/// What the canvas is filled with underneath, if anything.
+	pub background: Option<Rgb>,
+	pub units: Units,
+	/// Decimal places for each coordinate.
+	pub precision: usize,
+}
+
+impl Default for Style {
+	fn default() -> Self {
+		Style { stroke_width: 1f32, stroke: (0, 0, 0), background: None, units: Units::Pixels, precision: 2 }
+	}
+}
+
+/// `value` to `places` decimal places, less the zeros on the end.
+fn trimmed(value:f32, places:usize) -> String {
+	let text = format!("{:.*}", places, value);
+	if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.').to_string() } else { text }
+}
+
+impl Style {
+	fn attributes(&self) -> String {
+		format!(r#"fill="none" stroke="{}" stroke-width="{}""#, hex(self.stroke), self.stroke_width)
+	}
+}
+
+const COLOR_NAMES:[(&str, Rgb); 10] = [
+	("black", (0, 0, 0)),
+	("white", (255, 255, 255)),
+	("gray", (128, 128, 128)),
+	("grey", (128, 128, 128)),
+	("red", (255, 0, 0)),
+	("green", (0, 128, 0)),
+	("blue", (0, 0, 255)),
+	("navy", (0, 0, 128)),
+	("brown", (165, 42, 42)),
+	("orange", (255, 165, 0)),
+];
+
+/// A colour as `#rrggbb`, `#rgb`, or one of a few names that pens come in.
+pub fn parse_color(text:&str) -> Option<Rgb> {
+	let text = text.trim().to_ascii_lowercase();
+	if let Some(&(_, color)) = COLOR_NAMES.iter().find(|&&(name, _)| name == text) {
+		return Some(color);
+	}
+	let digits = text.strip_prefix('#')?;
+	let channel = |hex:&str| u8::from_str_radix(hex, 16).ok();
+	match digits.len() {
+		6 => Some((channel(&digits[0..2])?, channel(&digits[2..4])?, channel(&digits[4..6])?)),
+		3 => {
+			let short = |i:usize| channel(&digits[i..i+1]).map(|v| v * 17);
+			Some((short(0)?, short(1)?, short(2)?))
+		},
+		_ => None,
+	}
+}
+
+fn hex(color:Rgb) -> String {
+	format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+/// The opening of an SVG document, with the background if there is one.
+fn svg_header(canvas_width:u32, canvas_height:u32, style:&Style) -> String {
+	let mut svg = format!(r#"<svg width="{}" height="{}" viewBox="0 0 {} {}" xmlns="http://www.w3.org/2000/svg">"#, style.units.size(canvas_width), style.units.size(canvas_height), canvas_width, canvas_height);
+	svg.push('\n');
+	if let Some(background) = style.background {
+		svg.push_str(&format!(r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(background)));
+		svg.push('\n');
+	}
+	svg
+}
+
+/// Draw each path as straight lines into an SVG, and dump the points alongside it with `write_raw`.
+pub fn draw_image(paths:&[Polyline], filename:&str, canvas_width:u32, canvas_height:u32, style:&Style) -> Result<()> {
+	write_paths(paths, filename, canvas_width, canvas_height, style, |path| path_data(path, false, style.precision)).map_err(|e| Error::io(filename, e))?;
+	write_raw(paths, filename)
+}
+
+/// The whole SVG document as text, one `<path>` per path, drawn as straight segments or as smooth curves.
+pub fn svg_text(paths:&[Polyline], canvas_width:u32, canvas_height:u32, curves:bool, style:&Style) -> String {
+	let mut svg = svg_header(canvas_width, canvas_height, style);
+	for path in paths.iter().filter(|path| path.len() > 1) {
+		svg.push_str(&format!(r#"<path {} d="{}"/>"#, style.attributes(), path_data(path, curves, style.precision)));
+		svg.push('\n');
+	}
+	svg.push_str("</svg>\n");
+	svg
+}
+
+fn path_data(path:&Polyline, curves:bool, precision:usize) -> String {
+	if curves {
+		return crate::bezier::svg_path_data(&crate::bezier::through(&path.points), precision);
+	}
+	let mut data = String::new();
+	for (i, p) in path.points.iter().enumerate() {
+		data.push_str(&format!("{}{:.*} {:.*}", if i == 0 { "M" } else { " L" }, precision, p.x, precision, p.y));
+	}
+	data
+}
+
+/// Draw each path as a smooth Bézier curve through its points.
+pub fn draw_curves(paths:&[Polyline], filename:&str, canvas_width:u32, canvas_height:u32, style:&Style) -> Result<()> {
+	write_paths(paths, filename, canvas_width, canvas_height, style, |path| path_data(path, true, style.precision)).map_err(|e| Error::io(filename, e))?;
+	write_raw(paths, filename)
+}
+
+/// Draw each path with circular arcs fitted to it within `tolerance`, and lines where none fit.
+pub fn draw_arcs(paths:&[Polyline], filename:&str, canvas_width:u32, canvas_height:u32, tolerance:f32, style:&Style) -> Result<()> {
+	write_paths(paths, filename, canvas_width, canvas_height, style, |path| crate::arcs::svg_path_data(&path.points, tolerance, style.precision)).map_err(|e| Error::io(filename, e))?;
+	write_raw(paths, filename)
+}
+
+fn write_paths<F:Fn(&Polyline) -> String>(paths:&[Polyline], filename:&str, canvas_width:u32, canvas_height:u32, style:&Style, data:F) -> std::io::Result<()> {
+	write_groups(&[Group { id: String::new(), paths }], filename, canvas_width, canvas_height, style, data)
+}
+
+/// A set of paths written as an SVG group of its own, so it can be styled, hidden or plotted apart from the rest.
+pub struct Group<'a> {
+	/// The group's `id`.  Paths in a group without one go straight into the SVG.
+	pub id: String,
+	pub paths: &'a [Polyline],
+}
+
+/// Draw each group into the one SVG as a `<g>`, as straight lines, curves, or with arcs fitted within `arc_tolerance`,
+/// and dump all their points alongside it with `write_raw`.
+pub fn draw_groups(groups:&[Group], filename:&str, canvas_width:u32, canvas_height:u32, curves:bool, arc_tolerance:Option<f32>, style:&Style) -> Result<()> {
+	let data = |path:&Polyline| match arc_tolerance {
+		Some(tolerance) => crate::arcs::svg_path_data(&path.points, tolerance, style.precision),
+		None => path_data(path, curves, style.precision),
+	};
+	write_groups(groups, filename, canvas_width, canvas_height, style, data).map_err(|e| Error::io(filename, e))?;
+	write_raw(&groups.iter().flat_map(|group| group.paths.iter().cloned()).collect::<Vec<Polyline>>(), filename)
+}
+
+fn write_groups<F:Fn(&Polyline) -> String>(groups:&[Group], filename:&str, canvas_width:u32, canvas_height:u32, style:&Style, data:F) -> std::io::Result<()> {
+	let mut out = create(filename)?;
+	out.write_all(svg_header(canvas_width, canvas_height, style).as_bytes())?;
+	let bar = ProgressBar::new("Writing", groups.iter().map(|group| group.paths.len() as u64).sum());
+	for group in groups {
+		if !group.id.is_empty() {
+			writeln!(out, r#"<g id="{}">"#, group.id)?;
+		}
+		for path in group.paths {
+			if path.len() > 1 {
+				writeln!(out, r#"<path {} d="{}"/>"#, style.attributes(), data(path))?;
+			}
+			bar.inc(1);
+		}
+		if !group.id.is_empty() {
+			writeln!(out, "</g>")?;
+		}
+	}
+	writeln!(out, "</svg>")?;
+	out.flush()?;
+	bar.finish();
+	Ok(())
+}
+
+/// Whether `filename` is for a machine rather than a picture, going by its extension.  Standard output has none, so
+/// it always gets an SVG.
+pub fn is_gcode(filename:&str) -> bool {
+	let extension = std::path::Path::new(filename).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+	matches!(extension.as_deref(), Some("gcode") | Some("nc") | Some("ngc"))
+}
+
+/// Write the paths as a G-code program for the machine `profile` describes.
+pub fn draw_gcode(paths:&[Polyline], filename:&str, profile:&crate::gcode::Profile, canvas_width:u32, canvas_height:u32) -> Result<()> {
+	create(filename).and_then(|mut out| {
+		out.write_all(crate::gcode::gcode_text(paths, profile, canvas_width, canvas_height).as_bytes())?;
+		out.flush()
+	}).map_err(|e| Error::io(filename, e))?;
+	write_raw(paths, filename)
+}
+
+/// Write `lines` into a drawing that's already been saved: as a `<metadata>` element at the top of an SVG, or as
+/// comments at the top of G-code.  A drawing sent to standard output is gone by now, so it goes without.
+pub fn add_metadata(filename:&str, lines:&[String]) -> Result<()> {
+	if filename == STDOUT {
+		return Ok(());
+	}
+	let text = std::fs::read_to_string(filename).map_err(|e| Error::io(filename, e))?;
+	let text = with_metadata(&text, lines, is_gcode(filename));
+	std::fs::write(filename, text).map_err(|e| Error::io(filename, e))
+}
+
+fn with_metadata(text:&str, lines:&[String], gcode:bool) -> String {
+	if gcode {
+		return lines.iter().map(|line| format!("; {}\n", line)).collect::<String>() + text;
+	}
+	// Just inside the root element, after the end of the `<svg ...>` tag.
+	let at = match text.find("<svg").and_then(|start| text[start..].find('>').map(|end| start + end + 1)) {
+		Some(at) => at,
+		None => return text.to_string(),
+	};
+	format!("{}\n<metadata>\n{}\n</metadata>{}", &text[..at], lines.join("\n"), &text[at..])
+}
+
+/// The file for one pen's layer of a drawing: `portrait.svg` becomes `portrait_pen2.svg` for the second pen.
+pub fn layer_filename(filename:&str, pen:usize) -> String {
+	with_suffix(filename, &format!("_pen{}", pen))
+}
+
+/// The file for one chunk of a drawing split up to plot a bit at a time: `portrait_part3.svg` for the third.
+pub fn chunk_filename(filename:&str, chunk:usize) -> String {
+	with_suffix(filename, &format!("_part{}", chunk))
+}
+
+fn with_suffix(filename:&str, suffix:&str) -> String {
+	let path = std::path::Path::new(filename);
+	let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+	let name = match path.extension() {
+		Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+		None => format!("{}{}", stem, suffix),
+	};
+	path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// An image to show faintly under a drawing.
+pub struct Backdrop<'a> {
+	pub image: &'a GrayImage,
+	/// The transform that took the paths from the image onto the canvas, which the image gets too.
+	pub placement: Transform2D,
+	pub opacity: f32,
+}
+
+/// Draw the paths over a backdrop of the image they came from, for seeing how well the lines follow it before
+/// plotting.
+pub fn draw_overlay(paths:&[Polyline], filename:&str, canvas_width:u32, canvas_height:u32, style:&Style, backdrop:&Backdrop) -> Result<()> {
+	let Backdrop { image, placement, opacity } = backdrop;
+	let mut png = vec![];
+	image::png::PngEncoder::new(&mut png).encode(image.as_raw(), image.width(), image.height(), image::ColorType::L8)
+		.map_err(|e| Error::Output { path: filename.to_string(), message: e.to_string() })?;
+	create(filename).and_then(|mut out| {
+		out.write_all(svg_header(canvas_width, canvas_height, style).as_bytes())?;
+		writeln!(out, r#"<image width="{}" height="{}" opacity="{}" transform="{}" href="data:image/png;base64,{}"/>"#, image.width(), image.height(), opacity, placement.svg_matrix(), base64(&png))?;
+		for path in paths.iter().filter(|path| path.len() > 1) {
+			writeln!(out, r#"<path {} d="{}"/>"#, style.attributes(), path_data(path, false, style.precision))?;
+		}
+		writeln!(out, "</svg>")?;
+		out.flush()
+	}).map_err(|e| Error::io(filename, e))
+}
+
+fn base64(bytes:&[u8]) -> String {
+	const ALPHABET:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+	for chunk in bytes.chunks(3) {
+		let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+		for i in 0..4 {
+			if i <= chunk.len() {
+				text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+			} else {
+				text.push('=');
+			}
+		}
+	}
+	text
+}
+
+/// The file for the preview of a drawing over its image: `portrait_overlay.svg`.
+pub fn overlay_filename(filename:&str) -> String {
+	with_suffix(filename, "_overlay")
+}
+
+/// The file for the stats of a render, in place of the drawing's own extension: `portrait.stats.json`.
+pub fn stats_filename(filename:&str) -> String {
+	std::path::Path::new(filename).with_extension("stats.json").to_string_lossy().into_owned()
+}
+
+/// Dump the points next to the drawing, one `x,y` per line with a blank line between paths.
+/// A drawing sent to standard output has nowhere beside it, so it gets no dump.
+pub fn write_raw(paths:&[Polyline], filename:&str) -> Result<()> {
+	if filename == STDOUT {
+		return Ok(());
+	}
+	// The prefix goes on the file's own name, so the dump lands beside a drawing in another directory.
+	let path = std::path::Path::new(filename);
+	let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+	let raw_filename = path.with_file_name(format!("raw_{}", name)).to_string_lossy().into_owned();
+	write_points(paths, &raw_filename).map_err(|e| Error::io(raw_filename, e))
+}
+
+fn write_points(paths:&[Polyline], filename:&str) -> std::io::Result<()> {
+	let mut fout = BufWriter::new(File::create(filename)?);
+	for (i, points) in paths.iter().enumerate() {
+		if i > 0 {
+			fout.write_all(b"\n")?;
+		}
+		for p in &points.points {
+			fout.write_all(format!("{},{}\n", p.x, p.y).as_ref())?;
+		}
+	}
+	fout.flush()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::point::Point;
+
+	#[test]
+	fn test_layer_filename() {
+		assert_eq!(stats_filename("out/portrait.svg"), "out/portrait.stats.json");
+		assert_eq!(layer_filename("portrait.svg", 2), "portrait_pen2.svg");
+		assert_eq!(layer_filename("/tmp/out/portrait", 1), "/tmp/out/portrait_pen1");
+		assert_eq!(chunk_filename(&layer_filename("portrait.gcode", 1), 3), "portrait_pen1_part3.gcode");
+	}
+
+	#[test]
+	fn test_svg_text() {
+		let paths = vec![Polyline::new(vec![Point::new(1f32, 2f32), Point::new(3.5f32, 4f32)]), Polyline::new(vec![Point::new(0f32, 0f32)])];
+		let svg = svg_text(&paths, 10, 20, false, &Style { stroke_width: 0.5f32, ..Style::default() });
+		assert_eq!(svg.lines().nth(1), Some(r##"<path fill="none" stroke="#000000" stroke-width="0.5" d="M1.00 2.00 L3.50 4.00"/>"##));
+		assert_eq!(svg.lines().count(), 3);
+		let white_on_black = Style { stroke: parse_color("white").unwrap(), background: parse_color("#000"), ..Style::default() };
+		let svg = svg_text(&paths, 10, 20, false, &white_on_black);
+		assert_eq!(svg.lines().nth(1), Some(r##"<rect width="100%" height="100%" fill="#000000"/>"##));
+		assert!(svg.contains(r##"stroke="#ffffff""##));
+		assert_eq!(parse_color("#1a2B3c"), Some((0x1a, 0x2b, 0x3c)));
+		let on_paper = Style { units: Units::Millimetres(0.25f32), precision: 1, ..Style::default() };
+		let svg = svg_text(&paths, 10, 30, false, &on_paper);
+		assert!(svg.starts_with(r#"<svg width="2.5mm" height="7.5mm" viewBox="0 0 10 30""#));
+		assert!(svg.contains(r#"d="M1.0 2.0 L3.5 4.0""#));
+		assert_eq!(parse_color("chartreuse"), None);
+	}
+
+	#[test]
+	fn test_base64() {
+		assert_eq!(base64(b""), "");
+		assert_eq!(base64(b"f"), "Zg==");
+		assert_eq!(base64(b"fo"), "Zm8=");
+		assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+		assert_eq!(overlay_filename("out/portrait.svg"), "out/portrait_overlay.svg");
+	}
+
+	#[test]
+	fn test_with_metadata() {
+		let lines = vec!["Paths: 2".to_string(), "Estimated plot time: 0:01:40".to_string()];
+		let svg = with_metadata("<svg width=\"4\">\n<path/>\n</svg>\n", &lines, false);
+		assert_eq!(svg, "<svg width=\"4\">\n<metadata>\nPaths: 2\nEstimated plot time: 0:01:40\n</metadata>\n<path/>\n</svg>\n");
+		assert_eq!(with_metadata("G21\n", &lines, true), "; Paths: 2\n; Estimated plot time: 0:01:40\nG21\n");
+	}
+}
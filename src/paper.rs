@@ -0,0 +1,125 @@
+use crate::transform::Transform2D;
+
+/// SVG's own unit, the CSS pixel, is a 96th of an inch.
+pub const PX_PER_MM:f32 = 96f32 / 25.4f32;
+
+/// Sheet sizes by name, in millimetres, portrait.
+pub const PAPER_SIZES:[(&str, (f32, f32)); 6] = [
+	("a5", (148f32, 210f32)),
+	("a4", (210f32, 297f32)),
+	("a3", (297f32, 420f32)),
+	("a2", (420f32, 594f32)),
+	("letter", (215.9f32, 279.4f32)),
+	("legal", (215.9f32, 355.6f32)),
+];
+
+/// A sheet by name, or as `WIDTHxHEIGHT` in millimetres for anything else.  A named sheet comes portrait; give the
+/// size the other way round for landscape.
+pub fn paper_size(name:&str) -> Option<(f32, f32)> {
+	let name = name.to_ascii_lowercase();
+	if let Some(&(_, size)) = PAPER_SIZES.iter().find(|&&(paper, _)| paper == name) {
+		return Some(size);
+	}
+	let (width, height) = name.split_once('x')?;
+	match (width.trim().parse::<f32>(), height.trim().parse::<f32>()) {
+		(Ok(width), Ok(height)) if width > 0f32 && height > 0f32 => Some((width, height)),
+		_ => None,
+	}
+}
+
+/// Which way round the artwork goes on the sheet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+	/// Turned a quarter turn if that lets it be drawn bigger.
+	Auto,
+	/// Long side up the sheet.
+	Portrait,
+	/// Long side across the sheet.
+	Landscape,
+}
+
+impl std::str::FromStr for Orientation {
+	type Err = ();
+
+	fn from_str(s:&str) -> Result<Self, ()> {
+		match s {
+			"auto" => Ok(Orientation::Auto),
+			"portrait" => Ok(Orientation::Portrait),
+			"landscape" => Ok(Orientation::Landscape),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Whether a `drawing` wide by high should be turned a quarter turn to go onto a sheet `paper` wide by high.
+pub fn should_rotate(drawing:(f32, f32), paper:(f32, f32), orientation:Orientation) -> bool {
+	let (width, height) = drawing;
+	match orientation {
+		Orientation::Auto => (paper.0 / height).min(paper.1 / width) > (paper.0 / width).min(paper.1 / height),
+		Orientation::Portrait => (width > height) != (paper.0 > paper.1),
+		Orientation::Landscape => (height > width) != (paper.0 > paper.1),
+	}
+}
+
+/// Move a `drawing` wide by high onto the middle of a canvas `canvas` wide by high, turned a quarter turn if
+/// `rotate` says so and scaled to fill as much of it as it can without stretching.
+pub fn fit(drawing:(f32, f32), canvas:(f32, f32), rotate:bool) -> Transform2D {
+	let (width, height) = if rotate { (drawing.1, drawing.0) } else { drawing };
+	let scale = (canvas.0 / width).min(canvas.1 / height);
+	let turn = if rotate { Transform2D::rotate(std::f32::consts::FRAC_PI_2) } else { Transform2D::identity() };
+	Transform2D::translate(-drawing.0 / 2f32, -drawing.1 / 2f32)
+		.then(turn)
+		.then(Transform2D::scale(scale, scale))
+		.then(Transform2D::translate(canvas.0 / 2f32, canvas.1 / 2f32))
+}
+
+/// Shrink everything on a canvas `canvas` wide by high, keeping its shape, to leave `margin` clear all round.
+pub fn inset(canvas:(f32, f32), margin:f32) -> Transform2D {
+	fit(canvas, (canvas.0 - 2f32 * margin, canvas.1 - 2f32 * margin), false).then(Transform2D::translate(margin, margin))
+}
+
+/// A length like `12mm`, `0.5in` or `20px`, in pixels at `px_per_mm`.  A bare number is in pixels.
+pub fn parse_length(text:&str, px_per_mm:f32) -> Option<f32> {
+	let text = text.trim();
+	let (number, per_unit) = if let Some(mm) = text.strip_suffix("mm") {
+		(mm, px_per_mm)
+	} else if let Some(inches) = text.strip_suffix("in") {
+		(inches, 25.4f32 * px_per_mm)
+	} else {
+		(text.strip_suffix("px").unwrap_or(text), 1f32)
+	};
+	number.trim().parse::<f32>().ok().filter(|length| *length >= 0f32).map(|length| length * per_unit)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::point::Point;
+
+	#[test]
+	fn test_orientation_and_fit() {
+		assert_eq!(paper_size("A4"), Some((210f32, 297f32)));
+		assert_eq!(paper_size("300x200"), Some((300f32, 200f32)));
+		assert_eq!(paper_size("huge"), None);
+		// A wide picture on a tall sheet goes on sideways, unless it's told to stay landscape on portrait paper.
+		assert!(should_rotate((400f32, 300f32), (210f32, 297f32), Orientation::Auto));
+		assert!(!should_rotate((300f32, 400f32), (210f32, 297f32), Orientation::Auto));
+		assert!(should_rotate((300f32, 400f32), (210f32, 297f32), Orientation::Landscape));
+		assert!(!should_rotate((400f32, 300f32), (210f32, 297f32), Orientation::Landscape));
+		let transform = fit((400f32, 300f32), (300f32, 400f32), true);
+		let corner = transform.apply(Point::new(0f32, 0f32));
+		assert!((corner - Point::new(300f32, 0f32)).length() < 1e-3f32);
+	}
+
+	#[test]
+	fn test_inset() {
+		assert_eq!(parse_length("10mm", 2f32), Some(20f32));
+		assert_eq!(parse_length("1in", 2f32), Some(50.8f32));
+		assert_eq!(parse_length("7", 2f32), parse_length("7px", 2f32));
+		assert_eq!(parse_length("wide", 2f32), None);
+		// A wide canvas keeps its shape, so it's the top and bottom that set how much it shrinks.
+		let transform = inset((200f32, 100f32), 10f32);
+		assert!((transform.apply(Point::new(0f32, 0f32)) - Point::new(20f32, 10f32)).length() < 1e-4f32);
+		assert!((transform.apply(Point::new(200f32, 100f32)) - Point::new(180f32, 90f32)).length() < 1e-4f32);
+	}
+}
@@ -1,43 +1,165 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
 use std::ops;
 
+/// The number types a `Point` can be made of.
+pub trait Scalar: Copy + Debug + Default + PartialOrd + ops::Add<Output=Self> + ops::Sub<Output=Self> + ops::Mul<Output=Self> + ops::Div<Output=Self> + ops::Neg<Output=Self> {
+	/// Convert a constant into this type.
+	fn of(value:f64) -> Self;
+	fn to_f64(self) -> f64;
+	fn abs(self) -> Self;
+	fn sqrt(self) -> Self;
+	fn sin_cos(self) -> (Self, Self);
+}
+
+impl Scalar for f32 {
+	fn of(value:f64) -> Self {
+		value as f32
+	}
+
+	fn to_f64(self) -> f64 {
+		self as f64
+	}
+
+	fn abs(self) -> Self {
+		f32::abs(self)
+	}
+
+	fn sqrt(self) -> Self {
+		f32::sqrt(self)
+	}
+
+	fn sin_cos(self) -> (Self, Self) {
+		f32::sin_cos(self)
+	}
+}
+
+impl Scalar for f64 {
+	fn of(value:f64) -> Self {
+		value
+	}
+
+	fn to_f64(self) -> f64 {
+		self
+	}
+
+	fn abs(self) -> Self {
+		f64::abs(self)
+	}
+
+	fn sqrt(self) -> Self {
+		f64::sqrt(self)
+	}
+
+	fn sin_cos(self) -> (Self, Self) {
+		f64::sin_cos(self)
+	}
+}
+
+/// A point or vector in the plane, single precision unless asked otherwise.
 #[derive(Copy, Clone, Debug, Default)]
-pub struct Point {
-	pub x: f32,
-	pub y: f32,
+pub struct Point<T = f32> {
+	pub x: T,
+	pub y: T,
+}
+
+/// A double precision point, for the long chains of sums where f32 starts to drift.
+pub type DPoint = Point<f64>;
+
+/// A point snapped to a grid, so it can go in a hash set or be sorted.  See `Point::to_key`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PointKey {
+	pub x: i64,
+	pub y: i64,
 }
 
-impl Point {
-	pub fn new(x:f32, y:f32) -> Self {
+impl<T:Scalar> Point<T> {
+	pub fn new(x:T, y:T) -> Self {
 		Point {
 			x, y
 		}
 	}
+
+	pub fn dot(self, other:Point<T>) -> T {
+		self.x*other.x + self.y*other.y
+	}
+
+	/// The z part of the 3D cross product.  Positive if `other` is counterclockwise from `self`.
+	pub fn cross(self, other:Point<T>) -> T {
+		self.x*other.y - self.y*other.x
+	}
+
+	pub fn length(self) -> T {
+		self.dot(self).sqrt()
+	}
+
+	/// The same direction with length one.  Zero stays zero.
+	pub fn normalize(self) -> Point<T> {
+		let length = self.length();
+		if length > T::default() { self / length } else { self }
+	}
+
+	/// Rotated counterclockwise by `angle` radians.
+	pub fn rotate(self, angle:T) -> Point<T> {
+		let (sin, cos) = angle.sin_cos();
+		Point::new(self.x*cos - self.y*sin, self.x*sin + self.y*cos)
+	}
+
+	/// The point `t` of the way from `self` to `other`.
+	pub fn lerp(self, other:Point<T>, t:T) -> Point<T> {
+		self + (other - self) * t
+	}
+
+	/// Rotated a quarter turn counterclockwise: the left-hand normal of a direction.
+	pub fn perp(self) -> Point<T> {
+		Point::new(-self.y, self.x)
+	}
+
+	/// Snap to the nearest corner of a grid `epsilon` on a side.  Points closer than `epsilon` usually share a key,
+	/// but two on either side of a grid line won't.
+	pub fn to_key(self, epsilon:T) -> PointKey {
+		let snap = |v:T| (v.to_f64() / epsilon.to_f64()).round() as i64;
+		PointKey { x: snap(self.x), y: snap(self.y) }
+	}
+
+	/// The same point at another precision.
+	pub fn cast<U:Scalar>(self) -> Point<U> {
+		Point {
+			x: U::of(self.x.to_f64()), y: U::of(self.y.to_f64())
+		}
+	}
 }
 
-impl PartialEq for Point {
+/// The points with repeats dropped, keeping the first of each that land on the same `epsilon` grid key.
+pub fn dedup<T:Scalar>(points:&[Point<T>], epsilon:T) -> Vec<Point<T>> {
+	let mut seen = HashSet::new();
+	points.iter().filter(|p| seen.insert(p.to_key(epsilon))).copied().collect()
+}
+
+impl<T:Scalar> PartialEq for Point<T> {
 	fn eq(&self, other: &Self) -> bool {
-		(self.x - other.x).abs() + (self.y - other.y).abs() < 1e-8f32
+		(self.x - other.x).abs() + (self.y - other.y).abs() < T::of(1e-8)
 	}
 }
 
-impl From<(f32, f32)> for Point {
-	fn from(p: (f32, f32)) -> Self {
+impl<T> From<(T, T)> for Point<T> {
+	fn from(p: (T, T)) -> Self {
 		Point {
 			x: p.0, y: p.1
 		}
 	}
 }
 
-impl From<Point> for (f32, f32) {
-	fn from(p: Point) -> Self {
+impl<T> From<Point<T>> for (T, T) {
+	fn from(p: Point<T>) -> Self {
 		(p.x, p.y)
 	}
 }
 
-impl ops::Add<Point> for Point {
-	type Output = Point;
+impl<T:Scalar> ops::Add<Point<T>> for Point<T> {
+	type Output = Point<T>;
 
-	fn add(self, rhs: Point) -> Point {
+	fn add(self, rhs: Point<T>) -> Point<T> {
 		Point {
 			x: self.x + rhs.x,
 			y: self.y + rhs.y,
@@ -45,10 +167,10 @@ impl ops::Add<Point> for Point {
 	}
 }
 
-impl ops::Sub<Point> for Point {
-	type Output = Point;
+impl<T:Scalar> ops::Sub<Point<T>> for Point<T> {
+	type Output = Point<T>;
 
-	fn sub(self, rhs: Point) -> Point {
+	fn sub(self, rhs: Point<T>) -> Point<T> {
 		Point {
 			x: self.x-rhs.x,
 			y: self.y-rhs.y,
@@ -56,9 +178,9 @@ impl ops::Sub<Point> for Point {
 	}
 }
 
-impl ops::Mul<f32> for Point {
-	type Output = Point;
-	fn mul(self, rhs: f32) -> Point {
+impl<T:Scalar> ops::Mul<T> for Point<T> {
+	type Output = Point<T>;
+	fn mul(self, rhs: T) -> Point<T> {
 		Point {
 			x: self.x*rhs,
 			y: self.y*rhs,
@@ -66,6 +188,38 @@ impl ops::Mul<f32> for Point {
 	}
 }
 
+impl<T:Scalar> ops::Div<T> for Point<T> {
+	type Output = Point<T>;
+	fn div(self, rhs: T) -> Point<T> {
+		Point {
+			x: self.x/rhs,
+			y: self.y/rhs,
+		}
+	}
+}
+
+impl<T:Scalar> ops::Neg for Point<T> {
+	type Output = Point<T>;
+	fn neg(self) -> Point<T> {
+		Point {
+			x: -self.x,
+			y: -self.y,
+		}
+	}
+}
+
+impl<T:Scalar> ops::AddAssign<Point<T>> for Point<T> {
+	fn add_assign(&mut self, rhs: Point<T>) {
+		*self = *self + rhs;
+	}
+}
+
+impl<T:Scalar> ops::SubAssign<Point<T>> for Point<T> {
+	fn sub_assign(&mut self, rhs: Point<T>) {
+		*self = *self - rhs;
+	}
+}
+
 impl ops::Mul<Point> for f32 {
 	type Output = Point;
 	fn mul(self, rhs: Point) -> Point {
@@ -74,4 +228,53 @@ impl ops::Mul<Point> for f32 {
 			y: self*rhs.y,
 		}
 	}
-}
\ No newline at end of file
+}
+
+impl ops::Mul<DPoint> for f64 {
+	type Output = DPoint;
+	fn mul(self, rhs: DPoint) -> DPoint {
+		Point {
+			x: self*rhs.x,
+			y: self*rhs.y,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_double_precision_sums() {
+		// Far from the origin, a long chain of small f32 steps lands somewhere else than the f64 one does.
+		let start = Point::new(20_000.3f32, 0f32);
+		let step = Point::new(0.1f32, 0f32);
+		let single = (0..1000).fold(start, |p, _| p + step);
+		let double = (0..1000).fold(start.cast::<f64>(), |p, _| p + step.cast());
+		assert!((double.x - (20_000.3f64 + 100f64)).abs() < 1e-2f64);
+		assert!(single != double.cast());
+	}
+
+	#[test]
+	fn test_keys_and_dedup() {
+		assert_eq!(Point::new(1.04f32, -2.96f32).to_key(0.1f32), PointKey { x: 10, y: -30 });
+		let points = vec![Point::new(0f32, 0f32), Point::new(1f32, 1f32), Point::new(0.001f32, 0f32), Point::new(1f32, 1f32)];
+		assert_eq!(dedup(&points, 0.01f32), vec![Point::new(0f32, 0f32), Point::new(1f32, 1f32)]);
+	}
+
+	#[test]
+	fn test_vector_ops() {
+		let v = Point::new(3f32, 4f32);
+		assert_eq!(v.length(), 5f32);
+		assert_eq!(v.normalize(), Point::new(0.6f32, 0.8f32));
+		assert_eq!(v.perp(), Point::new(-4f32, 3f32));
+		assert_eq!(v.dot(v.perp()), 0f32);
+		assert_eq!(v.cross(v.perp()), 25f32);
+		assert!((v.rotate(std::f32::consts::FRAC_PI_2) - v.perp()).length() < 1e-5f32);
+		assert_eq!(v.lerp(-v, 0.5f32), Point::default());
+		let mut w = v;
+		w += v;
+		w -= v / 2f32;
+		assert_eq!(w, Point::new(4.5f32, 6f32));
+	}
+}
@@ -0,0 +1,263 @@
+use crate::point::Point;
+use crate::polyline::Polyline;
+use std::io::{self, Write};
+
+/// Read a list of points from text in either of two forms:
+/// CSV, one `x,y` (or `x y`) per line, which is what the raw dump next to each SVG holds.  Blank lines, `#` comments
+/// and a header line are skipped.
+/// JSON, an array of `[x, y]` pairs or of `{"x": .., "y": ..}` objects.
+pub fn parse_points(text:&str) -> io::Result<Vec<(f32, f32)>> {
+	if text.trim_start().starts_with('[') {
+		parse_json(text)
+	} else {
+		parse_csv(text)
+	}
+}
+
+fn invalid(msg:String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_csv(text:&str) -> io::Result<Vec<(f32, f32)>> {
+	let mut points = vec![];
+	let mut seen_line = false;
+	for (number, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let mut fields = line.split(|c:char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty());
+		let x = fields.next().and_then(|f| f.parse::<f32>().ok());
+		let y = fields.next().and_then(|f| f.parse::<f32>().ok());
+		match (x, y) {
+			(Some(x), Some(y)) => points.push((x, y)),
+			// The first line may be a header like "x,y".
+			_ if !seen_line => {},
+			_ => return Err(invalid(format!("line {} isn't an x,y pair: {}", number + 1, line))),
+		}
+		seen_line = true;
+	}
+	Ok(points)
+}
+
+fn parse_json(text:&str) -> io::Result<Vec<(f32, f32)>> {
+	let points:Vec<Point> = from_json(text)?;
+	Ok(points.into_iter().map(|p| p.into()).collect())
+}
+
+/// Things that can be written out as JSON.  The crate doesn't pull in serde, so the few types worth saving implement
+/// this and `FromJson` by hand: a point is `[x, y]`, a polyline is an array of points, and a Vec is an array.
+pub trait ToJson {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()>;
+}
+
+/// Things that can be read back from what `ToJson` writes.
+pub trait FromJson: Sized {
+	fn read_json(json:&mut Json) -> io::Result<Self>;
+}
+
+/// Read a whole document holding one `T`.
+pub fn from_json<T:FromJson>(text:&str) -> io::Result<T> {
+	let mut json = Json { text: text.as_bytes(), at: 0 };
+	let value = T::read_json(&mut json)?;
+	if json.peek().is_some() {
+		return Err(json.error("trailing characters"));
+	}
+	Ok(value)
+}
+
+impl ToJson for Point {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		write!(out, "[{}, {}]", self.x, self.y)
+	}
+}
+
+impl FromJson for Point {
+	/// Either `[x, y]` or `{"x": .., "y": ..}`.
+	fn read_json(json:&mut Json) -> io::Result<Self> {
+		json.point().map(Point::from)
+	}
+}
+
+impl ToJson for Polyline {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		self.points.write_json(out)
+	}
+}
+
+impl FromJson for Polyline {
+	fn read_json(json:&mut Json) -> io::Result<Self> {
+		Vec::<Point>::read_json(json).map(Polyline::new)
+	}
+}
+
+impl<T:ToJson> ToJson for [T] {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		out.write_all(b"[")?;
+		for (i, item) in self.iter().enumerate() {
+			if i > 0 {
+				out.write_all(b",\n")?;
+			}
+			item.write_json(out)?;
+		}
+		out.write_all(b"]")
+	}
+}
+
+impl<T:ToJson> ToJson for Vec<T> {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		self.as_slice().write_json(out)
+	}
+}
+
+impl<T:FromJson> FromJson for Vec<T> {
+	fn read_json(json:&mut Json) -> io::Result<Self> {
+		let mut items = vec![];
+		json.expect(b'[')?;
+		if json.peek() == Some(b']') {
+			json.at += 1;
+			return Ok(items);
+		}
+		loop {
+			items.push(T::read_json(json)?);
+			match json.next_byte() {
+				Some(b',') => continue,
+				Some(b']') => return Ok(items),
+				_ => return Err(json.error("expected , or ]")),
+			}
+		}
+	}
+}
+
+/// Just enough of a JSON reader for points and lists of them.
+pub struct Json<'a> {
+	text: &'a [u8],
+	at: usize,
+}
+
+impl Json<'_> {
+	fn error(&self, msg:&str) -> io::Error {
+		invalid(format!("{} at byte {}", msg, self.at))
+	}
+
+	fn peek(&mut self) -> Option<u8> {
+		while self.at < self.text.len() && self.text[self.at].is_ascii_whitespace() {
+			self.at += 1;
+		}
+		self.text.get(self.at).copied()
+	}
+
+	fn next_byte(&mut self) -> Option<u8> {
+		let byte = self.peek();
+		self.at += 1;
+		byte
+	}
+
+	fn expect(&mut self, byte:u8) -> io::Result<()> {
+		if self.next_byte() == Some(byte) {
+			Ok(())
+		} else {
+			Err(self.error(&format!("expected {}", byte as char)))
+		}
+	}
+
+	fn number(&mut self) -> io::Result<f32> {
+		self.peek();
+		let start = self.at;
+		while self.at < self.text.len() && matches!(self.text[self.at], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+			self.at += 1;
+		}
+		std::str::from_utf8(&self.text[start..self.at]).ok().and_then(|s| s.parse::<f32>().ok()).ok_or_else(|| self.error("expected a number"))
+	}
+
+	fn key(&mut self) -> io::Result<String> {
+		self.expect(b'"')?;
+		let start = self.at;
+		while self.at < self.text.len() && self.text[self.at] != b'"' {
+			self.at += 1;
+		}
+		let key = String::from_utf8_lossy(&self.text[start..self.at]).into_owned();
+		self.expect(b'"')?;
+		Ok(key)
+	}
+
+	fn point(&mut self) -> io::Result<(f32, f32)> {
+		match self.peek() {
+			Some(b'[') => {
+				self.at += 1;
+				let x = self.number()?;
+				self.expect(b',')?;
+				let y = self.number()?;
+				self.expect(b']')?;
+				Ok((x, y))
+			},
+			Some(b'{') => {
+				self.at += 1;
+				let (mut x, mut y) = (None, None);
+				loop {
+					let key = self.key()?;
+					self.expect(b':')?;
+					let value = self.number()?;
+					match key.as_str() {
+						"x" => x = Some(value),
+						"y" => y = Some(value),
+						_ => {},
+					}
+					match self.next_byte() {
+						Some(b',') => continue,
+						Some(b'}') => break,
+						_ => return Err(self.error("expected , or }")),
+					}
+				}
+				x.zip(y).ok_or_else(|| self.error("point needs both x and y"))
+			},
+			_ => Err(self.error("expected a point")),
+		}
+	}
+}
+
+/// Write points one `x,y` per line, the same as the raw dump.
+pub fn write_csv<W:Write>(points:&[(f32, f32)], out:&mut W) -> io::Result<()> {
+	for &(x, y) in points {
+		writeln!(out, "{},{}", x, y)?;
+	}
+	Ok(())
+}
+
+/// Write points as a JSON array of `[x, y]` pairs.
+pub fn write_json<W:Write>(points:&[(f32, f32)], out:&mut W) -> io::Result<()> {
+	let points:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+	points.write_json(out)?;
+	out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse_csv() {
+		let text = "x,y\n1,2\n\n# comment\n3.5 4\n";
+		assert_eq!(parse_points(text).unwrap(), vec![(1f32, 2f32), (3.5f32, 4f32)]);
+		assert!(parse_points("1,2\nthree,4\n").is_err());
+	}
+
+	#[test]
+	fn test_json_round_trip() {
+		let points = vec![(1f32, -2f32), (0.25f32, 1e3f32)];
+		let mut out = vec![];
+		write_json(&points, &mut out).unwrap();
+		assert_eq!(parse_points(std::str::from_utf8(&out).unwrap()).unwrap(), points);
+		assert_eq!(parse_points(r#"[{"y": 2, "x": 1}]"#).unwrap(), vec![(1f32, 2f32)]);
+	}
+
+	#[test]
+	fn test_paths_round_trip() {
+		let paths = vec![Polyline::from(vec![(0f32, 1f32), (2.5f32, -3f32)]), Polyline::default()];
+		let mut out = vec![];
+		paths.write_json(&mut out).unwrap();
+		let read:Vec<Polyline> = from_json(std::str::from_utf8(&out).unwrap()).unwrap();
+		assert_eq!(read, paths);
+		assert!(from_json::<Vec<Polyline>>("[[[1, 2]] junk").is_err());
+	}
+}
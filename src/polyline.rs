@@ -0,0 +1,193 @@
+use crate::point::Point;
+
+/// The most points resampling a whole drawing is allowed to make.  A spacing that's tiny next to the paths' length
+/// would otherwise make more than there's memory for.
+pub const MAX_RESAMPLED_POINTS:usize = 10_000_000;
+
+/// One unbroken stroke: the pen goes down at the first point and stays down until the last.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polyline {
+	pub points: Vec<Point>,
+}
+
+impl Polyline {
+	pub fn new(points:Vec<Point>) -> Self {
+		Polyline { points }
+	}
+
+	pub fn len(&self) -> usize {
+		self.points.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.points.is_empty()
+	}
+
+	/// Total length of the segments.
+	pub fn length(&self) -> f32 {
+		self.points.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum()
+	}
+
+	/// The corners (min, max) of the smallest axis-aligned box holding every point, or None if there are none.
+	pub fn bounding_box(&self) -> Option<(Point, Point)> {
+		let first = *self.points.first()?;
+		Some(self.points.iter().fold((first, first), |(lo, hi), p| {
+			(Point::new(lo.x.min(p.x), lo.y.min(p.y)), Point::new(hi.x.max(p.x), hi.y.max(p.y)))
+		}))
+	}
+
+	/// Draw the stroke from the other end.
+	pub fn reverse(&mut self) {
+		self.points.reverse();
+	}
+
+	/// Points spaced `spacing` apart along the stroke, starting at its first point and always keeping the last one.
+	pub fn resample(&self, spacing:f32) -> Polyline {
+		assert!(spacing > 0f32, "resampling needs a positive spacing");
+		let (first, last) = match (self.points.first(), self.points.last()) {
+			(Some(&first), Some(&last)) => (first, last),
+			_ => return Polyline::default(),
+		};
+		let mut result = vec![first];
+		// How far along the current segment the next sample falls.
+		let mut offset = spacing;
+		for pair in self.points.windows(2) {
+			let segment = (pair[1] - pair[0]).length();
+			while offset <= segment {
+				result.push(pair[0].lerp(pair[1], offset / segment));
+				offset += spacing;
+			}
+			offset -= segment;
+		}
+		if result.last() != Some(&last) {
+			result.push(last);
+		}
+		Polyline::new(result)
+	}
+
+	/// Break the stroke into dashes, one starting every `period` along it.  `duty` gives the fraction of the period
+	/// drawn from the point where the period starts, so it can change along the way; zero leaves a period blank.
+	pub fn dashes<F:Fn(Point) -> f32>(&self, period:f32, duty:F) -> Vec<Polyline> {
+		assert!(period > 0f32, "dashing needs a positive period");
+		let mut result = vec![];
+		let first = match self.points.first() {
+			Some(&first) => first,
+			None => return result,
+		};
+		let mut dash:Vec<Point> = vec![];
+		// How far into the current period we are, and how much of it is drawn.
+		let mut phase = 0f32;
+		let mut on = duty(first).clamp(0f32, 1f32) * period;
+		for pair in self.points.windows(2) {
+			let (a, b) = (pair[0], pair[1]);
+			let length = (b - a).length();
+			let mut along = 0f32;
+			while along < length {
+				let drawing = phase < on;
+				let step = (if drawing { on } else { period } - phase).min(length - along);
+				along += step;
+				phase += step;
+				let here = a.lerp(b, along / length);
+				if drawing {
+					if dash.is_empty() {
+						dash.push(a.lerp(b, (along - step) / length));
+					}
+					dash.push(here);
+					if phase >= on {
+						result.push(Polyline::new(std::mem::take(&mut dash)));
+					}
+				}
+				if phase >= period {
+					phase = 0f32;
+					on = duty(here).clamp(0f32, 1f32) * period;
+				}
+			}
+		}
+		if dash.len() > 1 {
+			result.push(Polyline::new(dash));
+		}
+		result
+	}
+
+	/// Drop points that land on the same `epsilon` grid key as the one before, which would only draw zero-length
+	/// segments.  Returns how many were dropped.
+	pub fn dedup(&mut self, epsilon:f32) -> usize {
+		let before = self.points.len();
+		self.points.dedup_by_key(|p| p.to_key(epsilon));
+		before - self.points.len()
+	}
+
+	/// Move every point through `f`.
+	pub fn transform<F:Fn(Point) -> Point>(&self, f:F) -> Polyline {
+		Polyline::new(self.points.iter().map(|&p| f(p)).collect())
+	}
+
+	/// Split into the stroke up to and including point `index` and the stroke from there on.  Both halves share that
+	/// point, so together they still draw the whole line.
+	pub fn split_at(&self, index:usize) -> (Polyline, Polyline) {
+		(Polyline::new(self.points[..=index].to_vec()), Polyline::new(self.points[index..].to_vec()))
+	}
+
+	/// Carry on drawing `other` from the end of this stroke.  If it starts where this one ends, the shared point isn't
+	/// repeated.
+	pub fn concat(&mut self, other:&Polyline) {
+		let skip = match (self.points.last(), other.points.first()) {
+			(Some(end), Some(start)) if end == start => 1,
+			_ => 0,
+		};
+		self.points.extend_from_slice(&other.points[skip..]);
+	}
+}
+
+impl From<Vec<Point>> for Polyline {
+	fn from(points:Vec<Point>) -> Self {
+		Polyline::new(points)
+	}
+}
+
+impl From<Vec<(f32, f32)>> for Polyline {
+	fn from(points:Vec<(f32, f32)>) -> Self {
+		Polyline::new(points.into_iter().map(|p| p.into()).collect())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_measure_and_resample() {
+		let line = Polyline::from(vec![(0f32, 0f32), (3f32, 0f32), (3f32, 4f32)]);
+		assert_eq!(line.length(), 7f32);
+		assert_eq!(line.bounding_box(), Some((Point::new(0f32, 0f32), Point::new(3f32, 4f32))));
+		let resampled = line.resample(2f32);
+		assert_eq!(resampled.points, vec![Point::new(0f32, 0f32), Point::new(2f32, 0f32), Point::new(3f32, 1f32), Point::new(3f32, 3f32), Point::new(3f32, 4f32)]);
+		assert!(Polyline::default().resample(1f32).is_empty());
+	}
+
+	#[test]
+	fn test_dashes() {
+		let line = Polyline::from(vec![(0f32, 0f32), (5f32, 0f32), (10f32, 0f32)]);
+		let ends = |dashes:Vec<Polyline>| dashes.iter().map(|d| (d.points[0].x, d.points[d.len()-1].x)).collect::<Vec<(f32, f32)>>();
+		assert_eq!(ends(line.dashes(4f32, |_| 0.5f32)), vec![(0f32, 2f32), (4f32, 6f32), (8f32, 10f32)]);
+		// A dash crossing a corner keeps the corner.
+		assert_eq!(line.dashes(4f32, |_| 0.5f32)[1].len(), 3);
+		// The duty is read where each period starts, so blank past x = 5 drops only the last dash.
+		assert_eq!(ends(line.dashes(4f32, |p| if p.x > 5f32 { 0f32 } else { 0.5f32 })), vec![(0f32, 2f32), (4f32, 6f32)]);
+	}
+
+	#[test]
+	fn test_split_and_concat() {
+		let line = Polyline::from(vec![(0f32, 0f32), (1f32, 0f32), (2f32, 0f32), (3f32, 1f32)]);
+		let (mut head, tail) = line.split_at(2);
+		assert_eq!((head.len(), tail.len()), (3, 2));
+		head.concat(&tail);
+		assert_eq!(head, line);
+		let mut doubled = Polyline::from(vec![(0f32, 0f32), (0f32, 0f32), (1f32, 0f32), (0f32, 0f32)]);
+		assert_eq!(doubled.dedup(0.01f32), 1);
+		assert_eq!(doubled.len(), 3);
+		let mut backwards = line.transform(|p| p * 2f32);
+		backwards.reverse();
+		assert_eq!(backwards.points[0], Point::new(6f32, 2f32));
+	}
+}
@@ -0,0 +1,167 @@
+use crate::point::Point;
+
+/// The most passes `--smooth-iterations` takes.  Chaikin doubles the points on every pass, so eight already makes 256
+/// of each one.
+pub const MAX_SMOOTH_ITERATIONS:u32 = 8;
+
+/// Chaikin corner cutting.  Each pass replaces every segment with points at 1/4 and 3/4 of the way along it, which
+/// rounds off corners while keeping the ends of the path where they were.
+pub fn chaikin(points:&[Point], iterations:u32) -> Vec<Point> {
+	let mut current = points.to_vec();
+	for _ in 0..iterations {
+		if current.len() < 3 {
+			break;
+		}
+		let mut next = Vec::with_capacity(current.len() * 2);
+		next.push(current[0]);
+		for pair in current.windows(2) {
+			next.push(pair[0]*0.75f32 + pair[1]*0.25f32);
+			next.push(pair[0]*0.25f32 + pair[1]*0.75f32);
+		}
+		next.push(*current.last().unwrap());
+		current = next;
+	}
+	current
+}
+
+/// Resample a path along a Catmull-Rom spline through its points, adding `samples` extra points per segment.
+/// Unlike Chaikin smoothing, the curve passes through every original point.
+pub fn catmull_rom(points:&[Point], samples:u32) -> Vec<Point> {
+	if points.len() < 3 || samples == 0 {
+		return points.to_vec();
+	}
+	let mut result = Vec::with_capacity(points.len() * (samples as usize + 1));
+	for i in 0..points.len()-1 {
+		// Repeat the end points so the first and last segments have neighbors.
+		let p0 = points[i.saturating_sub(1)];
+		let p1 = points[i];
+		let p2 = points[i+1];
+		let p3 = points[(i+2).min(points.len()-1)];
+		result.push(p1);
+		for s in 1..=samples {
+			let t = s as f32 / (samples + 1) as f32;
+			let t2 = t*t;
+			let t3 = t2*t;
+			result.push(0.5f32 * (
+				p1*2f32
+				+ (p2 - p0)*t
+				+ (p0*2f32 - p1*5f32 + p2*4f32 - p3)*t2
+				+ (p1*3f32 - p0 - p2*3f32 + p3)*t3
+			));
+		}
+	}
+	result.push(*points.last().unwrap());
+	result
+}
+
+/// Ramer-Douglas-Peucker simplification.  Drops every point that's within `tolerance` of the line through the points
+/// that are kept around it, so long straight runs collapse to their ends.
+pub fn simplify(points:&[Point], tolerance:f32) -> Vec<Point> {
+	if points.len() < 3 {
+		return points.to_vec();
+	}
+	let mut keep = vec![false; points.len()];
+	keep[0] = true;
+	keep[points.len()-1] = true;
+
+	// Long paths would blow the stack if this recursed, so keep our own list of spans to check.
+	let mut spans = vec![(0, points.len()-1)];
+	while let Some((start, end)) = spans.pop() {
+		let mut farthest = start;
+		let mut farthest_distance = 0f32;
+		for i in start+1..end {
+			let d = distance_to_segment(points[i], points[start], points[end]);
+			if d > farthest_distance {
+				farthest = i;
+				farthest_distance = d;
+			}
+		}
+		// Only a point strictly between the ends can split a span, or a span with none would split into itself forever.
+		if farthest > start && farthest_distance > tolerance {
+			keep[farthest] = true;
+			spans.push((start, farthest));
+			spans.push((farthest, end));
+		}
+	}
+
+	points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+/// Push every point a little way off by a smooth noise field, up to `amplitude` in each direction.  Points closer
+/// together than `wavelength` move nearly the same way, so lines wobble instead of fraying, like a wavering hand.
+pub fn jitter(points:&[Point], amplitude:f32, wavelength:f32, seed:u64) -> Vec<Point> {
+	points.iter().map(|&p| {
+		let at = p / wavelength;
+		p + Point::new(value_noise(at.x, at.y, seed), value_noise(at.x, at.y, seed ^ 0x5555)) * amplitude
+	}).collect()
+}
+
+/// Smooth noise from -1 to 1: a random value at each whole-number grid corner, eased between them.  The same seed
+/// always gives the same field.
+fn value_noise(x:f32, y:f32, seed:u64) -> f32 {
+	let corner = |ix:i64, iy:i64| {
+		let mut h = seed ^ (ix as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (iy as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+		h ^= h >> 33;
+		h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+		h ^= h >> 33;
+		(h >> 40) as f32 / (1u64 << 23) as f32 - 1f32
+	};
+	let (ix, iy) = (x.floor() as i64, y.floor() as i64);
+	let ease = |t:f32| t*t*(3f32 - 2f32*t);
+	let (tx, ty) = (ease(x - x.floor()), ease(y - y.floor()));
+	let top = corner(ix, iy) + (corner(ix+1, iy) - corner(ix, iy))*tx;
+	let bottom = corner(ix, iy+1) + (corner(ix+1, iy+1) - corner(ix, iy+1))*tx;
+	top + (bottom - top)*ty
+}
+
+fn distance_to_segment(p:Point, a:Point, b:Point) -> f32 {
+	let ab = b - a;
+	let ap = p - a;
+	let length_squared = ab.x*ab.x + ab.y*ab.y;
+	let t = if length_squared > 0f32 { ((ap.x*ab.x + ap.y*ab.y) / length_squared).clamp(0f32, 1f32) } else { 0f32 };
+	let d = ap - ab*t;
+	(d.x*d.x + d.y*d.y).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_chaikin_keeps_ends() {
+		let pts = vec![Point::new(0f32, 0f32), Point::new(4f32, 0f32), Point::new(4f32, 4f32)];
+		let smoothed = chaikin(&pts, 1);
+		assert_eq!(smoothed.len(), 6);
+		assert_eq!(smoothed[0], pts[0]);
+		assert_eq!(smoothed[5], pts[2]);
+		assert_eq!(smoothed[2], Point::new(3f32, 0f32));
+	}
+
+	#[test]
+	fn test_simplify_drops_collinear() {
+		let pts = vec![Point::new(0f32, 0f32), Point::new(1f32, 0f32), Point::new(2f32, 0.01f32), Point::new(3f32, 0f32), Point::new(3f32, 3f32)];
+		assert_eq!(simplify(&pts, 0.1f32), vec![pts[0], pts[3], pts[4]]);
+		assert_eq!(simplify(&pts, 0.009f32).len(), 4);
+		assert_eq!(simplify(&pts, -1f32), pts);
+	}
+
+	#[test]
+	fn test_jitter_is_small_and_smooth() {
+		let pts:Vec<Point> = (0..100).map(|i| Point::new(i as f32 * 0.5f32, 10f32)).collect();
+		let wobbly = jitter(&pts, 2f32, 20f32, 1);
+		assert_eq!(wobbly, jitter(&pts, 2f32, 20f32, 1));
+		assert!(wobbly != jitter(&pts, 2f32, 20f32, 2));
+		assert!(wobbly.iter().zip(&pts).all(|(&moved, &p)| (moved - p).length() <= 2f32 * 2f32.sqrt()));
+		// Neighbours half a unit apart move almost together.
+		assert!(wobbly.windows(2).all(|pair| (pair[1] - pair[0]).length() < 1f32));
+	}
+
+	#[test]
+	fn test_catmull_rom_passes_through_points() {
+		let pts = vec![Point::new(0f32, 0f32), Point::new(4f32, 0f32), Point::new(4f32, 4f32), Point::new(0f32, 4f32)];
+		let smoothed = catmull_rom(&pts, 3);
+		assert_eq!(smoothed.len(), 3*4 + 1);
+		assert_eq!(smoothed[4], pts[1]);
+		assert_eq!(smoothed[8], pts[2]);
+	}
+}
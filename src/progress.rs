@@ -0,0 +1,99 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH:usize = 30;
+const REDRAW_EVERY:Duration = Duration::from_millis(100);
+
+/// A one-line progress bar on stderr for the stages that can go on for minutes.  It can be shared between threads,
+/// redraws at most ten times a second, and stays out of the way when stderr isn't a terminal or logging is quiet.
+pub struct ProgressBar {
+	label: String,
+	total: u64,
+	done: AtomicU64,
+	start: Instant,
+	last_draw: Mutex<Option<Instant>>,
+	visible: bool,
+}
+
+impl ProgressBar {
+	pub fn new(label:&str, total:u64) -> Self {
+		ProgressBar {
+			label: label.to_string(),
+			total: total.max(1),
+			done: AtomicU64::new(0),
+			start: Instant::now(),
+			last_draw: Mutex::new(None),
+			visible: std::io::stderr().is_terminal() && log::log_enabled!(log::Level::Info),
+		}
+	}
+
+	pub fn inc(&self, amount:u64) {
+		let done = self.done.fetch_add(amount, Ordering::Relaxed) + amount;
+		self.draw(done, false);
+	}
+
+	pub fn set(&self, done:u64) {
+		self.done.store(done, Ordering::Relaxed);
+		self.draw(done, false);
+	}
+
+	/// Fill the bar and move to the next line, however far the work got.  Stopping early on a time limit still counts.
+	pub fn finish(&self) {
+		self.draw(self.total, true);
+		if self.visible {
+			eprintln!();
+		}
+	}
+
+	fn draw(&self, done:u64, force:bool) {
+		if !self.visible {
+			return;
+		}
+		// Whoever can't take the lock skips drawing, since someone else is about to.
+		let mut last_draw = match self.last_draw.try_lock() {
+			Ok(last_draw) if force || last_draw.is_none_or(|at| at.elapsed() >= REDRAW_EVERY) => last_draw,
+			_ => return,
+		};
+		*last_draw = Some(Instant::now());
+		let mut err = std::io::stderr();
+		let _ = write!(err, "\r{}", render(&self.label, done, self.total, self.start.elapsed()));
+		let _ = err.flush();
+	}
+}
+
+/// The text of the bar: `label [=====>    ]  45% 0:12, ~0:15 left`.  The estimate assumes the rest goes as fast as the
+/// part already done.
+pub fn render(label:&str, done:u64, total:u64, elapsed:Duration) -> String {
+	let fraction = (done as f64 / total.max(1) as f64).clamp(0f64, 1f64);
+	let filled = (fraction * BAR_WIDTH as f64) as usize;
+	let mut bar = "=".repeat(filled);
+	if filled < BAR_WIDTH {
+		bar.push('>');
+		bar.push_str(&" ".repeat(BAR_WIDTH - filled - 1));
+	}
+	let clock = |d:Duration| format!("{}:{:02}", d.as_secs() / 60, d.as_secs() % 60);
+	let left = if fraction > 0f64 && fraction < 1f64 {
+		format!(", ~{} left", clock(elapsed.mul_f64((1f64 - fraction) / fraction)))
+	} else {
+		String::new()
+	};
+	format!("{} [{}] {:3.0}% {}{}", label, bar, fraction * 100f64, clock(elapsed), left)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_render() {
+		let half = render("Subdividing", 50, 100, Duration::from_secs(30));
+		assert_eq!(half, format!("Subdividing [{}>{}]  50% 0:30, ~0:30 left", "=".repeat(15), " ".repeat(14)));
+		let done = render("Writing", 7, 7, Duration::from_secs(75));
+		assert_eq!(done, format!("Writing [{}] 100% 1:15", "=".repeat(30)));
+		// Overshooting the total, or an empty one, still draws a sensible bar.
+		assert!(render("Generations", 120, 100, Duration::from_secs(1)).contains("100%"));
+		assert!(render("Generations", 0, 0, Duration::from_secs(1)).contains("  0%"));
+	}
+}
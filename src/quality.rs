@@ -0,0 +1,146 @@
+use crate::polyline::Polyline;
+use image::GrayImage;
+
+/// How closely a drawing's tone follows the image it was traced from, once both are blurred to how they'd look from
+/// across the room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quality {
+	/// Root mean square difference in darkness, from 0 up to 1, after the drawing is scaled by `gain`.
+	pub rms_error: f32,
+	/// Mean structural similarity, 1 for a perfect match and near 0 for none.
+	pub ssim: f32,
+	/// What the drawing's ink was multiplied by to best match the image.  Lines only cover so much of the paper, so
+	/// it's the spread of tone being scored and not how black the darkest part gets.
+	pub gain: f32,
+	/// Regions across and down.
+	pub grid: (usize, usize),
+	/// `rms_error` over each region, a row at a time from the top left.
+	pub regions: Vec<f32>,
+}
+
+/// Score `paths`, in the image's own pixels, against the image they were drawn from.  Both are blurred with a box of
+/// `blur` pixels either side, three times over, which is close to a Gaussian, and the error is also given for each
+/// of a `grid` by `grid` set of regions.
+pub fn score(paths:&[Polyline], source:&GrayImage, blur:usize, grid:usize) -> Quality {
+	let (width, height) = (source.width() as usize, source.height() as usize);
+	let target = smooth(&source.pixels().map(|p| 1f32 - p[0] as f32 / 255f32).collect::<Vec<f32>>(), width, height, blur);
+	let drawn = smooth(&ink(paths, width, height), width, height, blur);
+	let energy:f32 = drawn.iter().map(|d| d * d).sum();
+	let gain = if energy > 0f32 { drawn.iter().zip(&target).map(|(d, s)| d * s).sum::<f32>() / energy } else { 0f32 };
+	let drawn:Vec<f32> = drawn.iter().map(|d| d * gain).collect();
+
+	let squared:Vec<f32> = drawn.iter().zip(&target).map(|(d, s)| (d - s) * (d - s)).collect();
+	let rms = |values:&mut dyn Iterator<Item = f32>| {
+		let (sum, count) = values.fold((0f32, 0usize), |(sum, count), value| (sum + value, count + 1));
+		if count == 0 { 0f32 } else { (sum / count as f32).sqrt() }
+	};
+	let rms_error = rms(&mut squared.iter().copied());
+	let grid = (grid.clamp(1, width.max(1)), grid.clamp(1, height.max(1)));
+	let mut regions = vec![];
+	for row in 0..grid.1 {
+		let (top, bottom) = (row * height / grid.1, (row + 1) * height / grid.1);
+		for column in 0..grid.0 {
+			let (left, right) = (column * width / grid.0, (column + 1) * width / grid.0);
+			regions.push(rms(&mut (top..bottom).flat_map(|y| (left..right).map(move |x| y * width + x)).map(|i| squared[i])));
+		}
+	}
+	Quality { rms_error, ssim: ssim(&drawn, &target, width, height, blur), gain, grid, regions }
+}
+
+/// How much of each pixel has ink on it, taking the pen as a pixel wide.
+pub fn ink(paths:&[Polyline], width:usize, height:usize) -> Vec<f32> {
+	const STEP:f32 = 0.25f32;
+	let mut coverage = vec![0f32; width * height];
+	for pair in paths.iter().flat_map(|path| path.points.windows(2)) {
+		let steps = ((pair[1] - pair[0]).length() / STEP).ceil().max(1f32) as usize;
+		let length = (pair[1] - pair[0]).length() / steps as f32;
+		for i in 0..steps {
+			let p = pair[0].lerp(pair[1], (i as f32 + 0.5f32) / steps as f32);
+			if p.x >= 0f32 && p.y >= 0f32 && (p.x as usize) < width && (p.y as usize) < height {
+				let pixel = &mut coverage[p.y as usize * width + p.x as usize];
+				*pixel = (*pixel + length).min(1f32);
+			}
+		}
+	}
+	coverage
+}
+
+/// Three passes of a box blur `radius` pixels either side, across and then down.  The edges are treated as clamped.
+fn smooth(values:&[f32], width:usize, height:usize, radius:usize) -> Vec<f32> {
+	let mut values = values.to_vec();
+	if radius == 0 {
+		return values;
+	}
+	for _ in 0..3 {
+		values = box_pass(&values, width, height, radius, true);
+		values = box_pass(&values, width, height, radius, false);
+	}
+	values
+}
+
+fn box_pass(values:&[f32], width:usize, height:usize, radius:usize, across:bool) -> Vec<f32> {
+	let (lines, length) = if across { (height, width) } else { (width, height) };
+	let at = |line:usize, i:usize| if across { line * width + i } else { i * width + line };
+	let mut result = vec![0f32; values.len()];
+	for line in 0..lines {
+		// A running sum over the window, with the ends held at the edge value.
+		let value = |i:isize| values[at(line, i.clamp(0, length as isize - 1) as usize)];
+		let r = radius as isize;
+		let mut sum:f32 = (-r..=r).map(value).sum();
+		for i in 0..length as isize {
+			result[at(line, i as usize)] = sum / (2 * r + 1) as f32;
+			sum += value(i + r + 1) - value(i - r);
+		}
+	}
+	result
+}
+
+/// Mean SSIM over windows the size of the blur, with the usual constants for values from 0 to 1.
+fn ssim(a:&[f32], b:&[f32], width:usize, height:usize, radius:usize) -> f32 {
+	const C1:f32 = 0.01f32 * 0.01f32;
+	const C2:f32 = 0.03f32 * 0.03f32;
+	let radius = radius.max(1);
+	let local = |values:Vec<f32>| smooth(&values, width, height, radius);
+	let (mean_a, mean_b) = (local(a.to_vec()), local(b.to_vec()));
+	let aa = local(a.iter().map(|x| x * x).collect());
+	let bb = local(b.iter().map(|x| x * x).collect());
+	let ab = local(a.iter().zip(b).map(|(x, y)| x * y).collect());
+	let total:f32 = (0..a.len()).map(|i| {
+		let (ma, mb) = (mean_a[i], mean_b[i]);
+		let (va, vb, cov) = ((aa[i] - ma * ma).max(0f32), (bb[i] - mb * mb).max(0f32), ab[i] - ma * mb);
+		((2f32 * ma * mb + C1) * (2f32 * cov + C2)) / ((ma * ma + mb * mb + C1) * (va + vb + C2))
+	}).sum();
+	if a.is_empty() { 1f32 } else { total / a.len() as f32 }
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::point::Point;
+	use image::Luma;
+
+	#[test]
+	fn test_ink() {
+		let line = Polyline::new(vec![Point::new(0f32, 1.5f32), Point::new(4f32, 1.5f32)]);
+		let coverage = ink(&[line], 4, 3);
+		assert!(coverage[4..8].iter().all(|c| (c - 1f32).abs() < 1e-4f32));
+		assert!(coverage[..4].iter().chain(&coverage[8..]).all(|&c| c == 0f32));
+	}
+
+	#[test]
+	fn test_score() {
+		// Dark on the left, and hatching on one side or the other.
+		let source = GrayImage::from_fn(32, 32, |x, _| Luma([if x < 16 { 0u8 } else { 255u8 }]));
+		let hatching = |x0:f32| -> Vec<Polyline> {
+			(0..16).map(|y| Polyline::new(vec![Point::new(x0, y as f32 * 2f32 + 0.5f32), Point::new(x0 + 16f32, y as f32 * 2f32 + 0.5f32)])).collect()
+		};
+		let matched = score(&hatching(0f32), &source, 2, 2);
+		let opposite = score(&hatching(16f32), &source, 2, 2);
+		assert!(matched.rms_error < 0.1f32 && matched.ssim > 0.8f32);
+		assert!(opposite.rms_error > 0.4f32 && opposite.ssim < matched.ssim);
+		assert_eq!(matched.grid, (2, 2));
+		assert_eq!(matched.regions.len(), 4);
+		// Half the paper is inked, so it takes about twice the ink to match solid black.
+		assert!((matched.gain - 2f32).abs() < 0.2f32);
+	}
+}
@@ -0,0 +1,184 @@
+use crate::error::{Error, Result};
+use crate::hilbert;
+use crate::output;
+use crate::point::Point;
+use crate::polyline::Polyline;
+use crate::progress::ProgressBar;
+use image::{GrayImage, Luma};
+
+pub type IntegralImage = image::ImageBuffer<Luma<u32>, Vec<u32>>;
+
+pub fn adjust_levels(img:&mut GrayImage, steps:u8) {
+	// Crush the image luminance from 0-255 to `steps` distinct values from 0 to `steps`.
+	img.enumerate_pixels_mut().for_each(|(_px, _py, value)| {
+		// Bit-crushing.
+		let floatval:f32 = value[0] as f32 / 255f32;
+		*value = Luma([(floatval*steps as f32) as u8]);
+	});
+}
+
+/// Mean darkness level (0 for white up to `gray_levels` for black) over the pixels a cell touches.
+/// Pixels past the edge of the image count as white.
+pub fn mean_darkness(img:&GrayImage, integral:&IntegralImage, gray_levels:u8, bounds:(f32, f32, f32, f32)) -> f32 {
+	let (left, top, right, bottom) = bounds;
+	let (left, top) = (left.floor() as u32, top.floor() as u32);
+	let (right, bottom) = (right.ceil() as u32, bottom.ceil() as u32);
+	let area = ((right - left) * (bottom - top)) as f32;
+	let (clip_right, clip_bottom) = (right.min(img.width()), bottom.min(img.height()));
+	if area <= 0f32 || clip_right <= left || clip_bottom <= top {
+		return 0f32;
+	}
+	let covered = (clip_right - left) * (clip_bottom - top);
+	let sum = imageproc::integral_image::sum_image_pixels(integral, left, top, clip_right - 1, clip_bottom - 1)[0];
+	(covered * gray_levels as u32 - sum) as f32 / area
+}
+
+/// Darkness from 0 for white to 1 for black of the pixel under `p`.  Off the image counts as white.
+pub fn darkness_at(img:&GrayImage, gray_levels:u8, p:Point) -> f32 {
+	if p.x < 0f32 || p.y < 0f32 || p.x >= img.width() as f32 || p.y >= img.height() as f32 {
+		return 0f32;
+	}
+	let level = img.get_pixel(p.x as u32, p.y as u32)[0];
+	gray_levels.saturating_sub(level) as f32 / gray_levels.max(1) as f32
+}
+
+/// How sharply the tone changes at each pixel of an image with `gray_levels` levels, from the Sobel gradient, laid out
+/// like the image itself so `mean_darkness` can read it: 0 for the sharpest step, from black straight to white, up to
+/// `gray_levels` where it's flat.
+pub fn edge_levels(img:&GrayImage, gray_levels:u8) -> GrayImage {
+	let gradients = imageproc::gradients::sobel_gradients(img);
+	// A step from one level to the next comes out of the Sobel kernels four levels strong.
+	GrayImage::from_fn(img.width(), img.height(), |x, y| {
+		let strength = (gradients.get_pixel(x, y)[0] / 4).min(gray_levels as u16) as u8;
+		Luma([gray_levels - strength])
+	})
+}
+
+/// Build the quadtree for the image, starting from the given root orientation.
+pub fn build_hilbert(img:&GrayImage, integral:&IntegralImage, gray_levels:u8, max_depth:Option<u32>, min_cell_size:u32, fit:hilbert::Fit, orientation:Option<hilbert::QuadOrientation>) -> hilbert::Hilbert {
+	// A pixel at darkness level L wants to sit in a cell L levels deep.  Anything off the edge of the image (when
+	// padding) counts as white.
+	build_hilbert_by(img.width(), img.height(), |bounds| mean_darkness(img, integral, gray_levels, bounds), max_depth, min_cell_size, fit, orientation)
+}
+
+/// Build the quadtree for an image `width` by `height`, splitting cells for as long as `wanted_depth` of a cell's
+/// (left, top, right, bottom) is deeper than the cell is.
+pub fn build_hilbert_by<F:Fn((f32, f32, f32, f32)) -> f32 + Sync>(width:u32, height:u32, wanted_depth:F, max_depth:Option<u32>, min_cell_size:u32, fit:hilbert::Fit, orientation:Option<hilbert::QuadOrientation>) -> hilbert::Hilbert {
+	let (canvas_width, canvas_height) = hilbert::canvas_size(width, height, fit);
+	let mut hilbert_curve = hilbert::Hilbert::new(canvas_width, 0, 0, canvas_height, orientation);
+	hilbert_curve.set_max_depth(max_depth);
+	hilbert_curve.set_min_cell_size(min_cell_size);
+	hilbert_curve.subdivide();
+
+	// A browser has no threads to spread the splitting over and no clock for the progress bar, so there it's done on
+	// the calling thread without one.
+	if cfg!(target_arch = "wasm32") {
+		hilbert_curve.subdivide_while(|cell, depth| wanted_depth(cell.bounds()) > depth as f32);
+	} else {
+		// Progress is the share of the canvas in cells that are done splitting.
+		let bar = ProgressBar::new("Subdividing", canvas_width as u64 * canvas_height as u64);
+		hilbert_curve.par_subdivide_while(|cell, depth| {
+			let (left, top, right, bottom) = cell.bounds();
+			let split = wanted_depth((left, top, right, bottom)) > depth as f32;
+			if !split {
+				bar.inc(((right - left) * (bottom - top)) as u64);
+			}
+			split
+		});
+		bar.finish();
+	}
+	hilbert_curve
+}
+
+/// The settings `render_svg` takes, the few that matter most from the command line's.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderOptions {
+	pub gray_levels: u8,
+	pub max_depth: Option<u32>,
+	pub min_cell_size: u32,
+	/// Smooth Bézier curves instead of straight segments.
+	pub curves: bool,
+}
+
+impl Default for RenderOptions {
+	fn default() -> Self {
+		RenderOptions { gray_levels: 10, max_depth: None, min_cell_size: 1, curves: false }
+	}
+}
+
+/// Draw an encoded image (PNG, JPEG and the rest `image` can read) as a Hilbert curve and give back the SVG text.
+/// Nothing here touches the file system, so it's the one call a browser build needs.
+pub fn render_svg(image_bytes:&[u8], options:&RenderOptions) -> Result<String> {
+	if options.gray_levels == 0 {
+		return Err(Error::BadValue { name: "gray_levels".to_string(), value: "0".to_string() });
+	}
+	let img = image::load_from_memory(image_bytes).map_err(|source| Error::Image { path: "image data".to_string(), source })?.to_luma8();
+	let (width, height) = img.dimensions();
+	let paths = vec![Polyline::from(trace(img, options))];
+	Ok(output::svg_text(&paths, width, height, options.curves, &output::Style::default()))
+}
+
+/// The Hilbert curve through a grayscale image given as raw rows of `width` bytes, 0 for black and 255 for white.
+/// This is the shape bindings from other languages want: a 2D array of pixels in, an array of points out.
+pub fn hilbert_points(pixels:&[u8], width:u32, height:u32, options:&RenderOptions) -> Result<Vec<(f32, f32)>> {
+	if options.gray_levels == 0 {
+		return Err(Error::BadValue { name: "gray_levels".to_string(), value: "0".to_string() });
+	}
+	if pixels.len() as u64 != width as u64 * height as u64 {
+		return Err(Error::Usage(format!("Expected {} pixels for a {}x{} image, got {}", width as u64 * height as u64, width, height, pixels.len())));
+	}
+	let img = GrayImage::from_raw(width, height, pixels.to_vec()).expect("the size was checked");
+	Ok(trace(img, options))
+}
+
+fn trace(mut img:GrayImage, options:&RenderOptions) -> Vec<(f32, f32)> {
+	adjust_levels(&mut img, options.gray_levels);
+	let integral:IntegralImage = imageproc::integral_image::integral_image(&img);
+	let hilbert_curve = build_hilbert(&img, &integral, options.gray_levels, options.max_depth, options.min_cell_size, hilbert::Fit::Stretch, None);
+	if cfg!(target_arch = "wasm32") { hilbert_curve.rasterize() } else { hilbert_curve.par_rasterize() }
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_render_svg_from_bytes() {
+		let img = GrayImage::from_fn(32, 32, |x, _| Luma([(x * 8) as u8]));
+		let mut png = vec![];
+		image::DynamicImage::ImageLuma8(img).write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+		let svg = render_svg(&png, &RenderOptions::default()).unwrap();
+		assert!(svg.starts_with("<svg width=\"32\" height=\"32\""));
+		assert_eq!(svg.matches("<path").count(), 1);
+		assert!(render_svg(b"not an image", &RenderOptions::default()).is_err());
+	}
+
+	#[test]
+	fn test_hilbert_points_from_pixels() {
+		let pixels:Vec<u8> = (0..16*16).map(|i| ((i % 16) * 16) as u8).collect();
+		let points = hilbert_points(&pixels, 16, 16, &RenderOptions::default()).unwrap();
+		assert!(points.len() > 4);
+		assert!(points.iter().all(|&(x, y)| (0f32..16f32).contains(&x) && (0f32..16f32).contains(&y)));
+		// Darker on the left, so more of the curve is there.
+		assert!(points.iter().filter(|p| p.0 < 8f32).count() > points.iter().filter(|p| p.0 >= 8f32).count());
+		assert!(hilbert_points(&pixels, 16, 15, &RenderOptions::default()).is_err());
+	}
+
+	#[test]
+	fn test_edges_draw_detail() {
+		let step = GrayImage::from_fn(8, 8, |x, _| Luma([if x < 4 { 0u8 } else { 10u8 }]));
+		let edges = edge_levels(&step, 10);
+		// A step from black to white is as strong as an edge gets, and flat parts have none.
+		assert_eq!(edges.get_pixel(4, 4)[0], 0);
+		assert_eq!(edges.get_pixel(0, 4)[0], 10);
+		// A light texture: not much darkness, but plenty going on.
+		let img = GrayImage::from_fn(32, 32, |x, y| Luma([if (x / 2 + y / 2) % 2 == 0 { 10u8 } else { 6u8 }]));
+		let (edges, integral) = (edge_levels(&img, 10), imageproc::integral_image::integral_image(&img));
+		let edge_integral:IntegralImage = imageproc::integral_image::integral_image(&edges);
+		let plain = build_hilbert(&img, &integral, 10, None, 1, hilbert::Fit::Stretch, None);
+		let detailed = build_hilbert_by(32, 32, |bounds| {
+			0.5f32 * mean_darkness(&img, &integral, 10, bounds) + 0.5f32 * mean_darkness(&edges, &edge_integral, 10, bounds)
+		}, None, 1, hilbert::Fit::Stretch, None);
+		assert!(detailed.point_count() > plain.point_count());
+	}
+}
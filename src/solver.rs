@@ -0,0 +1,193 @@
+use crate::distances;
+use crate::error::{Error, Result};
+use crate::hamiltonian;
+use crate::hilbert;
+use crate::point::Point;
+use crate::progress::ProgressBar;
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs::File;
+use std::io::BufReader;
+
+/// The options shared by the tsp mode and the solve subcommand.
+pub struct TspOptions {
+	/// One of hilbert, nn, greedy, christofides, ga, sa, lk or aco.
+	pub solver: String,
+	pub iterations: u64,
+	pub open_path: bool,
+	pub use_two_opt: bool,
+	pub use_or_opt: bool,
+	pub use_uncross: bool,
+	pub neighbor_count: usize,
+	/// Megabytes the distance matrix may take.  0 means don't build one.
+	pub distance_cache: usize,
+	pub stop: hamiltonian::StopCriteria,
+	pub ga_config: hamiltonian::GaConfig,
+	pub islands: hamiltonian::IslandConfig,
+	pub aco_config: hamiltonian::AcoConfig,
+	pub checkpoint: Option<hamiltonian::Checkpoint>,
+	/// exponential or linear.
+	pub cooling: String,
+	/// length, curvature or plot-time.
+	pub objective: String,
+	pub curvature_weight: f32,
+	pub max_speed: f32,
+	pub acceleration: f32,
+	/// Grid size for dropping repeated points before solving.
+	pub dedup: Option<f32>,
+	pub seed: u64,
+}
+
+impl Default for TspOptions {
+	fn default() -> Self {
+		TspOptions {
+			solver: "hilbert".to_string(), iterations: 100, open_path: false, use_two_opt: false, use_or_opt: false, use_uncross: false,
+			neighbor_count: 10, distance_cache: 0, stop: hamiltonian::StopCriteria { max_generations: Some(100), ..Default::default() },
+			ga_config: hamiltonian::GaConfig::default(), islands: hamiltonian::IslandConfig { islands: 1, ..Default::default() },
+			aco_config: hamiltonian::AcoConfig::default(), checkpoint: None, cooling: "exponential".to_string(), objective: "length".to_string(),
+			curvature_weight: 1f32, max_speed: 100f32, acceleration: 1000f32, dedup: None, seed: 0,
+		}
+	}
+}
+
+/// Load the solver state from the checkpoint file, if there is one and it already exists.
+pub fn resume_checkpoint(checkpoint:Option<&hamiltonian::Checkpoint>) -> Result<Option<hamiltonian::GaState>> {
+	match checkpoint {
+		Some(c) if c.path.exists() => {
+			info!("Resuming from {}", c.path.display());
+			let state = File::open(&c.path).and_then(|f| hamiltonian::GaState::load(&mut BufReader::new(f))).map_err(|e| Error::io(c.path.display().to_string(), e))?;
+			Ok(Some(state))
+		},
+		_ => Ok(None),
+	}
+}
+
+/// Run the chosen solver and refinement passes, or say which of the options don't make sense.
+pub fn solve_tour(points:&[(f32, f32)], resumed:Option<hamiltonian::GaState>, options:&TspOptions) -> Result<Vec<usize>> {
+	let open_path = options.open_path;
+	let mut rng = StdRng::seed_from_u64(options.seed);
+	let matrix = (options.distance_cache > 0).then(|| {
+		let matrix = distances::DistanceMatrix::new(points, options.distance_cache * 1024 * 1024);
+		if !matrix.is_cached() {
+			warn!("A distance matrix for {} points needs {} MB, more than the cap; working distances out as needed", points.len(), distances::DistanceMatrix::bytes_needed(points.len()) / (1024 * 1024));
+		}
+		matrix
+	});
+	let mut tour = match options.solver.as_str() {
+		"hilbert" => {
+			let stipples:Vec<Point> = points.iter().map(|&p| p.into()).collect();
+			hilbert::hilbert_order(&stipples)
+		},
+		"nn" => hamiltonian::nearest_neighbor_tour(points, 0),
+		"christofides" => hamiltonian::christofides_tour(points),
+		"greedy" => hamiltonian::greedy_edge_tour(points, options.neighbor_count.max(1)),
+		"ga" => {
+			let length = hamiltonian::Length { close: !open_path };
+			let matrix_length = matrix.as_ref().map(|distances| hamiltonian::MatrixLength { distances, close: !open_path });
+			let curvature = hamiltonian::Curvature { close: !open_path, weight: options.curvature_weight };
+			let plot_time = hamiltonian::PlotTime { close: !open_path, max_speed: options.max_speed, acceleration: options.acceleration };
+			let objective:&dyn hamiltonian::Objective = match options.objective.as_str() {
+				"length" => match &matrix_length {
+					Some(matrix_length) => matrix_length,
+					None => &length,
+				},
+				"curvature" => &curvature,
+				"plot-time" => &plot_time,
+				other => return Err(Error::BadValue { name: "--objective".to_string(), value: other.to_string() }),
+			};
+			let bar = ProgressBar::new("Generations", options.iterations);
+			let report = &mut |generation, cost, _:&[usize]| {
+				bar.set(generation);
+				debug!("Generation {}: best cost {}", generation, cost);
+			};
+			let tour = if options.islands.islands > 1 {
+				if options.checkpoint.is_some() {
+					return Err(Error::Usage("Checkpoints aren't supported with more than one island".to_string()));
+				}
+				hamiltonian::solve_tsp_islands(points, options.ga_config, options.islands, options.stop, objective, report, &mut rng)
+			} else {
+				let state = resumed.unwrap_or_else(|| hamiltonian::GaState::new(points, options.ga_config.population, &mut rng));
+				hamiltonian::evolve(state, options.ga_config, options.stop, objective, report, options.checkpoint.as_ref())
+			};
+			bar.finish();
+			tour
+		},
+		"aco" => {
+			let bar = ProgressBar::new("Iterations", options.iterations);
+			let tour = hamiltonian::solve_tsp_ant_colony(points, options.aco_config, options.stop, !open_path, &mut |iteration, length, _| {
+				bar.set(iteration);
+				debug!("Iteration {}: shortest tour {}", iteration, length);
+			}, &mut rng);
+			bar.finish();
+			tour
+		},
+		"lk" => {
+			let mut tour = hamiltonian::nearest_neighbor_tour(points, 0);
+			hamiltonian::lin_kernighan(points, &mut tour, 10, 50);
+			if open_path {
+				hamiltonian::cut_longest_edge(points, &tour)
+			} else {
+				tour
+			}
+		},
+		"sa" => {
+			// For annealing, iterations are sweeps: one proposed move per point each.
+			let start = hamiltonian::nearest_neighbor_tour(points, 0);
+			let schedule = match (options.cooling.as_str(), hamiltonian::CoolingSchedule::for_tour(points, &start)) {
+				("exponential", schedule) => schedule,
+				("linear", hamiltonian::CoolingSchedule::Exponential { start, .. }) => hamiltonian::CoolingSchedule::Linear { start },
+				_ => return Err(Error::BadValue { name: "--cooling".to_string(), value: options.cooling.clone() }),
+			};
+			let bar = ProgressBar::new("Sweeps", options.iterations);
+			let tour = hamiltonian::solve_tsp_annealing(points, start, options.iterations * points.len() as u64, schedule, !open_path, &mut |sweep, length, _| {
+				bar.set(sweep);
+				debug!("Sweep {}: tour {}", sweep, length);
+			}, &mut rng);
+			bar.finish();
+			tour
+		},
+		other => return Err(Error::BadValue { name: "--solver".to_string(), value: other.to_string() }),
+	};
+	// Zero neighbors means trying every move, which is only practical for a few thousand points.
+	let neighbors = if options.neighbor_count > 0 && (options.use_two_opt || options.use_or_opt) {
+		Some(hamiltonian::neighbor_lists(points, options.neighbor_count))
+	} else {
+		None
+	};
+	if options.use_two_opt {
+		match &matrix {
+			Some(matrix) => hamiltonian::two_opt(matrix, &mut tour, !open_path, neighbors.as_deref()),
+			None => hamiltonian::two_opt(points, &mut tour, !open_path, neighbors.as_deref()),
+		};
+	}
+	if options.use_or_opt {
+		match &matrix {
+			Some(matrix) => hamiltonian::or_opt(matrix, &mut tour, !open_path, neighbors.as_deref()),
+			None => hamiltonian::or_opt(points, &mut tour, !open_path, neighbors.as_deref()),
+		};
+	}
+	if options.use_uncross {
+		let removed = hamiltonian::uncross(points, &mut tour, !open_path);
+		info!("Removed {} crossings", removed);
+	}
+	Ok(tour)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_every_solver_visits_every_point() {
+		let points:Vec<(f32, f32)> = (0..30).map(|i| ((i * 7 % 13) as f32, (i * 5 % 11) as f32)).collect();
+		for solver in ["hilbert", "nn", "greedy", "christofides", "ga", "sa", "lk", "aco"] {
+			let options = TspOptions { solver: solver.to_string(), iterations: 5, stop: hamiltonian::StopCriteria { max_generations: Some(5), ..Default::default() }, use_two_opt: true, ..Default::default() };
+			let mut tour = solve_tour(&points, None, &options).unwrap();
+			tour.sort_unstable();
+			assert_eq!(tour, (0..points.len()).collect::<Vec<usize>>(), "{}", solver);
+		}
+		let bogus = TspOptions { solver: "bogus".to_string(), ..Default::default() };
+		assert!(matches!(solve_tour(&points, None, &bogus), Err(Error::BadValue { .. })));
+	}
+}
@@ -0,0 +1,155 @@
+use crate::hamiltonian::PlotTime;
+use crate::point::Point;
+use crate::pointfile::ToJson;
+use crate::polyline::Polyline;
+use std::io::{self, Write};
+
+/// What a drawing will take to plot, for checking it over before a long run.  Lengths are in drawing units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlotStats {
+	pub paths: usize,
+	pub points: usize,
+	/// Distance drawn with the pen down.
+	pub pen_down: f32,
+	/// Distance travelled with the pen up, from the end of each path to the start of the next.
+	pub pen_up: f32,
+	/// How many times the pen goes down to draw and comes back up.
+	pub lifts: usize,
+	/// Seconds to plot everything if the pen stops at every point, as `PlotTime` has it, and takes `pen_lift` seconds
+	/// to go down and up again for each path.
+	pub seconds: f32,
+}
+
+/// Every layer's stats together, as if they were plotted one after another.  The travel from one layer to the next
+/// isn't counted, since that's when the pen gets changed.
+impl std::iter::Sum for PlotStats {
+	fn sum<I:Iterator<Item = PlotStats>>(iter:I) -> Self {
+		iter.fold(PlotStats { paths: 0, points: 0, pen_down: 0f32, pen_up: 0f32, lifts: 0, seconds: 0f32 }, |a, b| PlotStats {
+			paths: a.paths + b.paths,
+			points: a.points + b.points,
+			pen_down: a.pen_down + b.pen_down,
+			pen_up: a.pen_up + b.pen_up,
+			lifts: a.lifts + b.lifts,
+			seconds: a.seconds + b.seconds,
+		})
+	}
+}
+
+pub fn plot_stats(paths:&[Polyline], plotter:&PlotTime, pen_lift:f32) -> PlotStats {
+	let mut stats = PlotStats { paths: paths.len(), points: 0, pen_down: 0f32, pen_up: 0f32, lifts: 0, seconds: 0f32 };
+	let mut pen = None;
+	for path in paths.iter().filter(|path| !path.is_empty()) {
+		stats.lifts += 1;
+		stats.seconds += pen_lift;
+		if let Some(at) = pen {
+			let travel = (path.points[0] - at).length();
+			stats.pen_up += travel;
+			stats.seconds += plotter.segment_time(travel);
+		}
+		stats.points += path.len();
+		for pair in path.points.windows(2) {
+			let distance = (pair[1] - pair[0]).length();
+			stats.pen_down += distance;
+			stats.seconds += plotter.segment_time(distance);
+		}
+		pen = path.points.last().copied();
+	}
+	stats
+}
+
+/// Everything worth keeping about one render, for cataloguing it and comparing it with others from a sweep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderStats {
+	pub plot: PlotStats,
+	/// The corners of the smallest box round the drawing, as it was written.  None if there's nothing in it.
+	pub bounds: Option<(Point, Point)>,
+	/// The arguments the render was run with, presets and config files filled in.
+	pub arguments: Vec<String>,
+	/// Seconds each stage took, in the order they first ran.
+	pub stages: Vec<(String, f32)>,
+}
+
+impl RenderStats {
+	pub fn new(paths:&[Polyline], plot:PlotStats, arguments:Vec<String>, stages:Vec<(String, f32)>) -> Self {
+		let bounds = paths.iter().filter_map(Polyline::bounding_box).reduce(|(low, high), (a, b)| {
+			(Point::new(low.x.min(a.x), low.y.min(a.y)), Point::new(high.x.max(b.x), high.y.max(b.y)))
+		});
+		RenderStats { plot, bounds, arguments, stages }
+	}
+}
+
+impl ToJson for RenderStats {
+	fn write_json<W:Write>(&self, out:&mut W) -> io::Result<()> {
+		let plot = &self.plot;
+		writeln!(out, "{{")?;
+		writeln!(out, "  \"paths\": {},\n  \"points\": {},", plot.paths, plot.points)?;
+		writeln!(out, "  \"path_length\": {},\n  \"pen_up_travel\": {},", plot.pen_down, plot.pen_up)?;
+		writeln!(out, "  \"pen_lifts\": {},\n  \"plot_seconds\": {},", plot.lifts, plot.seconds)?;
+		match self.bounds {
+			Some((low, high)) => writeln!(out, "  \"bounding_box\": {{\"min\": [{}, {}], \"max\": [{}, {}]}},", low.x, low.y, high.x, high.y)?,
+			None => writeln!(out, "  \"bounding_box\": null,")?,
+		}
+		let arguments:Vec<String> = self.arguments.iter().map(|argument| json_string(argument)).collect();
+		writeln!(out, "  \"arguments\": [{}],", arguments.join(", "))?;
+		let stages:Vec<String> = self.stages.iter().map(|(stage, seconds)| format!("{}: {}", json_string(stage), seconds)).collect();
+		writeln!(out, "  \"stage_seconds\": {{{}}}", stages.join(", "))?;
+		writeln!(out, "}}")
+	}
+}
+
+fn json_string(text:&str) -> String {
+	let mut quoted = String::from("\"");
+	for c in text.chars() {
+		match c {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+			c => quoted.push(c),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+/// Seconds as `h:mm:ss`.
+pub fn clock(seconds:f32) -> String {
+	let seconds = seconds.max(0f32).round() as u64;
+	format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::point::Point;
+
+	#[test]
+	fn test_plot_stats() {
+		let paths = vec![
+			Polyline::new(vec![Point::new(0f32, 0f32), Point::new(3f32, 0f32), Point::new(3f32, 4f32)]),
+			Polyline::new(vec![]),
+			Polyline::new(vec![Point::new(6f32, 8f32), Point::new(6f32, 9f32)]),
+		];
+		let plotter = PlotTime { close: false, max_speed: 10f32, acceleration: 100f32 };
+		let stats = plot_stats(&paths, &plotter, 0.5f32);
+		assert_eq!((stats.paths, stats.points, stats.pen_down, stats.pen_up, stats.lifts), (3, 5, 8f32, 5f32, 2));
+		let expected = plotter.segment_time(3f32) + plotter.segment_time(4f32) + plotter.segment_time(5f32) + plotter.segment_time(1f32) + 1f32;
+		assert!((stats.seconds - expected).abs() < 1e-6f32);
+		let both:PlotStats = vec![stats, stats].into_iter().sum();
+		assert_eq!((both.paths, both.lifts, both.pen_up), (6, 4, 10f32));
+		assert_eq!(clock(3725.4f32), "1:02:05");
+	}
+
+	#[test]
+	fn test_render_stats_json() {
+		let paths = vec![Polyline::new(vec![Point::new(1f32, 5f32), Point::new(4f32, 2f32)]), Polyline::new(vec![Point::new(-1f32, 3f32)])];
+		let plotter = PlotTime { close: false, max_speed: 10f32, acceleration: 100f32 };
+		let stats = RenderStats::new(&paths, plot_stats(&paths, &plotter, 0f32), vec!["in.png".to_string(), "a \"b\"".to_string()], vec![("write".to_string(), 0.5f32)]);
+		assert_eq!(stats.bounds, Some((Point::new(-1f32, 2f32), Point::new(4f32, 5f32))));
+		let mut json = vec![];
+		stats.write_json(&mut json).unwrap();
+		let json = String::from_utf8(json).unwrap();
+		assert!(json.contains(r#""bounding_box": {"min": [-1, 2], "max": [4, 5]},"#));
+		assert!(json.contains(r#""arguments": ["in.png", "a \"b\""],"#));
+		assert!(json.contains(r#""stage_seconds": {"write": 0.5}"#));
+	}
+}
@@ -0,0 +1,71 @@
+use crate::grid::SpatialGrid;
+use crate::point::Point;
+use image::GrayImage;
+use rand::Rng;
+
+/// Scatter `count` points over the image, with the odds of landing on a pixel proportional to its darkness.
+/// `max_level` is the value of a pure white pixel, i.e. the number of gray levels after `adjust_levels`.
+/// Each point is jittered uniformly inside the pixel it lands on.
+/// With a positive `min_spacing`, points that would land closer than that to one already placed are drawn again, which
+/// evens out the clumps pure chance leaves.  If the image can't fit `count` points that far apart, fewer come back.
+/// All randomness comes from `rng`, so a seeded generator places the same points every run.
+pub fn stipple(img:&GrayImage, max_level:u8, count:usize, min_spacing:f32, rng:&mut impl Rng) -> Vec<Point> {
+	// Cumulative darkness over all pixels in row-major order.
+	let mut total = 0f32;
+	let cumulative:Vec<f32> = img.pixels().map(|px| {
+		total += (max_level.saturating_sub(px[0])) as f32 / max_level.max(1) as f32;
+		total
+	}).collect();
+	if total <= 0f32 {
+		return vec![];
+	}
+
+	let mut draw = || {
+		let target = rng.gen::<f32>() * total;
+		let idx = cumulative.partition_point(|&c| c <= target).min(cumulative.len() - 1);
+		let x = (idx as u32 % img.width()) as f32 + rng.gen::<f32>();
+		let y = (idx as u32 / img.width()) as f32 + rng.gen::<f32>();
+		Point::new(x, y)
+	};
+	if min_spacing <= 0f32 {
+		return (0..count).map(|_| draw()).collect();
+	}
+
+	let mut placed = SpatialGrid::new(min_spacing);
+	for _ in 0..count * MAX_TRIES {
+		if placed.len() >= count {
+			break;
+		}
+		let p = draw();
+		if !placed.any_within(p, min_spacing) {
+			placed.insert(p);
+		}
+	}
+	(0..placed.len()).map(|idx| placed.point(idx)).collect()
+}
+
+/// With spacing, how many draws per point to make before settling for fewer points.
+const MAX_TRIES:usize = 30;
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use image::Luma;
+	use rand::rngs::StdRng;
+	use rand::SeedableRng;
+
+	#[test]
+	fn test_min_spacing() {
+		let img = GrayImage::from_pixel(20, 20, Luma([0u8]));
+		let points = stipple(&img, 10, 50, 2f32, &mut StdRng::seed_from_u64(3));
+		assert_eq!(points, stipple(&img, 10, 50, 2f32, &mut StdRng::seed_from_u64(3)));
+		assert_eq!(points.len(), 50);
+		for (i, &p) in points.iter().enumerate() {
+			for &q in &points[i+1..] {
+				assert!((p - q).length() >= 2f32);
+			}
+		}
+		// Twenty by twenty doesn't have room for a thousand points five apart.
+		assert!(stipple(&img, 10, 1000, 5f32, &mut StdRng::seed_from_u64(3)).len() < 1000);
+	}
+}
@@ -0,0 +1,681 @@
+use crate::point::{DPoint, Point, Scalar};
+use crate::pointfile::{FromJson, Json};
+use crate::polyline::Polyline;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Something that replaces a straight segment with a more interesting stroke between the same two points.
+pub trait SegmentTessellator: Sync {
+	/// The points to draw in place of the segment from `line_start` to `line_end`, starting at `line_start`.
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point>;
+
+	/// `tessellate` in double precision, for paths far enough out that f32 drifts.  By default it's just `tessellate`
+	/// rounded to f32 and back; the built-in motifs and the tessellators that wrap others do better.
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		self.tessellate(line_start.cast(), line_end.cast()).into_iter().map(|p| p.cast()).collect()
+	}
+}
+
+/// The built-in motifs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+	Bolt,
+	Hex,
+	Square,
+	Tee,
+	W,
+	FakeHilbert,
+}
+
+impl Pattern {
+	pub fn by_name(name:&str) -> Option<Pattern> {
+		match name {
+			"bolt" => Some(Pattern::Bolt),
+			"hex" => Some(Pattern::Hex),
+			"square" => Some(Pattern::Square),
+			"tee" => Some(Pattern::Tee),
+			"w" => Some(Pattern::W),
+			"fake-hilbert" => Some(Pattern::FakeHilbert),
+			_ => None,
+		}
+	}
+
+	fn apply<T:Scalar>(self, line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+		match self {
+			Pattern::Bolt => tessellate_bolt(line_start, line_end),
+			Pattern::Hex => tessellate_hex(line_start, line_end),
+			Pattern::Square => tessellate_square(line_start, line_end),
+			Pattern::Tee => tessellate_tee(line_start, line_end),
+			Pattern::W => tessellate_w(line_start, line_end),
+			Pattern::FakeHilbert => tessellate_fake_hilbert(line_start, line_end),
+		}
+	}
+}
+
+/// Past this many units from the origin, the long `a + f + f + f` sums in the tessellations drift visibly in f32.
+const PRECISE_CANVAS:f32 = 10_000f32;
+
+impl SegmentTessellator for Pattern {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		if line_end == line_start {
+			vec![line_start]
+		} else if [line_start.x, line_start.y, line_end.x, line_end.y].iter().any(|v| v.abs() > PRECISE_CANVAS) {
+			self.apply(line_start.cast::<f64>(), line_end.cast()).into_iter().map(|p| p.cast()).collect()
+		} else {
+			self.apply(line_start, line_end)
+		}
+	}
+
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		if line_end == line_start {
+			vec![line_start]
+		} else {
+			self.apply(line_start, line_end)
+		}
+	}
+}
+
+/// A motif described by its corners, each given as (forward, lateral): how far along the segment it is, and how far
+/// off to the left, both as fractions of the segment's length.  Negative lateral is to the right.  The segment's own
+/// ends are always drawn, so only the corners in between need listing.  Read from JSON as `[[0.5, 0.25], ...]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomPattern {
+	pub corners: Vec<(f32, f32)>,
+}
+
+impl SegmentTessellator for CustomPattern {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		if line_start == line_end {
+			return vec![line_start];
+		}
+		let dpos = line_end - line_start;
+		let mut points = vec![line_start];
+		points.extend(self.corners.iter().map(|&(forward, lateral)| line_start + dpos * forward + dpos.perp() * lateral));
+		points.push(line_end);
+		points
+	}
+}
+
+impl FromJson for CustomPattern {
+	fn read_json(json:&mut Json) -> io::Result<Self> {
+		let corners = Vec::<Point>::read_json(json)?;
+		Ok(CustomPattern { corners: corners.into_iter().map(|p| p.into()).collect() })
+	}
+}
+
+/// A tessellator picked at random for each segment, with odds in proportion to the weights.
+pub struct Mixed {
+	choices: Vec<(Box<dyn SegmentTessellator>, f32)>,
+	rng: Mutex<StdRng>,
+}
+
+impl Mixed {
+	/// The same seed gives the same picks for the same paths.  Without one, every run is different.
+	pub fn new(choices:Vec<(Box<dyn SegmentTessellator>, f32)>, seed:Option<u64>) -> Self {
+		assert!(choices.iter().any(|&(_, weight)| weight > 0f32), "a pattern mix needs something with positive weight");
+		let rng = match seed {
+			Some(seed) => StdRng::seed_from_u64(seed),
+			None => StdRng::from_entropy(),
+		};
+		Mixed { choices, rng: Mutex::new(rng) }
+	}
+}
+
+impl Mixed {
+	fn pick(&self) -> &dyn SegmentTessellator {
+		let total:f32 = self.choices.iter().map(|&(_, weight)| weight.max(0f32)).sum();
+		let mut pick = self.rng.lock().unwrap().gen::<f32>() * total;
+		for (choice, weight) in &self.choices {
+			if *weight > 0f32 && pick < *weight {
+				return choice.as_ref();
+			}
+			pick -= weight.max(0f32);
+		}
+		// Rounding can leave a sliver past the last weight.
+		let (last, _) = self.choices.iter().rev().find(|&&(_, weight)| weight > 0f32).unwrap();
+		last.as_ref()
+	}
+}
+
+impl SegmentTessellator for Mixed {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		self.pick().tessellate(line_start, line_end)
+	}
+
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		self.pick().tessellate_precise(line_start, line_end)
+	}
+}
+
+/// Cross stitches about `size` across, laid along the segment.  Each is drawn as its two diagonals joined across the
+/// top, and the thread carries on from its far bottom corner into the next.
+pub struct CrossStitch {
+	pub size: f32,
+}
+
+impl SegmentTessellator for CrossStitch {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		if line_start == line_end {
+			return vec![line_start];
+		}
+		let dpos = line_end - line_start;
+		let count = ((dpos.length() / self.size).round() as usize).max(1);
+		let width = 1f32 / count as f32;
+		let half = width / 2f32;
+		let at = |forward:f32, lateral:f32| line_start + dpos * forward + dpos.perp() * lateral;
+		let mut points = vec![line_start, at(0f32, -half)];
+		for k in 0..count {
+			let (near, far) = (k as f32 * width, (k + 1) as f32 * width);
+			points.extend_from_slice(&[at(far, half), at(near, half), at(far, -half)]);
+		}
+		points.push(line_end);
+		points
+	}
+}
+
+/// A Greek key: the stroke spirals in `turns` corners deep, turns round, and winds back out alongside itself to
+/// carry on along the base line.  It's drawn on a square grid `turns + 1` steps along the segment.
+pub struct Meander {
+	pub turns: u32,
+}
+
+impl SegmentTessellator for Meander {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		if line_start == line_end {
+			return vec![line_start];
+		}
+		let turns = self.turns.max(1) as i32;
+		// Grid steps in order of turning left: up, left, down, right.
+		let steps = [(0, 1), (-1, 0), (0, -1), (1, 0)];
+		let mut corners = vec![(0i32, 0i32)];
+		let mut walk = |dir:usize, length:i32| {
+			let (x, y) = *corners.last().unwrap();
+			corners.push((x + steps[dir].0 * length, y + steps[dir].1 * length));
+		};
+		// In, turning right: up the full height, then each side one shorter than the last.
+		let mut dir = 0;
+		for length in std::iter::once(turns).chain((1..=turns).rev()).chain(std::iter::once(1)) {
+			walk(dir, length);
+			dir = (dir + 3) % 4;
+		}
+		// Out, turning left, each side one longer, which ends heading along the base line.
+		dir = (dir + 2) % 4;
+		for length in 1..=turns {
+			walk(dir, length);
+			dir = (dir + 1) % 4;
+		}
+		let dpos = line_end - line_start;
+		let grid = 1f32 / (turns + 1) as f32;
+		let mut points:Vec<Point> = corners.into_iter().map(|(x, y)| line_start + dpos * (x as f32 * grid) + dpos.perp() * (y as f32 * grid)).collect();
+		// The walk ends on the far end already; this just keeps rounding from moving it.
+		*points.last_mut().unwrap() = line_end;
+		points
+	}
+}
+
+/// A value looked up at a point on the canvas, such as the image's darkness there.
+pub type Shade = Arc<dyn Fn(Point) -> f32 + Send + Sync>;
+
+/// A scribbled coil: `loops` overlapping circles rolled along the segment.  Big enough circles loop back on
+/// themselves; small ones only make waves.  With `shade`, each segment's circles are `radius` times the shade at its
+/// middle, so dark places get big loops and white ones flatten out.
+pub struct Coil {
+	pub loops: u32,
+	pub radius: f32,
+	pub shade: Option<Shade>,
+}
+
+/// Points drawn around each loop of a coil.
+const COIL_SAMPLES:u32 = 12;
+
+impl SegmentTessellator for Coil {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		if line_start == line_end {
+			return vec![line_start];
+		}
+		let dpos = line_end - line_start;
+		let radius = match &self.shade {
+			Some(shade) => self.radius * shade(line_start.lerp(line_end, 0.5f32)),
+			None => self.radius,
+		} / dpos.length();
+		let loops = self.loops.max(1);
+		let samples = loops * COIL_SAMPLES;
+		let mut points:Vec<Point> = (0..=samples).map(|i| {
+			let t = i as f32 / samples as f32;
+			let (sin, cos) = (std::f32::consts::TAU * loops as f32 * t).sin_cos();
+			// Start on the leading edge of the first circle and finish on the leading edge of the last.
+			line_start + dpos * (t + radius * (cos - 1f32)) + dpos.perp() * (radius * sin)
+		}).collect();
+		*points.last_mut().unwrap() = line_end;
+		points
+	}
+}
+
+/// The settings for the patterns that take more than a name.
+#[derive(Clone)]
+pub struct SpecOptions {
+	/// For picking patterns in a mix.
+	pub seed: Option<u64>,
+	pub stitch_size: f32,
+	pub meander_turns: u32,
+	pub coil_loops: u32,
+	pub coil_radius: f32,
+	/// Scales the size of the patterns that follow the image.
+	pub shade: Option<Shade>,
+}
+
+impl Default for SpecOptions {
+	fn default() -> Self {
+		SpecOptions { seed: None, stitch_size: 4f32, meander_turns: 4, coil_loops: 3, coil_radius: 2f32, shade: None }
+	}
+}
+
+fn named(name:&str, options:&SpecOptions) -> Option<Box<dyn SegmentTessellator>> {
+	match name {
+		"cross-stitch" => Some(Box::new(CrossStitch { size: options.stitch_size })),
+		"meander" => Some(Box::new(Meander { turns: options.meander_turns })),
+		"coil" => Some(Box::new(Coil { loops: options.coil_loops, radius: options.coil_radius, shade: options.shade.clone() })),
+		_ => Pattern::by_name(name).map(|pattern| Box::new(pattern) as Box<dyn SegmentTessellator>),
+	}
+}
+
+/// A tessellator from its command line description: one pattern name, or a weighted mix like `bolt:3,w:1,hex` where
+/// a missing weight counts as 1.
+pub fn from_spec(spec:&str, options:&SpecOptions) -> Option<Box<dyn SegmentTessellator>> {
+	if !spec.contains(',') && !spec.contains(':') {
+		return named(spec, options);
+	}
+	let mut choices:Vec<(Box<dyn SegmentTessellator>, f32)> = vec![];
+	for entry in spec.split(',') {
+		let mut parts = entry.splitn(2, ':');
+		let pattern = named(parts.next()?.trim(), options)?;
+		let weight = match parts.next() {
+			Some(weight) => weight.trim().parse::<f32>().ok()?,
+			None => 1f32,
+		};
+		choices.push((pattern, weight));
+	}
+	if !choices.iter().any(|&(_, weight)| weight > 0f32) {
+		return None;
+	}
+	Some(Box::new(Mixed::new(choices, options.seed)))
+}
+
+/// How deep `Recursive` goes.  Every level multiplies the points by the pieces in the motif, so past this even a short
+/// path runs to millions of them.
+pub const MAX_DEPTH:u32 = 8;
+
+/// Another tessellator applied again to each piece it makes, `depth` times over, up to `MAX_DEPTH`.  Pieces shorter
+/// than `min_length` are left straight, which keeps the deep levels from multiplying into points nobody can see.
+pub struct Recursive {
+	pub pattern: Box<dyn SegmentTessellator>,
+	pub depth: u32,
+	pub min_length: f32,
+}
+
+impl Recursive {
+	fn replace<T:Scalar>(&self, line_start:Point<T>, line_end:Point<T>, depth:u32, pattern:&dyn Fn(Point<T>, Point<T>) -> Vec<Point<T>>) -> Vec<Point<T>> {
+		if line_start == line_end {
+			return vec![line_start];
+		}
+		if depth == 0 || (line_end - line_start).length() < T::of(self.min_length as f64) {
+			return vec![line_start, line_end];
+		}
+		join(&pattern(line_start, line_end), |a, b| self.replace(a, b, depth - 1, pattern))
+	}
+}
+
+impl SegmentTessellator for Recursive {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		self.replace(line_start, line_end, self.depth.min(MAX_DEPTH), &|a, b| self.pattern.tessellate(a, b))
+	}
+
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		self.replace(line_start, line_end, self.depth.min(MAX_DEPTH), &|a, b| self.pattern.tessellate_precise(a, b))
+	}
+}
+
+/// Another tessellator with its sideways swing scaled by `amplitude` at the middle of each segment.  Given a darkness
+/// lookup, the motifs stand out where the image is dark and flatten to a straight line where it's white.
+pub struct Modulated {
+	pub pattern: Box<dyn SegmentTessellator>,
+	pub amplitude: Box<dyn Fn(Point) -> f32 + Sync>,
+}
+
+impl Modulated {
+	fn scale<T:Scalar>(&self, points:Vec<Point<T>>, line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+		let normal = (line_end - line_start).perp();
+		let length_squared = normal.dot(normal);
+		if length_squared <= T::default() {
+			return points;
+		}
+		let scale = T::of((self.amplitude)(line_start.lerp(line_end, T::of(0.5)).cast()) as f64);
+		points.into_iter().map(|p| {
+			let lateral = normal * (normal.dot(p - line_start) / length_squared);
+			p + lateral * (scale - T::of(1.0))
+		}).collect()
+	}
+}
+
+impl SegmentTessellator for Modulated {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		self.scale(self.pattern.tessellate(line_start, line_end), line_start, line_end)
+	}
+
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		self.scale(self.pattern.tessellate_precise(line_start, line_end), line_start, line_end)
+	}
+}
+
+/// Another tessellator mirrored across the segment on every other call, so a lopsided motif like the bolt doesn't
+/// lean the whole drawing to one side.
+pub struct Alternating {
+	pattern: Box<dyn SegmentTessellator>,
+	mirror_next: AtomicBool,
+}
+
+impl Alternating {
+	pub fn new(pattern:Box<dyn SegmentTessellator>) -> Self {
+		Alternating { pattern, mirror_next: AtomicBool::new(false) }
+	}
+}
+
+impl Alternating {
+	fn mirror<T:Scalar>(&self, points:Vec<Point<T>>, line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+		let normal = (line_end - line_start).perp();
+		let length_squared = normal.dot(normal);
+		if !self.mirror_next.fetch_xor(true, Ordering::Relaxed) || length_squared <= T::default() {
+			return points;
+		}
+		points.into_iter().map(|p| p - normal * (T::of(2.0) * normal.dot(p - line_start) / length_squared)).collect()
+	}
+}
+
+impl SegmentTessellator for Alternating {
+	fn tessellate(&self, line_start:Point, line_end:Point) -> Vec<Point> {
+		self.mirror(self.pattern.tessellate(line_start, line_end), line_start, line_end)
+	}
+
+	fn tessellate_precise(&self, line_start:DPoint, line_end:DPoint) -> Vec<DPoint> {
+		self.mirror(self.pattern.tessellate_precise(line_start, line_end), line_start, line_end)
+	}
+}
+
+/// Swap every segment of `path` for `pattern`.  Where a motif stops short of its segment's end, the stroke carries
+/// straight on to the start of the next one.
+/// Far out on the canvas the whole path is built in f64 and only rounded to f32 once it's done, so nesting and
+/// mirroring don't pile rounding on rounding.
+pub fn tessellate_path(path:&Polyline, pattern:&dyn SegmentTessellator) -> Polyline {
+	if path.points.iter().all(|p| p.x.abs() <= PRECISE_CANVAS && p.y.abs() <= PRECISE_CANVAS) {
+		return Polyline::new(join(&path.points, |a, b| pattern.tessellate(a, b)));
+	}
+	let points:Vec<DPoint> = path.points.iter().map(|p| p.cast()).collect();
+	Polyline::new(join(&points, |a, b| pattern.tessellate_precise(a, b)).into_iter().map(|p| p.cast()).collect())
+}
+
+/// The pieces `piece` makes of each pair of neighbours in `points`, run together where one ends on the next's start.
+/// The first and last of `points` are kept even if the pieces don't reach them.
+fn join<T:Scalar>(points:&[Point<T>], piece:impl Fn(Point<T>, Point<T>) -> Vec<Point<T>>) -> Vec<Point<T>> {
+	let mut result:Vec<Point<T>> = points.iter().take(1).copied().collect();
+	let pieces = points.windows(2).map(|pair| piece(pair[0], pair[1])).chain(points.last().map(|&end| vec![end]));
+	for piece in pieces {
+		let skip = match (result.last(), piece.first()) {
+			(Some(end), Some(start)) if end == start => 1,
+			_ => 0,
+		};
+		result.extend_from_slice(&piece[skip..]);
+	}
+	result
+}
+
+fn tessellate_bolt<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	// Replace ----
+	// With/\
+	//    /  \
+	//        \/
+	// One segment becomes four of 1/4th size.  We could also do three with different sizes.
+	let dpos = line_end - line_start;
+	let left = dpos.perp() * T::of(0.75); // Lob-sided Left-hand normal.
+	let right = -dpos.perp() * T::of(0.25);
+	let fwd = dpos * T::of(0.5);
+	vec![
+		line_start,
+		line_start + fwd + left,
+		line_start + fwd,
+		line_start + fwd + right,
+		line_end
+	]
+}
+
+fn tessellate_hex<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	//  e f
+	// a d g
+	//  b c
+	let dpos = line_end - line_start;
+	let left_normal = dpos.perp() * T::of(0.3);
+	let forward = dpos * T::of(0.3);
+	let right_normal = -dpos.perp() * T::of(0.3);
+	vec![
+		line_start,
+		line_start + forward + right_normal,
+		line_start + forward + right_normal + forward,
+		line_start + forward + forward,
+		line_start + forward + left_normal,
+		line_start + forward + left_normal + forward,
+		line_end
+	]
+}
+
+fn tessellate_square<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	// Replace
+	// ----
+	// With
+	// bc
+	// adg
+	//  ef
+	let dpos = line_end - line_start;
+	let left_normal = dpos.perp() * T::of(0.5);
+	let forward = dpos * T::of(0.5);
+	let right_normal = -dpos.perp() * T::of(0.5);
+	vec![
+		line_start,
+		line_start + left_normal,
+		line_start + left_normal + forward,
+		line_start + forward,
+		line_start + right_normal + forward,
+		line_start + right_normal + forward + forward,
+		line_end
+	]
+}
+
+fn tessellate_tee<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	// g     h
+	// f e j i
+	// a x x m
+	//   b l
+	let dpos = line_end - line_start;
+	let a = line_start;
+	let l = dpos.perp() * T::of(0.25);
+	let f = dpos * T::of(0.25);
+	let r = -dpos.perp() * T::of(0.25);
+	vec![
+		a,
+		a + f + r,
+		//a + f,
+		a + f + l,
+		a + l,
+		a + l + l,
+		a + l + l + f + f + f,
+		a + l + f + f + f,
+		a + l + f + f,
+		//a + f + f,
+		a + r + f + f,
+		a + f + f + f
+	]
+}
+
+fn tessellate_w<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	//   c
+	// a   e
+	//  b d
+	let dpos = line_end - line_start;
+	let a = line_start;
+	let l = dpos.perp() * T::of(0.4);
+	let f = dpos * T::of(0.2);
+	let r = -dpos.perp() * T::of(0.2);
+	vec![
+		a,
+		a + f + r,
+		a + f + f + l,
+		a + f + f + f + r,
+		line_end
+	]
+}
+
+fn tessellate_fake_hilbert<T:Scalar>(line_start:Point<T>, line_end:Point<T>) -> Vec<Point<T>> {
+	// e fi j
+	// dcghlk
+	// ab  mn
+	let dpos = line_end - line_start;
+	let a = line_start;
+	let l = dpos.perp() * T::of(0.25);
+	let f = dpos * T::of(0.15);
+	vec![
+		a,
+		a + f,
+		a + f + l,
+		a + l,
+		a + l + l,
+		a + l + l + f + f,
+		a + l + f + f,
+		a + l + f + f + f,
+		a + l + l + f + f + f,
+		a + l + l + f + f + f + f + f,
+		a + l + f + f + f + f + f,
+		a + l + f + f + f + f,
+		a + f + f + f + f,
+		line_end
+	]
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_tessellate_path() {
+		let path = Polyline::from(vec![(0f32, 0f32), (4f32, 0f32), (4f32, 4f32)]);
+		let bolted = tessellate_path(&path, &Pattern::Bolt);
+		// Each segment's four pieces, sharing the corner between them.
+		assert_eq!(bolted.len(), 9);
+		assert_eq!(bolted.points[1], Point::new(2f32, 3f32));
+		assert_eq!((bolted.points[4], bolted.points[8]), (path.points[1], path.points[2]));
+		// The tee stops short, so the path still has to reach its end.
+		assert_eq!(tessellate_path(&path, &Pattern::Tee).points.last(), Some(&Point::new(4f32, 4f32)));
+		assert_eq!(Pattern::by_name("fake-hilbert"), Some(Pattern::FakeHilbert));
+	}
+
+	#[test]
+	fn test_recursion_depth_and_cutoff() {
+		let (start, end) = (Point::new(0f32, 0f32), Point::new(100f32, 0f32));
+		let deep = |depth, min_length| Recursive { pattern: Box::new(Pattern::W), depth, min_length }.tessellate(start, end);
+		assert_eq!(deep(1, 0f32), Pattern::W.tessellate(start, end));
+		// Four pieces, each split in four again.
+		assert_eq!(deep(2, 0f32).len(), 17);
+		// The W's first piece is only 28 long, so a cutoff of 40 leaves it straight and splits the other three.
+		assert_eq!(deep(2, 40f32).len(), 14);
+		assert_eq!(deep(3, 1000f32), vec![start, end]);
+		// Deeper than the limit is the limit: 4^8 pieces.
+		assert_eq!(deep(MAX_DEPTH + 4, 0f32).len(), 65_537);
+	}
+
+	#[test]
+	fn test_far_paths_stay_in_double_precision() {
+		// A nested W a few units long, 50k units out, where f32 only resolves to about 0.004.
+		let path = Polyline::from(vec![(50_000f32, 50_000f32), (50_006f32, 50_002f32)]);
+		let nested = || Recursive { pattern: Box::new(Alternating::new(Box::new(Pattern::W))), depth: 3, min_length: 0f32 };
+		let exact = nested().tessellate_precise(path.points[0].cast(), path.points[1].cast());
+		let error = |points:&[Point]| points.iter().zip(&exact).map(|(p, q)| (p.cast::<f64>() - *q).length()).fold(0f64, f64::max);
+		let single = nested().tessellate(path.points[0], path.points[1]);
+		let double = tessellate_path(&path, &nested());
+		assert_eq!((single.len(), double.len()), (exact.len(), exact.len()));
+		// Rounded once, every point is within half a step of f32's grid either way, which it isn't when each level
+		// builds on the last one's rounding.
+		let half_step = 0.5f64 * 2f64.sqrt() * (50_000f32.next_up() - 50_000f32) as f64;
+		assert!(error(&double.points) <= half_step);
+		assert!(error(&single) > half_step);
+	}
+
+	#[test]
+	fn test_mixed_is_seeded_and_weighted() {
+		let path = Polyline::from((0..50).map(|i| (i as f32, 0f32)).collect::<Vec<(f32, f32)>>());
+		let seeded = SpecOptions { seed: Some(7), ..SpecOptions::default() };
+		let first = tessellate_path(&path, from_spec("bolt:2,w,hex:0", &seeded).unwrap().as_ref());
+		let again = tessellate_path(&path, from_spec("bolt:2,w,hex:0", &seeded).unwrap().as_ref());
+		assert_eq!(first, again);
+		// Bolts and Ws both add three points per segment, and a hex with no weight (six) never turns up.
+		assert_eq!(first.len(), 49 * 4 + 1);
+		assert!(from_spec("bolt:x", &seeded).is_none() && from_spec("hex:0", &seeded).is_none());
+	}
+
+	#[test]
+	fn test_meander_never_crosses_itself() {
+		let key = Meander { turns: 4 }.tessellate(Point::new(0f32, 0f32), Point::new(5f32, 0f32));
+		let expected:Vec<Point> = vec![(0f32, 0f32), (0f32, 4f32), (4f32, 4f32), (4f32, 1f32), (2f32, 1f32), (2f32, 2f32), (3f32, 2f32), (3f32, 3f32), (1f32, 3f32), (1f32, 0f32), (5f32, 0f32)]
+			.into_iter().map(Point::from).collect();
+		assert_eq!(key, expected);
+		for turns in 1..8 {
+			let key = Polyline::new(Meander { turns }.tessellate(Point::new(0f32, 0f32), Point::new(10f32, 3f32)));
+			assert!(crate::intersect::self_intersections(&key).is_empty(), "{} turns", turns);
+		}
+	}
+
+	#[test]
+	fn test_coil_loops_back() {
+		let (start, end) = (Point::new(0f32, 0f32), Point::new(30f32, 0f32));
+		let coil = Coil { loops: 3, radius: 3f32, shade: None }.tessellate(start, end);
+		assert_eq!((coil[0], coil[coil.len()-1]), (start, end));
+		assert!(coil.iter().all(|p| p.y.abs() <= 3f32 + 1e-4f32));
+		// Each circle is wider than the step between them, so the stroke runs backwards on every loop.
+		assert!(coil.windows(2).any(|pair| pair[1].x < pair[0].x));
+		let flat = Coil { loops: 3, radius: 3f32, shade: Some(Arc::new(|_| 0f32)) }.tessellate(start, end);
+		assert!(flat.windows(2).all(|pair| pair[1].x >= pair[0].x && pair[1].y == 0f32));
+	}
+
+	#[test]
+	fn test_cross_stitch() {
+		let crosses = CrossStitch { size: 4f32 }.tessellate(Point::new(0f32, 0f32), Point::new(8f32, 0f32));
+		let expected:Vec<Point> = vec![(0f32, 0f32), (0f32, -2f32), (4f32, 2f32), (0f32, 2f32), (4f32, -2f32), (8f32, 2f32), (4f32, 2f32), (8f32, -2f32), (8f32, 0f32)]
+			.into_iter().map(Point::from).collect();
+		assert_eq!(crosses, expected);
+	}
+
+	#[test]
+	fn test_custom_pattern_matches_builtin() {
+		// The W, written out by hand.
+		let custom:CustomPattern = crate::pointfile::from_json("[[0.2, -0.2], [0.4, 0.4], [0.6, -0.2]]").unwrap();
+		let (start, end) = (Point::new(3f32, 1f32), Point::new(-2f32, 6f32));
+		assert_eq!(custom.tessellate(start, end), Pattern::W.tessellate(start, end));
+		assert!(crate::pointfile::from_json::<CustomPattern>("[[0.2]]").is_err());
+	}
+
+	#[test]
+	fn test_alternating_mirrors() {
+		let path = Polyline::from(vec![(0f32, 0f32), (4f32, 0f32), (8f32, 0f32)]);
+		let bolted = tessellate_path(&path, &Alternating::new(Box::new(Pattern::Bolt)));
+		// The first bolt peaks to the left, the second to the right.
+		assert_eq!((bolted.points[1], bolted.points[5]), (Point::new(2f32, 3f32), Point::new(6f32, -3f32)));
+	}
+
+	#[test]
+	fn test_modulated_amplitude() {
+		let (start, end) = (Point::new(0f32, 0f32), Point::new(4f32, 0f32));
+		let halved = Modulated { pattern: Box::new(Pattern::Bolt), amplitude: Box::new(|_| 0.5f32) }.tessellate(start, end);
+		assert_eq!(halved[1], Point::new(2f32, 1.5f32));
+		let flat = Modulated { pattern: Box::new(Pattern::Square), amplitude: Box::new(|_| 0f32) }.tessellate(start, end);
+		assert!(flat.iter().all(|p| p.y.abs() < 1e-6f32));
+	}
+}
@@ -0,0 +1,96 @@
+use crate::point::Point;
+
+/// An affine map of the plane: x' = a*x + b*y + c, y' = d*x + e*y + f.
+/// Build one up from the constructors with `then`, which applies transforms in the order they're chained.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform2D {
+	a: f32,
+	b: f32,
+	c: f32,
+	d: f32,
+	e: f32,
+	f: f32,
+}
+
+impl Default for Transform2D {
+	fn default() -> Self {
+		Transform2D::identity()
+	}
+}
+
+impl Transform2D {
+	pub fn identity() -> Self {
+		Transform2D { a: 1f32, b: 0f32, c: 0f32, d: 0f32, e: 1f32, f: 0f32 }
+	}
+
+	pub fn translate(dx:f32, dy:f32) -> Self {
+		Transform2D { c: dx, f: dy, ..Transform2D::identity() }
+	}
+
+	pub fn scale(sx:f32, sy:f32) -> Self {
+		Transform2D { a: sx, e: sy, ..Transform2D::identity() }
+	}
+
+	/// Counterclockwise by `radians` about the origin.  With y pointing down, as on the canvas, that looks clockwise.
+	pub fn rotate(radians:f32) -> Self {
+		let (sin, cos) = radians.sin_cos();
+		Transform2D { a: cos, b: -sin, c: 0f32, d: sin, e: cos, f: 0f32 }
+	}
+
+	/// Mirror left to right, across the y axis.
+	pub fn flip_x() -> Self {
+		Transform2D::scale(-1f32, 1f32)
+	}
+
+	/// Mirror top to bottom, across the x axis.
+	pub fn flip_y() -> Self {
+		Transform2D::scale(1f32, -1f32)
+	}
+
+	/// `self` followed by `next`.
+	pub fn then(self, next:Transform2D) -> Self {
+		Transform2D {
+			a: next.a*self.a + next.b*self.d,
+			b: next.a*self.b + next.b*self.e,
+			c: next.a*self.c + next.b*self.f + next.c,
+			d: next.d*self.a + next.e*self.d,
+			e: next.d*self.b + next.e*self.e,
+			f: next.d*self.c + next.e*self.f + next.f,
+		}
+	}
+
+	/// `transform` done about `center` instead of the origin.
+	pub fn about(transform:Transform2D, center:Point) -> Self {
+		Transform2D::translate(-center.x, -center.y).then(transform).then(Transform2D::translate(center.x, center.y))
+	}
+
+	pub fn apply(&self, p:Point) -> Point {
+		Point::new(self.a*p.x + self.b*p.y + self.c, self.d*p.x + self.e*p.y + self.f)
+	}
+
+	pub fn is_identity(&self) -> bool {
+		*self == Transform2D::identity()
+	}
+
+	/// The same map as an SVG `transform` attribute's value.
+	pub fn svg_matrix(&self) -> String {
+		format!("matrix({} {} {} {} {} {})", self.a, self.d, self.b, self.e, self.c, self.f)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_chain_order() {
+		let p = Point::new(1f32, 0f32);
+		// Scaling then moving isn't the same as moving then scaling.
+		assert_eq!(Transform2D::scale(2f32, 2f32).then(Transform2D::translate(1f32, 0f32)).apply(p), Point::new(3f32, 0f32));
+		assert_eq!(Transform2D::translate(1f32, 0f32).then(Transform2D::scale(2f32, 2f32)).apply(p), Point::new(4f32, 0f32));
+		let quarter = Transform2D::rotate(std::f32::consts::FRAC_PI_2).apply(p);
+		assert!((quarter - Point::new(0f32, 1f32)).length() < 1e-6f32);
+		let mirrored = Transform2D::about(Transform2D::flip_x(), Point::new(5f32, 5f32));
+		assert_eq!(mirrored.apply(Point::new(1f32, 2f32)), Point::new(9f32, 2f32));
+	}
+}
@@ -0,0 +1,161 @@
+use crate::kdtree::KdTree;
+use crate::point::Point;
+use crate::polyline::Polyline;
+
+/// How far the pen goes up between the end of each path and the start of the next.
+pub fn pen_up_travel(paths:&[Polyline]) -> f32 {
+	let ends:Vec<(Point, Point)> = paths.iter().filter(|path| !path.is_empty()).map(|path| (path.points[0], *path.points.last().unwrap())).collect();
+	ends.windows(2).map(|pair| (pair[1].0 - pair[0].1).length()).sum()
+}
+
+/// Put the paths in an order, each drawn whichever way round, that keeps the pen-up travel between them short.
+/// Starting at the origin, the pen goes to the nearest end of any path it hasn't drawn yet, draws that path from there,
+/// and repeats.  With `two_opt`, runs of paths in that order are then flipped end to end while doing so shortens the
+/// trip, which undoes most of the long jumps back the greedy order leaves at the end.  Paths that come in an order
+/// already better than the greedy one, like the dashes along a single curve, keep it.  Empty paths are dropped.
+pub fn order_paths(paths:Vec<Polyline>, two_opt:bool) -> Vec<Polyline> {
+	let given:Vec<Polyline> = paths.into_iter().filter(|path| !path.is_empty()).collect();
+	let mut ordered = greedy(given.clone());
+	if pen_up_travel(&given) < pen_up_travel(&ordered) {
+		ordered = given;
+	}
+	if two_opt {
+		improve(&mut ordered);
+	}
+	ordered
+}
+
+fn greedy(paths:Vec<Polyline>) -> Vec<Polyline> {
+	let mut paths:Vec<Option<Polyline>> = paths.into_iter().map(Some).collect();
+	// Endpoint 2i is where path i starts and 2i+1 where it ends.
+	let ends:Vec<Point> = paths.iter().flatten().flat_map(|path| vec![path.points[0], *path.points.last().unwrap()]).collect();
+	let mut tree = KdTree::new(&ends);
+	let mut ordered = Vec::with_capacity(paths.len());
+	let mut pen = Point::new(0f32, 0f32);
+	while let Some((idx, _)) = tree.nearest(pen) {
+		tree.remove(idx & !1);
+		tree.remove(idx | 1);
+		let mut path = paths[idx / 2].take().unwrap();
+		if idx % 2 == 1 {
+			path.reverse();
+		}
+		pen = *path.points.last().unwrap();
+		ordered.push(path);
+	}
+	ordered
+}
+
+/// Join up paths whose ends come within `tolerance` of each other, turning them round where needed, so the pen can
+/// carry on instead of lifting.  Each path gets grown from both ends, nearest neighbour first, until nothing is close
+/// enough; the pen draws straight across whatever gap is left.  Empty paths are dropped.
+pub fn merge_paths(paths:Vec<Polyline>, tolerance:f32) -> Vec<Polyline> {
+	let mut paths:Vec<Option<Polyline>> = paths.into_iter().filter(|path| !path.is_empty()).map(Some).collect();
+	let ends:Vec<Point> = paths.iter().flatten().flat_map(|path| vec![path.points[0], *path.points.last().unwrap()]).collect();
+	let mut tree = KdTree::new(&ends);
+	let mut merged = vec![];
+	for i in 0..paths.len() {
+		let mut chain = match paths[i].take() {
+			Some(path) => path,
+			None => continue,
+		};
+		tree.remove(2 * i);
+		tree.remove(2 * i + 1);
+		for _ in 0..2 {
+			while let Some((idx, _)) = tree.nearest(*chain.points.last().unwrap()).filter(|&(_, d2)| d2 <= tolerance * tolerance) {
+				tree.remove(idx & !1);
+				tree.remove(idx | 1);
+				let mut next = paths[idx / 2].take().unwrap();
+				if idx % 2 == 1 {
+					next.reverse();
+				}
+				chain.concat(&next);
+			}
+			// Turned round to grow from the other end, and then back the way it was.
+			chain.reverse();
+		}
+		merged.push(chain);
+	}
+	merged
+}
+
+/// Finish the drawing back where it started by running the last path on to the first point of the first one, so the
+/// pen or beam goes round a loop.  Returns whether there was anything to close.
+pub fn close_loop(paths:&mut [Polyline]) -> bool {
+	let start = match paths.iter().find(|path| !path.is_empty()) {
+		Some(path) => path.points[0],
+		None => return false,
+	};
+	let last = paths.iter_mut().rev().find(|path| !path.is_empty()).unwrap();
+	if *last.points.last().unwrap() != start {
+		last.points.push(start);
+	}
+	true
+}
+
+/// Flip runs of paths over, order and direction both, for as long as one of them shortens the pen-up travel.
+fn improve(paths:&mut [Polyline]) {
+	const MAX_PASSES:usize = 20;
+	let start = |path:&Polyline| path.points[0];
+	let end = |path:&Polyline| *path.points.last().unwrap();
+	for _ in 0..MAX_PASSES {
+		let mut improved = false;
+		for i in 0..paths.len() {
+			// Flipping paths i..=j swaps the jumps into i and out of j for ones into j's end and out of i's start.
+			let before = if i == 0 { Point::new(0f32, 0f32) } else { end(&paths[i - 1]) };
+			for j in i..paths.len() {
+				let after = paths.get(j + 1).map(start);
+				let old = (start(&paths[i]) - before).length() + after.map_or(0f32, |a| (a - end(&paths[j])).length());
+				let new = (end(&paths[j]) - before).length() + after.map_or(0f32, |a| (a - start(&paths[i])).length());
+				if new < old - 1e-4f32 {
+					paths[i..=j].reverse();
+					paths[i..=j].iter_mut().for_each(Polyline::reverse);
+					improved = true;
+				}
+			}
+		}
+		if !improved {
+			break;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_order_paths() {
+		let segment = |x0:f32, x1:f32| Polyline::new(vec![Point::new(x0, 0f32), Point::new(x1, 0f32)]);
+		// Shuffled and pointing every which way, these should end up laid end to end from the origin.
+		let paths = vec![segment(5f32, 3f32), segment(1f32, 2f32), Polyline::new(vec![]), segment(3f32, 2f32), segment(0f32, 1f32)];
+		assert_eq!(pen_up_travel(&paths), 5f32);
+		for two_opt in [false, true] {
+			let ordered = order_paths(paths.clone(), two_opt);
+			assert_eq!(ordered.len(), 4);
+			assert_eq!(pen_up_travel(&ordered), 0f32);
+		}
+		// Drawing the last two backwards and the other way round saves a jump back.
+		let mut paths = vec![segment(0f32, 1f32), segment(4f32, 3f32), segment(3f32, 2f32)];
+		improve(&mut paths);
+		assert_eq!(pen_up_travel(&paths), 1f32);
+		assert_eq!(paths[1].points, vec![Point::new(2f32, 0f32), Point::new(3f32, 0f32)]);
+	}
+
+	#[test]
+	fn test_merge_paths() {
+		let segment = |x0:f32, x1:f32| Polyline::new(vec![Point::new(x0, 0f32), Point::new(x1, 0f32)]);
+		let paths = vec![segment(2f32, 3f32), segment(10f32, 11f32), segment(1.9f32, 1f32), segment(3f32, 4f32), segment(0f32, 1f32)];
+		let merged = merge_paths(paths, 0.2f32);
+		assert_eq!(merged.len(), 2);
+		let xs:Vec<f32> = merged[0].points.iter().map(|p| p.x).collect();
+		assert_eq!(xs, vec![0f32, 1f32, 1.9f32, 2f32, 3f32, 4f32]);
+		assert_eq!(merged[1], segment(10f32, 11f32));
+		let mut paths = vec![segment(0f32, 1f32), Polyline::new(vec![]), segment(2f32, 3f32)];
+		assert!(close_loop(&mut paths));
+		assert_eq!(paths[2].points.last(), Some(&Point::new(0f32, 0f32)));
+		// Already back at the start, so nothing more is added.
+		assert!(close_loop(&mut paths));
+		assert_eq!(paths[2].len(), 3);
+		assert!(!close_loop(&mut [Polyline::new(vec![])]));
+	}
+}
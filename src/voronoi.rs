@@ -0,0 +1,78 @@
+use crate::hilbert::index_to_xy;
+use crate::kdtree::KdTree;
+use crate::point::Point;
+use image::GrayImage;
+
+/// Label every pixel with the index of its nearest site, in row-major order.
+pub fn label_pixels(width:u32, height:u32, sites:&[Point]) -> Vec<usize> {
+	let tree = KdTree::new(sites);
+	let mut labels = Vec::with_capacity((width * height) as usize);
+	for y in 0..height {
+		for x in 0..width {
+			let (site, _) = tree.nearest(Point::new(x as f32 + 0.5f32, y as f32 + 0.5f32)).unwrap();
+			labels.push(site);
+		}
+	}
+	labels
+}
+
+/// Split the image into the Voronoi cells of `sites` and fill each cell with a Hilbert curve of its own.
+/// The curve order for a cell is picked from the cell's mean darkness, so darker cells get denser curves,
+/// but a cell never gets a curve finer than one pixel.  Curves are clipped to their cell, so one cell can
+/// produce several strokes.
+/// `max_level` is the value of a pure white pixel, i.e. the number of gray levels after `adjust_levels`.
+pub fn fill_cells(img:&GrayImage, max_level:u8, sites:&[Point]) -> Vec<Vec<Point>> {
+	let mut paths = vec![];
+	if sites.is_empty() {
+		return paths;
+	}
+	let width = img.width();
+	let labels = label_pixels(width, img.height(), sites);
+
+	// Bounding box and total darkness for each cell.
+	let mut bounds = vec![(u32::MAX, u32::MAX, 0u32, 0u32); sites.len()];
+	let mut darkness = vec![0f32; sites.len()];
+	let mut area = vec![0u32; sites.len()];
+	for (x, y, px) in img.enumerate_pixels() {
+		let cell = labels[(y * width + x) as usize];
+		let b = &mut bounds[cell];
+		*b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y));
+		darkness[cell] += max_level.saturating_sub(px[0]) as f32 / max_level.max(1) as f32;
+		area[cell] += 1;
+	}
+
+	for cell in 0..sites.len() {
+		if area[cell] == 0 {
+			continue;
+		}
+		let (left, top, right, bottom) = bounds[cell];
+		let cell_width = (right - left + 1) as f32;
+		let cell_height = (bottom - top + 1) as f32;
+		let max_depth = (cell_width.max(cell_height).log2().floor() as u32).max(1);
+		let depth = ((darkness[cell] / area[cell] as f32) * max_depth as f32).round() as u32;
+		if depth == 0 {
+			continue;
+		}
+
+		let n = 1u32 << depth;
+		let mut current = vec![];
+		for d in 0..(n as u64 * n as u64) {
+			let (cx, cy) = index_to_xy(n, d);
+			let p = Point::new(
+				left as f32 + (cx as f32 + 0.5f32) * cell_width / n as f32,
+				top as f32 + (cy as f32 + 0.5f32) * cell_height / n as f32,
+			);
+			if labels[(p.y as u32 * width + p.x as u32) as usize] == cell {
+				current.push(p);
+			} else if !current.is_empty() {
+				paths.push(std::mem::take(&mut current));
+			}
+		}
+		if !current.is_empty() {
+			paths.push(current);
+		}
+	}
+
+	paths.retain(|p| p.len() > 1);
+	paths
+}